@@ -0,0 +1,80 @@
+//! Frame time of the chat pane on a large synthetic conversation.
+//!
+//! Run with `cargo bench --bench render`. It times drawing the chat the way every frame does,
+//! both with the rendered lines cached and with them wrapped from scratch, and while a response
+//! streams in token by token.
+use gptrs::chat::History;
+use gptrs::config::{Prompt, Role};
+use gptrs::widgets::chat::ChatView;
+use ratatui::prelude::*;
+use ratatui::widgets::Widget;
+use std::hint::black_box;
+use std::time::{Duration, Instant};
+
+const MESSAGES: usize = 500;
+const FRAMES: u32 = 200;
+const WIDTH: u16 = 120;
+const HEIGHT: u16 = 40;
+
+/// A conversation of `MESSAGES` messages of a few paragraphs each.
+fn conversation() -> History {
+    let paragraph = "The borrow checker rejects this because the reference outlives the value \
+        it points to. Move the value into the struct or clone it before the loop, and the \
+        lifetimes line up again. ";
+    let mut history = History::default();
+    for i in 0..MESSAGES {
+        let role = if i % 2 == 0 {
+            Role::User
+        } else {
+            Role::Assistant
+        };
+        let content = format!("{}\n\n{}", paragraph.repeat(3), paragraph.repeat(2));
+        history.push(Prompt { role, content });
+    }
+    history.text_width = WIDTH - 2;
+    history
+}
+
+/// Draw the chat into a buffer like `ui::render` does and return how long it took.
+fn frame(history: &mut History, buffer: &mut Buffer) -> Duration {
+    let start = Instant::now();
+    history.layout();
+    let scroll = history.text_lines.saturating_sub(usize::from(HEIGHT));
+    ChatView::new(history, scroll).render(buffer.area, buffer);
+    black_box(&buffer);
+    start.elapsed()
+}
+
+fn report(name: &str, total: Duration) {
+    println!("{name:<24} {:>10.3?} per frame", total / FRAMES);
+}
+
+fn main() {
+    let mut buffer = Buffer::empty(Rect::new(0, 0, WIDTH, HEIGHT));
+    let mut history = conversation();
+    history.layout();
+    println!("{MESSAGES} messages, {} lines", history.text_lines);
+
+    // Changing the width throws the cache away, like the first frame after a resize
+    let mut total = Duration::ZERO;
+    for i in 0..FRAMES {
+        history.text_width = WIDTH - 2 - u16::try_from(i % 2).unwrap_or_default();
+        total += frame(&mut history, &mut buffer);
+    }
+    report("uncached", total);
+
+    history.text_width = WIDTH - 2;
+    frame(&mut history, &mut buffer);
+    let mut total = Duration::ZERO;
+    for _ in 0..FRAMES {
+        total += frame(&mut history, &mut buffer);
+    }
+    report("cached", total);
+
+    let mut total = Duration::ZERO;
+    for i in 0..FRAMES {
+        history.push_stream("token ", i == 0);
+        total += frame(&mut history, &mut buffer);
+    }
+    report("streaming", total);
+}
@@ -0,0 +1,94 @@
+//! A tiny MCP server that talks over stdio, for trying out the MCP client.
+//!
+//! Add it to the config with
+//!
+//! ```json
+//! "mcp_servers": {
+//!   "example": { "command": "cargo", "args": ["run", "--quiet", "--example", "mcp_server"] }
+//! }
+//! ```
+use serde_json::{json, Value};
+use std::io::{self, BufRead, Write};
+
+fn tools() -> Value {
+    json!([
+        {
+            "name": "echo",
+            "description": "Repeat the given text back",
+            "inputSchema": {
+                "type": "object",
+                "properties": { "text": { "type": "string" } },
+                "required": ["text"]
+            }
+        },
+        {
+            "name": "add",
+            "description": "Add two numbers",
+            "inputSchema": {
+                "type": "object",
+                "properties": { "a": { "type": "number" }, "b": { "type": "number" } },
+                "required": ["a", "b"]
+            }
+        }
+    ])
+}
+
+fn call(name: &str, arguments: &Value) -> Value {
+    let text = match name {
+        "echo" => arguments["text"].as_str().unwrap_or_default().to_string(),
+        "add" => {
+            let a = arguments["a"].as_f64().unwrap_or_default();
+            let b = arguments["b"].as_f64().unwrap_or_default();
+            (a + b).to_string()
+        }
+        _ => {
+            return json!({
+                "content": [{ "type": "text", "text": format!("Unknown tool {name}") }],
+                "isError": true
+            })
+        }
+    };
+    json!({ "content": [{ "type": "text", "text": text }] })
+}
+
+fn main() -> io::Result<()> {
+    let mut stdout = io::stdout();
+    for line in io::stdin().lock().lines() {
+        let Ok(request) = serde_json::from_str::<Value>(&line?) else {
+            continue;
+        };
+        // Notifications don't get a response
+        let Some(id) = request.get("id") else {
+            continue;
+        };
+
+        let result = match request["method"].as_str() {
+            Some("initialize") => json!({
+                "protocolVersion": "2024-11-05",
+                "capabilities": { "tools": {} },
+                "serverInfo": { "name": "example", "version": "0.1.0" }
+            }),
+            Some("tools/list") => json!({ "tools": tools() }),
+            Some("tools/call") => call(
+                request["params"]["name"].as_str().unwrap_or_default(),
+                &request["params"]["arguments"],
+            ),
+            _ => {
+                let error = json!({
+                    "jsonrpc": "2.0",
+                    "id": id,
+                    "error": { "code": -32601, "message": "Method not found" }
+                });
+                writeln!(stdout, "{error}")?;
+                continue;
+            }
+        };
+        writeln!(
+            stdout,
+            "{}",
+            json!({ "jsonrpc": "2.0", "id": id, "result": result })
+        )?;
+        stdout.flush()?;
+    }
+    Ok(())
+}
@@ -0,0 +1,204 @@
+use crossterm::event::{KeyCode, KeyEvent, KeyModifiers};
+use std::fmt;
+use std::str::FromStr;
+
+/// A key and the modifiers held with it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct KeyBinding {
+    pub code: KeyCode,
+    pub modifiers: KeyModifiers,
+}
+
+impl KeyBinding {
+    #[must_use]
+    pub const fn ctrl(c: char) -> Self {
+        Self {
+            code: KeyCode::Char(c),
+            modifiers: KeyModifiers::CONTROL,
+        }
+    }
+
+    /// Whether the key is pressed with at least the binding's modifiers, so it still works when
+    /// the terminal reports others like Shift.
+    #[must_use]
+    pub fn matches(&self, event: &KeyEvent) -> bool {
+        self.code == event.code && event.modifiers.contains(self.modifiers)
+    }
+
+    /// Whether the key is pressed after the leader, where the modifiers can be left off so
+    /// `C-c d` works as well as `C-c C-d`.
+    #[must_use]
+    pub fn matches_after_leader(&self, event: &KeyEvent) -> bool {
+        self.code == event.code && self.modifiers.contains(event.modifiers)
+    }
+}
+
+impl FromStr for KeyBinding {
+    type Err = String;
+
+    /// Parse a key written like `C-c`, `M-x`, `C-Space` or `F2`.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut modifiers = KeyModifiers::NONE;
+        let mut rest = s;
+        loop {
+            if let Some(after) = rest.strip_prefix("C-") {
+                modifiers |= KeyModifiers::CONTROL;
+                rest = after;
+            } else if let Some(after) = rest.strip_prefix("M-") {
+                modifiers |= KeyModifiers::ALT;
+                rest = after;
+            } else {
+                break;
+            }
+        }
+
+        let mut chars = rest.chars();
+        let code = match (chars.next(), chars.next(), rest) {
+            (Some(c), None, _) => KeyCode::Char(c),
+            (_, _, "Space") => KeyCode::Char(' '),
+            (_, _, "Esc") => KeyCode::Esc,
+            (_, _, "Tab") => KeyCode::Tab,
+            _ => rest
+                .strip_prefix('F')
+                .and_then(|n| n.parse().ok())
+                .map(KeyCode::F)
+                .ok_or_else(|| format!("Unknown key {s}"))?,
+        };
+        Ok(Self { code, modifiers })
+    }
+}
+
+impl fmt::Display for KeyBinding {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.modifiers.contains(KeyModifiers::CONTROL) {
+            write!(f, "C-")?;
+        }
+        if self.modifiers.contains(KeyModifiers::ALT) {
+            write!(f, "M-")?;
+        }
+        match self.code {
+            KeyCode::Char(' ') => write!(f, "Space"),
+            KeyCode::Char(c) => write!(f, "{c}"),
+            KeyCode::F(n) => write!(f, "F{n}"),
+            code => write!(f, "{code:?}"),
+        }
+    }
+}
+
+/// Everything the app can do outside of editing the input.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Action {
+    Quit,
+    Submit,
+    Reset,
+    Retry,
+    Cancel,
+    CopyLast,
+    Palette,
+    Focus,
+    Search,
+    HistorySearch,
+    SystemPrompt,
+    NextPersona,
+    Template,
+    Recall,
+    Save,
+    Export,
+    Help,
+}
+
+impl Action {
+    /// Every action in the order they're listed in the palette.
+    pub const ALL: [Self; 17] = [
+        Self::Submit,
+        Self::Retry,
+        Self::Cancel,
+        Self::CopyLast,
+        Self::Reset,
+        Self::Focus,
+        Self::Search,
+        Self::HistorySearch,
+        Self::SystemPrompt,
+        Self::NextPersona,
+        Self::Template,
+        Self::Recall,
+        Self::Save,
+        Self::Export,
+        Self::Help,
+        Self::Palette,
+        Self::Quit,
+    ];
+
+    #[must_use]
+    pub const fn name(self) -> &'static str {
+        match self {
+            Self::Quit => "Quit",
+            Self::Submit => "Submit message",
+            Self::Reset => "Reset chat",
+            Self::Retry => "Retry request",
+            Self::Cancel => "Stop the response",
+            Self::CopyLast => "Copy last message",
+            Self::Palette => "Command palette",
+            Self::Focus => "Switch focus between input and chat",
+            Self::Search => "Search conversation",
+            Self::HistorySearch => "Search sent prompts",
+            Self::SystemPrompt => "Edit system prompt",
+            Self::NextPersona => "Next persona",
+            Self::Template => "Insert template",
+            Self::Recall => "Search saved conversations",
+            Self::Save => "Save conversation",
+            Self::Export => "Export conversation as Markdown",
+            Self::Help => "Show commands",
+        }
+    }
+
+    /// The key that runs the action, if it has one.
+    #[must_use]
+    pub const fn binding(self) -> Option<KeyBinding> {
+        match self {
+            Self::Quit => Some(KeyBinding::ctrl('c')),
+            Self::Submit => Some(KeyBinding::ctrl('d')),
+            Self::Reset => Some(KeyBinding::ctrl('l')),
+            Self::Retry => Some(KeyBinding::ctrl('t')),
+            Self::Cancel => Some(KeyBinding::ctrl('g')),
+            Self::CopyLast => Some(KeyBinding::ctrl('x')),
+            Self::Palette => Some(KeyBinding::ctrl('q')),
+            Self::Focus => Some(KeyBinding::ctrl('o')),
+            Self::Search => Some(KeyBinding::ctrl('s')),
+            Self::HistorySearch => Some(KeyBinding::ctrl('r')),
+            Self::SystemPrompt
+            | Self::NextPersona
+            | Self::Template
+            | Self::Recall
+            | Self::Save
+            | Self::Export
+            | Self::Help => None,
+        }
+    }
+
+    /// The action bound to a key event.
+    #[must_use]
+    pub fn from_key(event: &KeyEvent) -> Option<Self> {
+        Self::ALL.into_iter().find(|action| {
+            action
+                .binding()
+                .is_some_and(|binding| binding.matches(event))
+        })
+    }
+
+    /// The action bound to a key event pressed after the leader key.
+    #[must_use]
+    pub fn from_leader_key(event: &KeyEvent) -> Option<Self> {
+        Self::ALL.into_iter().find(|action| {
+            action
+                .binding()
+                .is_some_and(|binding| binding.matches_after_leader(event))
+        })
+    }
+}
+
+impl fmt::Display for Action {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.name())
+    }
+}
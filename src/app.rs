@@ -1,18 +1,43 @@
-use crate::config::{Prompt, Role};
+use crate::archive::{self, Embedder, Hit};
+use crate::attach::{self, Image};
+use crate::chat::{History, Meta};
+use crate::command::{self, Command, Format};
+use crate::config::{self, Final, Prompt, Role};
+use crate::context::{self, Index};
 use crate::event::Event;
-use crate::input::{Mode, StyledTextArea, Transition, Vim};
+use crate::input::{Emacs, Mode, StyledTextArea, Vim};
+use crate::input_history::InputHistory;
+use crate::mcp;
+use crate::shell::{self, Shell};
+use crate::template;
+use crate::tool::{Runner, Tool, ToolCall};
+use crate::utils;
 use crate::widgets::error::PopupMessage;
 use crate::widgets::error::Severity;
-use crate::{chat::History, config::Final};
+use crate::widgets::palette::Palette;
+use crate::widgets::preview::Preview;
+use crate::widgets::recall::Recall;
+use crate::widgets::reverse_search::ReverseSearch;
+use crate::widgets::search::Search;
+use crate::widgets::system::SystemEditor;
+use crate::widgets::template::{Form, TemplatePicker};
 use async_openai::config::OpenAIConfig;
-use async_openai::types::CreateChatCompletionRequestArgs;
+use async_openai::types::{
+    ChatCompletionRequestMessage, ChatCompletionRequestSystemMessageArgs,
+    CreateChatCompletionRequestArgs,
+};
 use async_openai::Client;
 use clippers::Clipboard;
-use crossterm::event::KeyEvent;
+use crossterm::event::{KeyCode, KeyEvent, KeyModifiers};
 use futures::StreamExt;
 use log::{debug, error, info, warn};
+use std::collections::HashMap;
 use std::error::Error;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
 use tokio::sync::mpsc;
+use tokio::task::JoinHandle;
 use tui_logger::TuiWidgetState;
 use tui_textarea::TextArea;
 
@@ -20,7 +45,16 @@ use tui_textarea::TextArea;
 /// Application result type.
 pub type AppResult<T> = std::result::Result<T, Box<dyn Error>>;
 
+/// Which pane key presses go to.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Focus {
+    #[default]
+    Input,
+    Chat,
+}
+
 /// Application.
+#[allow(clippy::struct_excessive_bools)]
 pub struct App<'a> {
     /// Is the application running?
     pub running: bool,
@@ -30,54 +64,144 @@ pub struct App<'a> {
     pub input_editor: TextArea<'a>,
     /// the vim mode handler
     pub vim: Vim,
-    /// how much to scroll text
-    pub chat_scroll: (u16, u16),
+    /// the emacs mode handler
+    pub emacs: Emacs,
+    /// whether the leader key was pressed and the next key runs an action
+    pub leader_pending: bool,
+    /// the first line of the chat in view
+    pub chat_scroll: usize,
+    /// how many lines of the chat fit in the chat pane
+    pub chat_height: u16,
+    /// whether the chat stays scrolled to the bottom as new text comes in
+    pub follow: bool,
+    /// whether there's text below the view that came in since the user scrolled up
+    pub unseen: bool,
+    /// the pane keys go to
+    pub focus: Focus,
+    /// the message selected in the chat while it has focus
+    pub selected: Option<usize>,
     /// the text of the chat
     pub chat_text: History,
     /// Is GPT currently generating text?
     pub generating: bool,
+    /// The task streaming the response, so it can be cancelled
+    pub generation: Option<JoinHandle<()>>,
+    /// when the answer being generated was asked for, and how it's going so far
+    pub answer: Option<(Instant, Meta)>,
     /// logger widget state
     pub debug_state: TuiWidgetState,
     // if we have an error message
     pub error: Option<PopupMessage>,
+    /// hint shown under the input editor
+    pub input_hint: Option<String>,
+    /// the command palette, if it's open
+    pub palette: Option<Palette>,
+    /// the system prompt editor, if it's open
+    pub system_editor: Option<SystemEditor<'a>>,
+    /// the template picker, if it's open
+    pub template_picker: Option<TemplatePicker<'a>>,
+    /// the message waiting to be sent, if it's being previewed
+    pub preview: Option<Preview>,
+    /// the search of saved conversations, if it's open
+    pub recall: Option<Recall>,
+    /// the name the conversation is archived under
+    pub conversation: String,
+    /// the message to scroll to the next time the chat is drawn
+    pub jump_to: Option<usize>,
+    /// the search of the conversation, if it's open
+    pub search: Option<Search>,
+    /// the prompts sent before
+    pub input_history: InputHistory,
+    /// the search of the prompt history, if it's open
+    pub reverse_search: Option<ReverseSearch>,
+    /// whether the `!{command}`s of a message are running
+    pub running_commands: bool,
+    /// the file `/save` is waiting for the user to confirm overwriting
+    pub overwrite: Option<PathBuf>,
+    /// tool calls waiting to be confirmed
+    pub pending_tools: Vec<ToolCall>,
+    /// the confirmed tool calls that haven't finished yet, by call id
+    pub running_tools: HashMap<String, JoinHandle<()>>,
+    /// the index of the context directory, if there is one
+    pub context: Option<Index>,
+    /// the files given to the model for the answer being generated
+    pub pending_sources: Vec<String>,
 }
 
-impl Default for App<'_> {
-    fn default() -> Self {
-        let config = Final::default();
+impl App<'_> {
+    /// Constructs a new instance of [`App`].
+    pub fn new() -> AppResult<Self> {
+        let config = Final::load()?;
         let mut def = Self {
             running: true,
             config: config.clone(),
             input_editor: StyledTextArea::styled_default(),
             vim: Vim::new(Mode::Normal),
-            chat_scroll: (0, 0),
+            emacs: Emacs::new(),
+            leader_pending: false,
+            chat_scroll: 0,
+            chat_height: 0,
+            follow: true,
+            unseen: false,
+            focus: Focus::Input,
+            selected: None,
             chat_text: History::default(),
             generating: false,
+            generation: None,
+            answer: None,
             debug_state: TuiWidgetState::default(),
             error: None,
+            input_hint: None,
+            palette: None,
+            system_editor: None,
+            template_picker: None,
+            preview: None,
+            recall: None,
+            conversation: utils::timestamp(),
+            jump_to: None,
+            search: None,
+            input_history: InputHistory::load(),
+            reverse_search: None,
+            running_commands: false,
+            overwrite: None,
+            pending_tools: vec![],
+            running_tools: HashMap::new(),
+            context: None,
+            pending_sources: vec![],
         };
 
+        def.chat_text.folding = config.folding;
         def.chat_text.extend(config.prompt);
 
+        if let Some(dir) = &config.context_dir {
+            match Index::open(dir) {
+                Ok(index) => def.context = Some(index),
+                Err(err) => {
+                    def.error = Some(PopupMessage::new(
+                        format!("Couldn't index {}: {err}", dir.display()),
+                        Severity::Error,
+                    ));
+                }
+            }
+        }
+
+        if let Some(name) = &config.template {
+            if let Err(err) = def.open_templates(Some(name)) {
+                def.error = Some(PopupMessage::new(err.to_string(), Severity::Error));
+            }
+        }
+
         if config.vim {
             def.input_editor.set_block(
                 StyledTextArea::styled_default()
                     .block()
                     .unwrap()
                     .clone()
-                    .title(def.vim.mode.to_string()),
+                    .title(def.vim.to_string()),
             );
         }
 
-        def
-    }
-}
-
-impl App<'_> {
-    /// Constructs a new instance of [`App`].
-    #[must_use]
-    pub fn new() -> Self {
-        Self::default()
+        Ok(def)
     }
 
     /// Handles the tick event of the terminal.
@@ -87,17 +211,220 @@ impl App<'_> {
     pub fn quit(&mut self) {
         self.running = false;
     }
-    pub fn scroll_down(&mut self, by_lines: u16) {
-        if self.chat_scroll.0 < u16::try_from(self.chat_text.text_lines).unwrap_or(10) - (by_lines)
-        {
-            self.chat_scroll.0 += by_lines;
+
+    /// The furthest the chat can be scrolled, which shows its last line at the bottom.
+    #[must_use]
+    pub fn max_scroll(&self) -> usize {
+        self.chat_text
+            .text_lines
+            .saturating_sub(usize::from(self.chat_height))
+    }
+
+    /// Scroll the chat to `line`. Following new text stops unless that's the bottom.
+    pub fn scroll_to(&mut self, line: usize) {
+        self.chat_scroll = line.min(self.max_scroll());
+        self.follow = self.chat_scroll >= self.max_scroll();
+        if self.follow {
+            self.unseen = false;
+        }
+    }
+
+    pub fn scroll_down(&mut self, by_lines: usize) {
+        self.scroll_to(self.chat_scroll.saturating_add(by_lines));
+    }
+
+    pub fn scroll_up(&mut self, by_lines: usize) {
+        self.scroll_to(self.chat_scroll.saturating_sub(by_lines));
+    }
+
+    /// Scroll down to the newest text and keep following it.
+    pub fn scroll_to_bottom(&mut self) {
+        self.scroll_to(usize::MAX);
+    }
+
+    /// How far a page up or down scrolls. A line of the last page stays in view.
+    #[must_use]
+    pub const fn page(&self) -> usize {
+        if self.chat_height > 1 {
+            self.chat_height as usize - 1
+        } else {
+            1
+        }
+    }
+
+    /// Move key presses between the input editor and the chat. The chat starts with the message
+    /// at the bottom of the view selected.
+    pub fn toggle_focus(&mut self) {
+        self.focus = match self.focus {
+            Focus::Input => Focus::Chat,
+            Focus::Chat => Focus::Input,
+        };
+        if self.focus == Focus::Chat {
+            let bottom = self.chat_scroll + usize::from(self.chat_height);
+            let line = bottom.min(self.chat_text.text_lines).saturating_sub(1);
+            let message = self
+                .chat_text
+                .message_at(line)
+                .unwrap_or(self.chat_text.history.len());
+            self.selected = self.shown_message((0..=message).rev());
+        }
+    }
+
+    /// The selected message, if it's still there.
+    #[must_use]
+    pub fn selected(&self) -> Option<usize> {
+        self.selected
+            .filter(|&index| self.chat_text.is_shown(index))
+    }
+
+    /// The first of `indices` that's shown in the chat.
+    fn shown_message(&self, mut indices: impl Iterator<Item = usize>) -> Option<usize> {
+        indices.find(|&index| self.chat_text.is_shown(index))
+    }
+
+    /// Select the message after the selected one.
+    pub fn select_next(&mut self) {
+        let start = self.selected().map_or(0, |index| index + 1);
+        if let Some(index) = self.shown_message(start..self.chat_text.history.len()) {
+            self.select(index);
+        }
+    }
+
+    /// Select the message before the selected one.
+    pub fn select_previous(&mut self) {
+        let end = self.selected().unwrap_or(self.chat_text.history.len());
+        if let Some(index) = self.shown_message((0..end).rev()) {
+            self.select(index);
+        }
+    }
+
+    /// Select the next pinned message after the selected one, or before it if not `forward`.
+    pub fn select_pinned(&mut self, forward: bool) {
+        let pinned = &self.chat_text.pinned;
+        let next = match (self.selected(), forward) {
+            (Some(index), true) => pinned.range(index + 1..).next(),
+            (Some(index), false) => pinned.range(..index).next_back(),
+            (None, true) => pinned.first(),
+            (None, false) => pinned.last(),
+        };
+        if let Some(&index) = next {
+            self.select(index);
+        }
+    }
+
+    /// Select the message at `index` and scroll it into view.
+    pub fn select(&mut self, index: usize) {
+        self.selected = Some(index);
+        self.chat_text.layout();
+        let lines = self.chat_text.message_range(index);
+        let height = usize::from(self.chat_height);
+        if lines.start < self.chat_scroll {
+            self.scroll_to(lines.start);
+        } else if lines.end > self.chat_scroll + height {
+            self.scroll_to(lines.end.saturating_sub(height).min(lines.start));
+        }
+    }
+
+    /// Copy the selected message to the clipboard.
+    pub fn copy_selected(&self) -> AppResult<()> {
+        let Some(index) = self.selected() else {
+            return Ok(());
+        };
+        let text = History::message_to_string(&self.chat_text.history[index]);
+        Clipboard::get()
+            .write_text(&text)
+            .map_err(|err| format!("Couldn't copy the message to the clipboard: {err:?}").into())
+    }
+
+    /// Remove the selected message from the conversation, along with the tool calls and outputs
+    /// that go with it.
+    pub fn delete_selected(&mut self) -> AppResult<()> {
+        let Some(index) = self.selected() else {
+            return Ok(());
+        };
+        if self.generating {
+            return Err("Wait for the response to finish first.".into());
+        }
+        let group = self.chat_text.group(index);
+        let start = group.start;
+        self.chat_text.remove(group);
+        self.chat_text.tokens = self.chat_text.num_tokens(&self.config.model);
+        self.selected = self
+            .shown_message(start..self.chat_text.history.len())
+            .or_else(|| self.shown_message((0..start).rev()));
+        Ok(())
+    }
+
+    /// Put the selected message back in the input editor to be changed and sent again. Everything
+    /// from it on is removed from the conversation.
+    pub fn edit_selected(&mut self) -> AppResult<()> {
+        let Some(index) = self.selected() else {
+            return Ok(());
+        };
+        if self.generating {
+            return Err("Wait for the response to finish first.".into());
+        }
+        let Some(draft) = self.chat_text.draft(index) else {
+            return Err("Only your own messages can be edited.".into());
+        };
+        self.chat_text.truncate(index);
+        self.chat_text.tokens = self.chat_text.num_tokens(&self.config.model);
+        self.set_input(&draft);
+        self.focus = Focus::Input;
+        self.follow = true;
+        Ok(())
+    }
+
+    /// Remove everything after the selected message, or the selected answer and everything after
+    /// it, so the answer can be generated again. Returns false if there's no message selected.
+    pub fn regenerate_selected(&mut self) -> AppResult<bool> {
+        let Some(index) = self.selected() else {
+            return Ok(false);
+        };
+        if self.generating {
+            return Err("Wait for the response to finish first.".into());
         }
+        let keep = match self.chat_text.history[index] {
+            ChatCompletionRequestMessage::Assistant(_) => self.chat_text.group(index).start,
+            _ => self.chat_text.group(index).end,
+        };
+        self.chat_text.truncate(keep);
+        self.chat_text.tokens = self.chat_text.num_tokens(&self.config.model);
+        self.selected = self.shown_message((0..keep).rev());
+        self.follow = true;
+        Ok(true)
     }
 
-    pub fn scroll_up(&mut self, by_lines: u16) {
-        if self.chat_scroll.0 > (by_lines - 1) {
-            self.chat_scroll.0 -= by_lines;
+    /// What's known about the selected message.
+    #[must_use]
+    pub fn selected_info(&self) -> Option<String> {
+        let index = self.selected()?;
+        let role = match self.chat_text.history[index] {
+            ChatCompletionRequestMessage::User(_) => "Your message",
+            ChatCompletionRequestMessage::Assistant(_) => "Answer",
+            _ => "Tool output",
+        };
+        let mut info = vec![format!(
+            "{role} ({} of {})",
+            index + 1,
+            self.chat_text.history.len()
+        )];
+        if let Some(meta) = self.chat_text.meta.get(&index) {
+            info.push(format!("Model: {}", meta.model));
+            info.push(format!("Tokens: {}", meta.tokens));
+            info.push(format!(
+                "First token after: {:.2}s",
+                meta.latency.as_secs_f64()
+            ));
+            info.push(format!("Took: {:.2}s", meta.duration.as_secs_f64()));
+        } else {
+            let tokens = self.chat_text.message_tokens(index, &self.config.model);
+            info.push(format!("Tokens: ~{tokens}"));
         }
+        if self.chat_text.pinned.contains(&index) {
+            info.push("Pinned".to_string());
+        }
+        Some(info.join("\n"))
     }
 
     /// # Panics
@@ -105,46 +432,263 @@ impl App<'_> {
     /// Will panic if ``StyledTextArea`` cannot be created
     pub fn edit_input(&mut self, input: KeyEvent) {
         if self.config.vim {
-            self.vim = match self
-                .vim
-                .transition(StyledTextArea::into_input(input), &mut self.input_editor)
+            self.vim
+                .transition(StyledTextArea::into_input(input), &mut self.input_editor);
+            // The title shows the keys typed so far as well as the mode
+            self.input_editor.set_block(
+                StyledTextArea::styled_default()
+                    .block()
+                    .unwrap()
+                    .clone()
+                    .title(self.vim.to_string()),
+            );
+        } else if self.config.emacs {
+            // The leader keeps C-r from the app, so searching the prompts is part of the keymap
+            let ctrl = input.modifiers == KeyModifiers::CONTROL;
+            if ctrl
+                && matches!(input.code, KeyCode::Char('r' | 's'))
+                && !self.emacs.prefix_pending()
             {
-                Transition::Mode(mode) if self.vim.mode != mode => {
-                    self.input_editor.set_block(
-                        StyledTextArea::styled_default()
-                            .block()
-                            .unwrap()
-                            .clone()
-                            .title(mode.to_string()),
-                    );
-                    Vim::new(mode)
-                }
-                Transition::Nop | Transition::Mode(_) => self.vim.clone(),
-                Transition::Pending(input) => self.vim.clone().with_pending(input),
+                self.start_reverse_search();
+                return;
             }
+            self.emacs
+                .input(&StyledTextArea::into_input(input), &mut self.input_editor);
+            // The title shows a pending C-x or the mark
+            self.input_editor.set_block(
+                StyledTextArea::styled_default()
+                    .block()
+                    .unwrap()
+                    .clone()
+                    .title(self.emacs.to_string()),
+            );
         } else {
             self.input_editor.input(StyledTextArea::into_input(input));
         }
     }
 
-    pub fn append_message(&mut self) {
-        if !self.generating {
-            self.chat_text.push(Prompt {
-                role: Role::User,
-                content: StyledTextArea::text(&mut self.input_editor),
+    /// Replace the text in the input editor, leaving the cursor at the end.
+    pub fn set_input(&mut self, text: &str) {
+        self.clear_input();
+        self.input_editor.insert_str(text);
+        self.input_hint = command::hint(text);
+    }
+
+    /// Put the prompt sent before the one in the editor into it. Returns false if there isn't one.
+    pub fn history_previous(&mut self) -> bool {
+        let current = StyledTextArea::text(&mut self.input_editor);
+        let Some(text) = self.input_history.older(&current).map(str::to_string) else {
+            return false;
+        };
+        self.set_input(&text);
+        true
+    }
+
+    /// Put the prompt sent after the one in the editor into it. Returns false if there isn't one.
+    pub fn history_next(&mut self) -> bool {
+        let Some(text) = self.input_history.newer().map(str::to_string) else {
+            return false;
+        };
+        self.set_input(&text);
+        true
+    }
+
+    pub fn start_reverse_search(&mut self) {
+        self.focus = Focus::Input;
+        let draft = StyledTextArea::text(&mut self.input_editor);
+        self.reverse_search = Some(ReverseSearch::new(draft));
+    }
+
+    /// Show the prompt the reverse search found in the editor.
+    pub fn show_reverse_search(&mut self) {
+        let Some(search) = &self.reverse_search else {
+            return;
+        };
+        let text = search.text(&self.input_history).to_string();
+        self.set_input(&text);
+    }
+
+    /// Close the reverse search and put back what was in the editor before it.
+    pub fn cancel_reverse_search(&mut self) {
+        if let Some(search) = self.reverse_search.take() {
+            self.set_input(&search.draft);
+        }
+    }
+
+    /// Insert pasted text at the cursor, whatever mode Vim is in.
+    pub fn paste(&mut self, text: &str) {
+        self.focus = Focus::Input;
+        self.input_editor.insert_str(text);
+        self.input_hint = command::hint(&StyledTextArea::text(&mut self.input_editor));
+    }
+
+    /// Complete the slash command or `@path` reference in front of the cursor. Returns false if
+    /// there isn't one.
+    pub fn complete_input(&mut self) -> bool {
+        if self.config.vim && self.vim.mode != Mode::Insert {
+            return false;
+        }
+
+        let (row, col) = self.input_editor.cursor();
+        let before: String = self.input_editor.lines()[row].chars().take(col).collect();
+        if row == 0 && before.starts_with('/') && !before.contains(char::is_whitespace) {
+            let (insert, candidates) = command::complete(&before);
+            self.input_hint = (candidates.len() > 1).then(|| {
+                candidates
+                    .iter()
+                    .map(|spec| format!("/{}", spec.name))
+                    .collect::<Vec<_>>()
+                    .join("  ")
             });
+            self.input_editor.insert_str(insert);
+            return true;
+        }
 
-            self.input_editor = StyledTextArea::styled_default();
+        let word = before.rsplit(char::is_whitespace).next().unwrap_or("");
+        let Some(partial) = word.strip_prefix('@') else {
+            return false;
+        };
+
+        let (insert, candidates) = attach::complete(partial);
+        self.input_hint = if insert.is_empty() && candidates.len() > 1 {
+            Some(
+                candidates
+                    .iter()
+                    .map(|path| {
+                        let name = path.trim_end_matches('/');
+                        &path[name.rfind('/').map_or(0, |i| i + 1)..]
+                    })
+                    .collect::<Vec<_>>()
+                    .join("  "),
+            )
+        } else {
+            None
+        };
+        self.input_editor.insert_str(insert);
+
+        true
+    }
+
+    pub fn append_message(&mut self, content: String, images: Vec<Image>) {
+        if !self.generating {
+            self.chat_text.push_user(content, images);
+            self.follow = true;
+            self.clear_input();
+        }
+    }
+
+    /// Replace the input editor with an empty one.
+    ///
+    /// # Panics
+    ///
+    /// Will panic if ``StyledTextArea`` cannot be created
+    pub fn clear_input(&mut self) {
+        self.input_editor = StyledTextArea::styled_default();
+        self.emacs.forget_mark();
 
-            if self.config.vim {
-                self.input_editor.set_block(
-                    StyledTextArea::styled_default()
-                        .block()
-                        .unwrap()
-                        .clone()
-                        .title(self.vim.mode.to_string()),
+        if self.config.vim {
+            self.input_editor.set_block(
+                StyledTextArea::styled_default()
+                    .block()
+                    .unwrap()
+                    .clone()
+                    .title(self.vim.to_string()),
+            );
+        }
+    }
+
+    /// Run a slash command. Returns a message to show the user if there is one.
+    pub fn run_command(&mut self, command: Command) -> AppResult<Option<String>> {
+        match command {
+            Command::Model(model) => {
+                self.config.model = model;
+                Ok(None)
+            }
+            Command::System(_) | Command::Persona(Some(_)) | Command::Clear if self.generating => {
+                Err("Wait for the response to finish first.".into())
+            }
+            Command::System(prompt) => {
+                self.chat_text.set_system_prompts(vec![prompt]);
+                Ok(None)
+            }
+            Command::EditSystem => {
+                self.system_editor = Some(SystemEditor::new(self.chat_text.system_prompts()));
+                Ok(None)
+            }
+            Command::Persona(Some(name)) => {
+                self.config.set_persona(&name)?;
+                self.chat_text.set_system_prompts(
+                    self.config
+                        .prompt
+                        .iter()
+                        .filter(|prompt| matches!(prompt.role, Role::System))
+                        .map(|prompt| prompt.content.clone())
+                        .collect(),
                 );
+                Ok(None)
+            }
+            Command::Persona(None) => {
+                let active = self.config.persona.as_deref().unwrap_or("default");
+                let list = self
+                    .config
+                    .persona_names()
+                    .into_iter()
+                    .map(|name| {
+                        let model = self
+                            .config
+                            .personas
+                            .get(&name)
+                            .and_then(|persona| persona.model.clone())
+                            .or_else(|| self.config.defaults.model.clone())
+                            .unwrap_or_default();
+                        let marker = if name == active { "*" } else { " " };
+                        format!("{marker} {name} ({model})")
+                    })
+                    .collect::<Vec<_>>()
+                    .join("\n");
+                Ok(Some(list))
+            }
+            Command::Template(name) => {
+                self.open_templates(name.as_deref())?;
+                Ok(None)
+            }
+            Command::Recall(query) => {
+                self.recall = Some(Recall::new(query.unwrap_or_default()));
+                Ok(None)
             }
+            Command::Temp(temperature) => {
+                self.config.temperature = Some(temperature);
+                Ok(None)
+            }
+            Command::Save(name) => {
+                let name = name.unwrap_or_else(utils::timestamp);
+                let path = archive::path(&name)?;
+                if path.exists() {
+                    // It could be another conversation, so ask first
+                    self.overwrite = Some(path);
+                    return Ok(None);
+                }
+                self.save_conversation(&path).map(Some)
+            }
+            Command::Export(format, path) => {
+                let path = path.unwrap_or_else(|| {
+                    PathBuf::from(format!("gptrs-{}", utils::timestamp()))
+                        .with_extension(format.extension())
+                });
+                match format {
+                    Format::Markdown => fs::write(&path, self.chat_text.to_markdown())?,
+                    Format::Json => self.chat_text.save(&path)?,
+                }
+                Ok(Some(format!(
+                    "Exported the conversation to {}",
+                    path.display()
+                )))
+            }
+            Command::Clear => {
+                self.reset_history();
+                Ok(None)
+            }
+            Command::Help => Ok(Some(command::help())),
         }
     }
 
@@ -156,61 +700,414 @@ impl App<'_> {
     ///
     /// Returns Ok
     pub fn start_generation(&mut self, sender: mpsc::Sender<Event>) -> Result<(), Box<dyn Error>> {
+        // Two streams would write into the same answer, and the old one couldn't be cancelled
+        if let Some(generation) = self.generation.take() {
+            generation.abort();
+        }
         let model = self.config.model.clone();
-        let messages = self.chat_text.history.clone();
+        let mut messages = self.chat_text.history.clone();
+        if let Some((i, message)) = self.retrieve_context() {
+            messages.insert(i, message);
+        }
         let key = self.config.api_key.clone();
         let base = self.config.api_base.clone();
-        tokio::spawn(async move {
+        let temperature = self.config.temperature;
+        let top_p = self.config.top_p;
+        let max_tokens = self.config.max_tokens.unwrap_or(2048);
+        let tools: Vec<_> = self.config.tools.iter().map(Tool::to_openai).collect();
+        let frame = self.frame_time();
+        self.generation = Some(tokio::spawn(async move {
             let config = OpenAIConfig::new().with_api_key(key).with_api_base(base);
             let client = Client::with_config(config);
             debug!("Created a new client");
 
-            let request = CreateChatCompletionRequestArgs::default()
+            let mut request = CreateChatCompletionRequestArgs::default();
+            request
                 .model(model)
-                .max_tokens(2048u16)
-                .messages(messages)
-                .build()
-                .unwrap();
+                .max_tokens(max_tokens)
+                .messages(messages);
+            if let Some(temperature) = temperature {
+                request.temperature(temperature);
+            }
+            if let Some(top_p) = top_p {
+                request.top_p(top_p);
+            }
+            if !tools.is_empty() {
+                request.tools(tools);
+            }
+            let request = request.build().unwrap();
             info!("New request: {:?}", request);
             let mut stream = client.chat().create_stream(request).await.unwrap();
             let mut first = true;
-            while let Some(result) = stream.next().await {
-                debug!("Handling {:?}", result);
-                match result {
-                    Ok(response) => {
+            let mut calls = vec![];
+            // Text is held back until a frame has passed since the last send, so a fast stream
+            // sends one event a frame instead of one a token
+            let mut pending = String::new();
+            let mut count = 0;
+            let mut sent = Instant::now();
+            loop {
+                let next = if pending.is_empty() {
+                    Some(stream.next().await)
+                } else {
+                    let wait = frame.saturating_sub(sent.elapsed());
+                    tokio::time::timeout(wait, stream.next()).await.ok()
+                };
+                let done = matches!(next, Some(None));
+                match next.flatten() {
+                    Some(Ok(response)) => {
+                        debug!("Handling {:?}", response);
                         for chat_choice in &response.choices {
                             if let Some(ref content) = chat_choice.delta.content {
-                                match sender.send(Event::Token(content.to_string(), first)).await {
-                                    Ok(()) => {}
-                                    Err(err) => {
-                                        error!("Couldn't send event because of this error: {err:?}. Assuming we shut down.");
-                                        return;
-                                    }
-                                }
-                                first = false;
+                                pending.push_str(content);
+                                count += 1;
+                            }
+                            for chunk in chat_choice.delta.tool_calls.iter().flatten() {
+                                ToolCall::accumulate(&mut calls, chunk);
                             }
                         }
                     }
-                    Err(res) => {
+                    Some(Err(res)) => {
                         warn!("Stream response returned {:?}", res);
                         sender
                             .send(Event::ErrorPopup(Severity::Error, res.to_string()))
                             .await
                             .unwrap();
                     }
+                    None => {}
+                }
+
+                if !pending.is_empty() && (done || sent.elapsed() >= frame) {
+                    let token = std::mem::take(&mut pending);
+                    let event = Event::Token(token, std::mem::take(&mut count), first);
+                    if let Err(err) = sender.send(event).await {
+                        error!("Couldn't send event because of this error: {err:?}. Assuming we shut down.");
+                        return;
+                    }
+                    first = false;
+                    sent = Instant::now();
+                }
+                if done {
+                    break;
                 }
             }
 
-            sender.send(Event::EndGeneration).await.unwrap();
-        });
+            if calls.is_empty() {
+                sender.send(Event::EndGeneration).await.unwrap();
+            } else {
+                sender.send(Event::ToolCalls(calls)).await.unwrap();
+            }
+        }));
 
         Ok(())
     }
 
+    /// Stop streaming the response. What came in so far is kept.
+    ///
+    /// Returns whether there was a response to stop.
+    pub fn cancel_generation(&mut self) -> bool {
+        let Some(generation) = self.generation.take() else {
+            return false;
+        };
+        generation.abort();
+        info!("Cancelled the response");
+        true
+    }
+
+    /// Stop the tools that are still running. Each gets an output saying so, since every call
+    /// needs one before the conversation can go on.
+    ///
+    /// Returns whether there were tools to stop.
+    pub fn cancel_tools(&mut self) -> bool {
+        if self.running_tools.is_empty() {
+            return false;
+        }
+        for (id, task) in self.running_tools.drain() {
+            task.abort();
+            self.chat_text
+                .push_tool_output(id, "The user stopped the tool.".to_string());
+        }
+        info!("Stopped the running tools");
+        true
+    }
+
+    /// Forget the tool calls of the conversation. Anything they send back later is dropped.
+    fn forget_tools(&mut self) {
+        self.pending_tools.clear();
+        for (_, task) in self.running_tools.drain() {
+            task.abort();
+        }
+    }
+
+    /// Start keeping track of how the answer being generated is going.
+    pub fn start_answer(&mut self) {
+        let meta = Meta {
+            model: self.config.model.clone(),
+            ..Meta::default()
+        };
+        self.answer = Some((Instant::now(), meta));
+    }
+
+    /// Note how the answer that just finished was generated.
+    pub fn finish_answer(&mut self) {
+        if let Some((started, mut meta)) = self.answer.take() {
+            meta.duration = started.elapsed();
+            self.chat_text.set_last_meta(meta);
+        }
+    }
+
+    /// How long a frame lasts at the configured frame rate.
+    #[must_use]
+    pub fn frame_time(&self) -> Duration {
+        Duration::from_secs(1) / u32::from(self.config.fps)
+    }
+
+    /// Find the parts of the context directory that match the last user message. Returns the
+    /// system message to send before it and where it goes.
+    ///
+    /// # Panics
+    ///
+    /// Will panic if the ``ChatCompletionRequestSystemMessageArgs`` cannot be created
+    pub fn retrieve_context(&mut self) -> Option<(usize, ChatCompletionRequestMessage)> {
+        self.pending_sources.clear();
+        let index = self.context.as_ref()?;
+        let (i, query) = self.chat_text.last_user_message()?;
+        let chunks = index.search(&query, context::RESULTS);
+        if chunks.is_empty() {
+            return None;
+        }
+
+        self.pending_sources = chunks.iter().map(|chunk| chunk.citation()).collect();
+        let message = ChatCompletionRequestSystemMessageArgs::default()
+            .content(context::message(&index.root, &chunks))
+            .build()
+            .unwrap();
+        Some((i, ChatCompletionRequestMessage::System(message)))
+    }
+
     pub fn reset_history(&mut self) {
+        self.conversation = utils::timestamp();
+        self.forget_tools();
+        let remote_tools = std::mem::take(&mut self.chat_text.remote_tools);
         self.chat_text = History::default();
+        self.chat_text.remote_tools = remote_tools;
+        self.chat_text.folding = self.config.folding;
         self.chat_text.extend(self.config.prompt.clone());
-        self.chat_scroll = (0, 0);
+        self.selected = None;
+        self.chat_scroll = 0;
+        self.follow = true;
+        self.unseen = false;
+    }
+
+    /// Save the conversation to the archive so it can be searched later. Conversations the user
+    /// hasn't said anything in aren't saved.
+    pub fn archive_conversation(&self) -> AppResult<()> {
+        if !self.config.archive || self.chat_text.last_user_message().is_none() {
+            return Ok(());
+        }
+        self.chat_text.save(&archive::path(&self.conversation)?)
+    }
+
+    /// Save the conversation to `path`, replacing what's there.
+    ///
+    /// Returns the message telling the user where it went.
+    pub fn save_conversation(&self, path: &Path) -> AppResult<String> {
+        self.chat_text.save(path)?;
+        Ok(format!("Saved the conversation to {}", path.display()))
+    }
+
+    /// Search the archive for the query in the search overlay in the background. The hits are
+    /// sent back as an event.
+    pub fn search_archive(&mut self, sender: mpsc::Sender<Event>) {
+        let Some(recall) = self.recall.as_mut().filter(|recall| recall.is_stale()) else {
+            return;
+        };
+        recall.searching = true;
+
+        let query = recall.query.clone();
+        let embedder = Embedder {
+            api_base: self.config.embedding_base.clone(),
+            api_key: self.config.api_key.clone(),
+            model: self.config.embedding_model.clone(),
+        };
+        tokio::spawn(async move {
+            let hits = archive::search(embedder, query.clone())
+                .await
+                .map_err(|err| format!("Couldn't search the saved conversations: {err}"));
+            if let Err(err) = sender.send(Event::RecallResults(query, hits)).await {
+                error!("Couldn't send the search results because of this error: {err:?}. Assuming we shut down.");
+            }
+        });
+    }
+
+    /// Replace the conversation with the archived one `hit` is in and scroll to the hit.
+    pub fn open_conversation(&mut self, hit: &Hit) -> AppResult<()> {
+        if self.generating {
+            return Err("Wait for the response to finish first.".into());
+        }
+        self.archive_conversation()?;
+
+        let mut history = History::load(&archive::path(&hit.conversation)?)?;
+        history.remote_tools = std::mem::take(&mut self.chat_text.remote_tools);
+        history.folding = self.config.folding;
+        self.chat_text = history;
+        self.conversation.clone_from(&hit.conversation);
+        self.forget_tools();
+        self.selected = None;
+        self.jump_to = Some(hit.message);
+        Ok(())
+    }
+
+    /// Start the configured MCP servers and make their tools available.
+    pub async fn connect_mcp(&mut self) {
+        if self.config.mcp_servers.is_empty() {
+            return;
+        }
+
+        let (tools, errors) = mcp::connect(&self.config.mcp_servers).await;
+        info!("Connected to {} tools from MCP servers", tools.len());
+        self.chat_text
+            .remote_tools
+            .extend(tools.iter().map(|tool| tool.name.clone()));
+        self.config.tools.extend(tools);
+        if !errors.is_empty() {
+            self.error = Some(PopupMessage::new(errors.join("\n"), Severity::Error));
+        }
+    }
+
+    /// The tool the model calls `name`.
+    #[must_use]
+    pub fn tool(&self, name: &str) -> Option<&Tool> {
+        self.config.tools.iter().find(|tool| tool.name == name)
+    }
+
+    /// The settings commands run with, from the shell tool if it's set up.
+    #[must_use]
+    pub fn shell(&self) -> Shell {
+        match self.tool(shell::NAME).map(|tool| &tool.runner) {
+            Some(Runner::Shell(shell)) => shell.clone(),
+            _ => Shell::default(),
+        }
+    }
+
+    /// Whether a tool call can run without asking first.
+    #[must_use]
+    pub fn is_preapproved(&self, call: &ToolCall) -> bool {
+        match self.tool(&call.name).map(|tool| &tool.runner) {
+            Some(Runner::Shell(shell)) => {
+                shell::command(&call.arguments).is_ok_and(|command| shell.is_allowed(&command))
+            }
+            _ => false,
+        }
+    }
+
+    /// Let the shell command `entry` run without asking from now on, and save that in the config
+    /// file.
+    pub fn allow_shell_command(&mut self, entry: String) -> AppResult<()> {
+        let Some(Runner::Shell(shell)) = self
+            .config
+            .tools
+            .iter_mut()
+            .find(|tool| tool.name == shell::NAME)
+            .map(|tool| &mut tool.runner)
+        else {
+            return Err("The shell tool isn't set up".into());
+        };
+
+        if !shell.allow.contains(&entry) {
+            shell.allow.push(entry);
+        }
+        config::save_shell(&self.config.config_path, shell)
+    }
+
+    /// Run a confirmed tool call in the background. Its output is sent back as an event.
+    pub fn run_tool(&mut self, call: ToolCall, sender: mpsc::Sender<Event>) {
+        let Some(tool) = self.tool(&call.name).cloned() else {
+            self.chat_text
+                .push_tool_output(call.id, format!("There's no tool called {}.", call.name));
+            return;
+        };
+
+        let id = call.id.clone();
+        let task = tokio::spawn(async move {
+            let output = match tool.run(&call.arguments).await {
+                Ok(output) => output,
+                Err(err) => format!("The tool couldn't be run: {err}"),
+            };
+            if let Err(err) = sender.send(Event::ToolOutput(call.id, output)).await {
+                error!("Couldn't send the tool output because of this error: {err:?}. Assuming we shut down.");
+            }
+        });
+        self.running_tools.insert(id, task);
+    }
+
+    /// Open the template picker. If `name` is given skip straight to filling in that template.
+    pub fn open_templates(&mut self, name: Option<&str>) -> AppResult<()> {
+        let dir = template::dir(&self.config.config_path);
+        let mut picker = TemplatePicker::new(template::load_all(&dir)?);
+
+        if let Some(name) = name {
+            let form = Form::new(template::load(&dir, name)?);
+            if form.fields.is_empty() {
+                self.use_template(&form);
+                return Ok(());
+            }
+            picker.form = Some(form);
+        }
+
+        self.template_picker = Some(picker);
+        Ok(())
+    }
+
+    /// Put a filled in template into the input editor and switch to its system prompt.
+    pub fn use_template(&mut self, form: &Form) {
+        self.input_editor.insert_str(form.fill());
+
+        if !form.template.prompt.is_empty() {
+            let (system, examples): (Vec<Prompt>, Vec<Prompt>) = form
+                .template
+                .prompt
+                .iter()
+                .cloned()
+                .partition(|prompt| matches!(prompt.role, Role::System));
+            self.chat_text
+                .set_system_prompts(system.into_iter().map(|prompt| prompt.content).collect());
+            self.chat_text.extend(examples);
+        }
+    }
+
+    /// Make the system prompts of the conversation the default in the config file, or the prompt
+    /// of the active persona.
+    pub fn save_system_prompts(&mut self) -> AppResult<String> {
+        let mut prompt: Vec<Prompt> = self
+            .chat_text
+            .system_prompts()
+            .into_iter()
+            .map(|content| Prompt {
+                role: Role::System,
+                content,
+            })
+            .collect();
+        // Keep any example messages the config starts the conversation with
+        prompt.extend(
+            self.config
+                .prompt
+                .iter()
+                .filter(|prompt| !matches!(prompt.role, Role::System))
+                .cloned(),
+        );
+
+        let persona = self.config.persona.clone();
+        config::save_prompt(&self.config.config_path, persona.as_deref(), &prompt)?;
+        // Switching persona reads the prompt back from these
+        match persona.and_then(|name| self.config.personas.get_mut(&name)) {
+            Some(persona) => persona.prompt.clone_from(&prompt),
+            None => self.config.defaults.prompt.clone_from(&prompt),
+        }
+        self.config.prompt = prompt;
+        Ok(format!(
+            "Saved the system prompt to {}",
+            self.config.config_path.display()
+        ))
     }
 
     pub async fn copy_last_message(&mut self, sender: mpsc::Sender<Event>) {
@@ -0,0 +1,249 @@
+use crate::app::AppResult;
+use crate::chat::History;
+use crate::utils;
+use async_openai::config::OpenAIConfig;
+use async_openai::types::{ChatCompletionRequestMessage, CreateEmbeddingRequestArgs};
+use async_openai::Client;
+use log::info;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, BTreeSet};
+use std::ffi::OsStr;
+use std::fs;
+use std::io;
+use std::path::PathBuf;
+use std::time::UNIX_EPOCH;
+
+/// How many messages are embedded in one request.
+const BATCH: usize = 64;
+/// The most of a message that's embedded. Embedding models have small context windows.
+const TEXT_LIMIT: usize = 4000;
+/// How many hits a search returns.
+const RESULTS: usize = 20;
+
+/// Where conversations are saved, both by `/save` and automatically.
+pub fn dir() -> io::Result<PathBuf> {
+    utils::data_dir("conversations")
+}
+
+/// The file the conversation `name` is saved in. Names can have dots in them, so the extension
+/// is added rather than set.
+pub fn path(name: &str) -> io::Result<PathBuf> {
+    Ok(dir()?.join(format!("{name}.json")))
+}
+
+/// The embeddings endpoint and model used to index conversations.
+#[derive(Clone, Debug)]
+pub struct Embedder {
+    pub api_base: String,
+    pub api_key: String,
+    pub model: String,
+}
+
+impl Embedder {
+    async fn embed(&self, texts: Vec<String>) -> AppResult<Vec<Vec<f32>>> {
+        let config = OpenAIConfig::new()
+            .with_api_key(&self.api_key)
+            .with_api_base(&self.api_base);
+        let request = CreateEmbeddingRequestArgs::default()
+            .model(&self.model)
+            .input(texts)
+            .build()?;
+        let mut response = Client::with_config(config)
+            .embeddings()
+            .create(request)
+            .await?;
+        response.data.sort_by_key(|embedding| embedding.index);
+        Ok(response
+            .data
+            .into_iter()
+            .map(|embedding| embedding.embedding)
+            .collect())
+    }
+}
+
+/// A message found by a search.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Hit {
+    /// The name of the conversation it's in
+    pub conversation: String,
+    /// Where the message is in the conversation
+    pub message: usize,
+    pub role: String,
+    pub text: String,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+struct Entry {
+    conversation: String,
+    message: usize,
+    role: String,
+    text: String,
+    embedding: Vec<f32>,
+}
+
+/// The embeddings of every message in the archive.
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct Embeddings {
+    /// The model the embeddings came from. Embeddings from different models can't be compared.
+    model: String,
+    /// When each conversation was last modified, to tell which ones need embedding again
+    conversations: BTreeMap<String, u64>,
+    entries: Vec<Entry>,
+}
+
+impl Embeddings {
+    fn path() -> io::Result<PathBuf> {
+        Ok(utils::data_dir("embeddings")?.join("index.json"))
+    }
+
+    fn load(model: &str) -> Self {
+        Self::path()
+            .and_then(fs::read_to_string)
+            .ok()
+            .and_then(|text| serde_json::from_str::<Self>(&text).ok())
+            .filter(|embeddings| embeddings.model == model)
+            .unwrap_or_else(|| Self {
+                model: model.to_string(),
+                ..Self::default()
+            })
+    }
+
+    /// Embed the messages that were added or changed since the last update. Messages that are
+    /// still the same keep their embeddings, so an archived conversation that grew by one answer
+    /// only costs that answer.
+    async fn update(&mut self, embedder: &Embedder) -> AppResult<()> {
+        let current = conversations()?;
+        self.entries
+            .retain(|entry| current.contains_key(&entry.conversation));
+
+        let mut pending = vec![];
+        for (name, modified) in &current {
+            if self.conversations.get(name) == Some(modified) {
+                continue;
+            }
+            let Ok(history) = History::load(&path(name)?) else {
+                self.entries.retain(|entry| entry.conversation != *name);
+                continue;
+            };
+            let mut messages = BTreeMap::new();
+            for (message, text) in history.history.iter().enumerate() {
+                let role = match text {
+                    ChatCompletionRequestMessage::User(_) => "user",
+                    ChatCompletionRequestMessage::Assistant(_) => "assistant",
+                    _ => continue,
+                };
+                let text = History::message_to_string(text);
+                if !text.trim().is_empty() {
+                    messages.insert(message, (role, text));
+                }
+            }
+
+            self.entries.retain(|entry| {
+                entry.conversation != *name
+                    || messages
+                        .get(&entry.message)
+                        .is_some_and(|(_, text)| *text == entry.text)
+            });
+            let known: BTreeSet<usize> = self
+                .entries
+                .iter()
+                .filter(|entry| entry.conversation == *name)
+                .map(|entry| entry.message)
+                .collect();
+            for (message, (role, text)) in messages {
+                if !known.contains(&message) {
+                    pending.push((name.clone(), message, role.to_string(), text));
+                }
+            }
+        }
+
+        if !pending.is_empty() {
+            info!("Embedding {} archived messages", pending.len());
+        }
+        for batch in pending.chunks(BATCH) {
+            let texts = batch
+                .iter()
+                .map(|(_, _, _, text)| text.chars().take(TEXT_LIMIT).collect())
+                .collect();
+            let embeddings = embedder.embed(texts).await?;
+            for ((conversation, message, role, text), embedding) in batch.iter().zip(embeddings) {
+                self.entries.push(Entry {
+                    conversation: conversation.clone(),
+                    message: *message,
+                    role: role.clone(),
+                    text: text.clone(),
+                    embedding,
+                });
+            }
+        }
+
+        self.conversations = current;
+        fs::write(Self::path()?, serde_json::to_string(self)?)?;
+        Ok(())
+    }
+}
+
+/// The archived messages most similar in meaning to `query`, best first. Messages that haven't
+/// been embedded yet are embedded first.
+pub async fn search(embedder: Embedder, query: String) -> AppResult<Vec<Hit>> {
+    let mut embeddings = Embeddings::load(&embedder.model);
+    embeddings.update(&embedder).await?;
+    let query = embedder
+        .embed(vec![query])
+        .await?
+        .pop()
+        .ok_or("The embeddings endpoint didn't return anything")?;
+
+    let mut scored: Vec<(f32, &Entry)> = embeddings
+        .entries
+        .iter()
+        .map(|entry| (similarity(&query, &entry.embedding), entry))
+        .collect();
+    scored.sort_by(|a, b| b.0.total_cmp(&a.0));
+    Ok(scored
+        .into_iter()
+        .take(RESULTS)
+        .map(|(_, entry)| Hit {
+            conversation: entry.conversation.clone(),
+            message: entry.message,
+            role: entry.role.clone(),
+            text: entry.text.clone(),
+        })
+        .collect())
+}
+
+/// The cosine similarity of two embeddings.
+fn similarity(a: &[f32], b: &[f32]) -> f32 {
+    let dot: f32 = a.iter().zip(b).map(|(a, b)| a * b).sum();
+    let norm = |v: &[f32]| v.iter().map(|x| x * x).sum::<f32>().sqrt();
+    let norms = norm(a) * norm(b);
+    if norms == 0.0 {
+        0.0
+    } else {
+        dot / norms
+    }
+}
+
+/// The name of every archived conversation with when it was last modified.
+fn conversations() -> io::Result<BTreeMap<String, u64>> {
+    let mut conversations = BTreeMap::new();
+    for entry in fs::read_dir(dir()?)?.filter_map(Result::ok) {
+        let path = entry.path();
+        if path.extension().and_then(OsStr::to_str) != Some("json") {
+            continue;
+        }
+        let Some(name) = path.file_stem() else {
+            continue;
+        };
+        let modified = entry
+            .metadata()
+            .and_then(|metadata| metadata.modified())
+            .ok()
+            .and_then(|time| time.duration_since(UNIX_EPOCH).ok())
+            .map_or(0, |time| {
+                u64::try_from(time.as_millis()).unwrap_or(u64::MAX)
+            });
+        conversations.insert(name.to_string_lossy().to_string(), modified);
+    }
+    Ok(conversations)
+}
@@ -0,0 +1,420 @@
+use crate::app::AppResult;
+use crate::chat::History;
+use crate::config::{Prompt, Role};
+use crate::shell::Shell;
+use base64::engine::general_purpose::STANDARD;
+use base64::Engine;
+use serde::{Deserialize, Serialize};
+use std::fs::{self, File};
+use std::io::Read;
+use std::path::Path;
+
+/// The line that introduces an attached file in an expanded message.
+const HEADER: &str = "Attached file: ";
+
+/// The line that introduces the output of a `!{command}` in an expanded message.
+const OUTPUT_HEADER: &str = "Output of: ";
+
+/// The text part that names the image part following it.
+pub const IMAGE_HEADER: &str = "Attached image: ";
+
+/// How much of a file over the attachment limit is read to estimate its tokens.
+const SAMPLE_SIZE: u64 = 64 * 1024;
+
+/// The largest image the API accepts.
+const IMAGE_LIMIT: u64 = 20 * 1024 * 1024;
+
+/// An image attached to a message, encoded as a data URL.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Image {
+    pub name: String,
+    pub url: String,
+}
+
+/// A user message with its `@path` references expanded.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Expanded {
+    pub text: String,
+    pub images: Vec<Image>,
+}
+
+/// A piece of a user message as it's shown in the chat pane.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Segment<'a> {
+    /// Text the user typed
+    Text(&'a str),
+    /// A file that was attached with an `@path` reference
+    File { path: &'a str, lines: usize },
+    /// The output of a `!{command}`
+    Output { command: &'a str, lines: usize },
+}
+
+/// Expand every `@path` reference in `text` that points at a file.
+///
+/// Text files become a fenced, language tagged block appended to the end of the message and images
+/// are read into data URLs. The reference itself is replaced with the path.
+///
+/// * `text` - The message as typed by the user
+/// * `limit` - The maximum size of a single attached file in bytes
+/// * `model` - The model used to estimate how many tokens a file will cost
+pub fn expand(text: &str, limit: usize, model: &str) -> AppResult<Expanded> {
+    let mut message = String::with_capacity(text.len());
+    let mut files = vec![];
+    let mut images = vec![];
+    let mut last = 0;
+
+    for (start, reference) in references(text) {
+        let path = &reference[1..];
+        if !Path::new(path).is_file() {
+            continue;
+        }
+
+        let size = fs::metadata(path)?.len();
+        if let Some(mime) = image_mime(path) {
+            if size > IMAGE_LIMIT {
+                return Err(format!(
+                    "{path} is {size} bytes, which is over the image limit of {IMAGE_LIMIT} bytes."
+                )
+                .into());
+            }
+            let data = fs::read(path).map_err(|err| format!("Couldn't attach {path}: {err}"))?;
+            images.push(Image {
+                name: path.to_string(),
+                url: format!("data:{mime};base64,{}", STANDARD.encode(data)),
+            });
+        } else {
+            if usize::try_from(size).unwrap_or(usize::MAX) > limit {
+                let tokens = estimate_file_tokens(path, size, model)?;
+                return Err(format!(
+                    "{path} is {size} bytes (~{tokens} tokens), which is over the attachment limit of {limit} bytes."
+                )
+                .into());
+            }
+            let contents =
+                fs::read_to_string(path).map_err(|err| format!("Couldn't attach {path}: {err}"))?;
+            files.push(fence(HEADER, path, language(path), &contents));
+        }
+
+        message.push_str(&text[last..start]);
+        message.push('`');
+        message.push_str(path);
+        message.push('`');
+        last = start + reference.len();
+    }
+    message.push_str(&text[last..]);
+
+    for file in files {
+        message.push_str("\n\n");
+        message.push_str(&file);
+    }
+
+    Ok(Expanded {
+        text: message,
+        images,
+    })
+}
+
+/// Run every `!{command}` in `text` and attach their output.
+///
+/// Returns the text with each marker replaced by its command and the fenced blocks of output to
+/// append to the message.
+pub async fn run_commands(text: &str, shell: &Shell) -> (String, Vec<String>) {
+    let mut message = String::with_capacity(text.len());
+    let mut outputs = vec![];
+    let mut last = 0;
+
+    for (start, end) in commands(text) {
+        let command = text[start + 2..end - 1].trim();
+        let output = match shell.run(command).await {
+            Ok(output) => output,
+            Err(err) => format!("Couldn't run the command: {err}"),
+        };
+        outputs.push(fence(
+            OUTPUT_HEADER,
+            &command.replace('\n', " "),
+            "",
+            &output,
+        ));
+
+        message.push_str(&text[last..start]);
+        message.push('`');
+        message.push_str(command);
+        message.push('`');
+        last = end;
+    }
+    message.push_str(&text[last..]);
+
+    (message, outputs)
+}
+
+/// Whether `text` has any `!{command}` markers.
+#[must_use]
+pub fn has_commands(text: &str) -> bool {
+    !commands(text).is_empty()
+}
+
+/// Split an expanded message into the text the user typed and the files they attached.
+#[must_use]
+pub fn segments(text: &str) -> Vec<Segment<'_>> {
+    let mut segments = vec![];
+    let mut rest = text;
+
+    while let Some((header, kind)) = find_header(rest) {
+        let after_header = &rest[header + kind.len()..];
+        let Some((path, after_path)) = after_header.split_once('\n') else {
+            break;
+        };
+        let fence_len = after_path.chars().take_while(|c| *c == '`').count();
+        let Some((_, body)) = after_path.split_once('\n').filter(|_| fence_len >= 3) else {
+            break;
+        };
+        let closing = format!("\n{}", "`".repeat(fence_len));
+        let Some(end) = find_closing(body, &closing) else {
+            break;
+        };
+
+        let typed = rest[..header].trim_end_matches('\n');
+        if !typed.is_empty() {
+            segments.push(Segment::Text(typed));
+        }
+        let lines = body[..end].lines().count();
+        segments.push(if kind == OUTPUT_HEADER {
+            Segment::Output {
+                command: path,
+                lines,
+            }
+        } else {
+            Segment::File { path, lines }
+        });
+        rest = &body[end + closing.len()..];
+    }
+
+    let rest = rest.trim_start_matches('\n');
+    if !rest.is_empty() || segments.is_empty() {
+        segments.push(Segment::Text(rest));
+    }
+
+    segments
+}
+
+/// Turn an expanded message back into what the user typed, with `@path` references for the
+/// files and `images` it attached and `!{command}` markers for the outputs.
+#[must_use]
+pub fn unexpand(text: &str, images: &[String]) -> String {
+    let mut typed = vec![];
+    let mut references = vec![];
+    for segment in segments(text) {
+        match segment {
+            Segment::Text(text) => typed.push(text),
+            Segment::File { path, .. } => references.push((path, format!("@{path}"))),
+            Segment::Output { command, .. } => {
+                references.push((command, format!("!{{{command}}}")));
+            }
+        }
+    }
+    references.extend(
+        images
+            .iter()
+            .map(|name| (name.as_str(), format!("@{name}"))),
+    );
+
+    let mut text = typed.join("\n\n");
+    for (name, reference) in references {
+        text = text.replacen(&format!("`{name}`"), &reference, 1);
+    }
+    text
+}
+
+/// Complete the path of the `@path` reference being typed.
+///
+/// Returns the text that should be inserted after `partial` and all of the candidate paths.
+#[must_use]
+pub fn complete(partial: &str) -> (String, Vec<String>) {
+    let (dir, file) = partial
+        .rfind('/')
+        .map_or(("", partial), |i| (&partial[..=i], &partial[i + 1..]));
+    let Ok(entries) = fs::read_dir(if dir.is_empty() { "." } else { dir }) else {
+        return (String::new(), vec![]);
+    };
+
+    let mut candidates: Vec<String> = entries
+        .filter_map(Result::ok)
+        .filter_map(|entry| {
+            let name = entry.file_name().into_string().ok()?;
+            if !name.starts_with(file) || (name.starts_with('.') && !file.starts_with('.')) {
+                return None;
+            }
+            let is_dir = entry.file_type().is_ok_and(|t| t.is_dir());
+            Some(format!("{dir}{name}{}", if is_dir { "/" } else { "" }))
+        })
+        .collect();
+    candidates.sort();
+
+    let Some(first) = candidates.first() else {
+        return (String::new(), candidates);
+    };
+    let mut common = first.clone();
+    for candidate in &candidates[1..] {
+        while !candidate.starts_with(&common) {
+            common.pop();
+        }
+    }
+
+    (
+        common[partial.len().min(common.len())..].to_string(),
+        candidates,
+    )
+}
+
+/// The MIME type of a file if it's an image the API accepts.
+fn image_mime(path: &str) -> Option<&'static str> {
+    let extension = Path::new(path).extension()?.to_str()?.to_lowercase();
+    match extension.as_str() {
+        "png" => Some("image/png"),
+        "jpg" | "jpeg" => Some("image/jpeg"),
+        "gif" => Some("image/gif"),
+        "webp" => Some("image/webp"),
+        _ => None,
+    }
+}
+
+/// Find every `@reference` that starts a word.
+fn references(text: &str) -> Vec<(usize, &str)> {
+    let mut found = vec![];
+    let mut previous = None;
+    for (i, c) in text.char_indices() {
+        if c == '@' && previous.is_none_or(char::is_whitespace) {
+            let len = text[i..]
+                .find(char::is_whitespace)
+                .unwrap_or(text.len() - i);
+            if len > 1 {
+                found.push((i, &text[i..i + len]));
+            }
+        }
+        previous = Some(c);
+    }
+    found
+}
+
+/// The byte ranges of every `!{command}`, braces included. Braces inside the command have to be
+/// balanced. Like references the marker has to start a word, so macros like `json!{..}` are left
+/// alone.
+fn commands(text: &str) -> Vec<(usize, usize)> {
+    let mut found = vec![];
+    let mut rest = 0;
+    while let Some(start) = text[rest..].find("!{").map(|i| i + rest) {
+        if !text[..start]
+            .chars()
+            .next_back()
+            .is_none_or(char::is_whitespace)
+        {
+            rest = start + 2;
+            continue;
+        }
+        let mut depth = 0;
+        let end = text[start + 1..].char_indices().find_map(|(i, c)| {
+            match c {
+                '{' => depth += 1,
+                '}' => depth -= 1,
+                _ => {}
+            }
+            (depth == 0).then_some(start + 1 + i + 1)
+        });
+        let Some(end) = end else {
+            break;
+        };
+        if !text[start + 2..end - 1].trim().is_empty() {
+            found.push((start, end));
+        }
+        rest = end;
+    }
+    found
+}
+
+/// Where the next attached block starts and which header it has.
+fn find_header(text: &str) -> Option<(usize, &'static str)> {
+    [HEADER, OUTPUT_HEADER]
+        .into_iter()
+        .filter_map(|header| {
+            if text.starts_with(header) {
+                return Some((0, header));
+            }
+            text.find(&format!("\n{header}")).map(|i| (i + 1, header))
+        })
+        .min_by_key(|(i, _)| *i)
+}
+
+fn find_closing(body: &str, closing: &str) -> Option<usize> {
+    body.match_indices(closing)
+        .map(|(i, _)| i)
+        .find(|i| body[i + closing.len()..].starts_with('\n') || i + closing.len() == body.len())
+}
+
+/// Wrap the contents of a file in a fence long enough that the contents can't close it.
+fn fence(header: &str, name: &str, language: &str, contents: &str) -> String {
+    let longest = contents
+        .split(|c| c != '`')
+        .map(str::len)
+        .max()
+        .unwrap_or(0);
+    let fence = "`".repeat((longest + 1).max(3));
+    let contents = contents.strip_suffix('\n').unwrap_or(contents);
+
+    format!("{header}{name}\n{fence}{language}\n{contents}\n{fence}")
+}
+
+/// The Markdown language tag for a file based on its extension.
+fn language(path: &str) -> &str {
+    let extension = Path::new(path)
+        .extension()
+        .and_then(|ext| ext.to_str())
+        .unwrap_or("");
+    match extension {
+        "rs" => "rust",
+        "py" => "python",
+        "js" | "mjs" | "cjs" => "javascript",
+        "ts" => "typescript",
+        "tsx" => "tsx",
+        "jsx" => "jsx",
+        "go" => "go",
+        "c" | "h" => "c",
+        "cc" | "cpp" | "cxx" | "hpp" => "cpp",
+        "java" => "java",
+        "rb" => "ruby",
+        "sh" | "bash" | "zsh" => "bash",
+        "md" => "markdown",
+        "json" => "json",
+        "toml" => "toml",
+        "yaml" | "yml" => "yaml",
+        "html" => "html",
+        "css" => "css",
+        "sql" => "sql",
+        "lua" => "lua",
+        "nix" => "nix",
+        _ => extension,
+    }
+}
+
+/// Roughly how many tokens the file at `path` of `size` bytes costs, going by its start so a huge
+/// file isn't read just to be turned down.
+fn estimate_file_tokens(path: &str, size: u64, model: &str) -> AppResult<usize> {
+    let mut sample = vec![];
+    File::open(path)?
+        .take(SAMPLE_SIZE)
+        .read_to_end(&mut sample)?;
+    if sample.is_empty() {
+        return Ok(0);
+    }
+    let tokens = estimate_tokens(&String::from_utf8_lossy(&sample), model) as u64;
+    Ok(usize::try_from(tokens * size / sample.len() as u64).unwrap_or(usize::MAX))
+}
+
+/// Roughly how many tokens a piece of text costs as a user message.
+fn estimate_tokens(text: &str, model: &str) -> usize {
+    let mut history = History::default();
+    history.push(Prompt {
+        role: Role::User,
+        content: text.to_string(),
+    });
+    history.num_tokens(model)
+}
@@ -1,8 +1,11 @@
 use async_openai::types::ChatCompletionRequestSystemMessageArgs;
 use async_openai::types::{
     ChatCompletionRequestAssistantMessageArgs, ChatCompletionRequestMessage,
-    ChatCompletionRequestUserMessageArgs,
+    ChatCompletionRequestMessageContentPart, ChatCompletionRequestMessageContentPartImageArgs,
+    ChatCompletionRequestMessageContentPartTextArgs, ChatCompletionRequestToolMessageArgs,
+    ChatCompletionRequestUserMessage, ChatCompletionRequestUserMessageArgs,
     ChatCompletionRequestUserMessageContent::{Array, Text},
+    ImageUrlArgs,
 };
 use ratatui::{
     style::{Color, Modifier, Style},
@@ -11,59 +14,599 @@ use ratatui::{
 use textwrap::wrap;
 use tiktoken_rs::ChatCompletionRequestMessage as TokenChatCompletionRequestMessage;
 
+use crate::app::AppResult;
+use crate::attach::{self, Image, Segment, IMAGE_HEADER};
 use crate::config::{Prompt, Role};
+use crate::mcp::SEPARATOR;
+use crate::tool::ToolCall;
+use serde::de::Error as _;
+use serde::{Deserialize, Deserializer, Serialize};
+use serde_json::Value;
+use std::collections::{BTreeMap, BTreeSet, HashSet};
+use std::fs;
+use std::ops::Range;
+use std::path::Path;
+use std::time::Duration;
 
-#[derive(Default, Clone, Debug)]
+/// Roughly what an image costs. The real cost depends on its size and detail level.
+pub const IMAGE_TOKENS: usize = 765;
+
+/// How an answer was generated.
+#[derive(Default, Clone, Debug, Serialize, Deserialize)]
+pub struct Meta {
+    pub model: String,
+    /// How many tokens were streamed
+    pub tokens: usize,
+    /// How long the first text took to arrive
+    pub latency: Duration,
+    /// How long the whole answer took
+    pub duration: Duration,
+}
+
+/// When messages and the code blocks in answers are folded.
+#[derive(Clone, Copy, Debug)]
+pub struct Folding {
+    /// How many lines of a folded message or code block are shown
+    pub lines: usize,
+    /// User messages longer than this many lines start folded
+    pub user: Option<usize>,
+    /// Code blocks longer than this many lines start folded
+    pub code: Option<usize>,
+}
+
+impl Default for Folding {
+    fn default() -> Self {
+        Self {
+            lines: 8,
+            user: Some(30),
+            code: Some(40),
+        }
+    }
+}
+
+#[derive(Default, Clone, Debug, Serialize, Deserialize)]
 pub struct History {
+    #[serde(deserialize_with = "deserialize_messages")]
     pub history: Vec<ChatCompletionRequestMessage>,
+    #[serde(skip)]
     pub current_response: String,
+    #[serde(skip)]
     pub text_width: u16,
+    #[serde(skip)]
     pub text_lines: usize,
+    #[serde(default)]
     pub tokens: usize,
+    /// The names of the tools that come from MCP servers
+    #[serde(skip)]
+    pub remote_tools: HashSet<String>,
+    /// The files each answer drew on, by the index of the answer in the history
+    #[serde(default)]
+    pub sources: BTreeMap<usize, Vec<String>>,
+    /// How each answer was generated, by the index of the answer in the history
+    #[serde(default)]
+    pub meta: BTreeMap<usize, Meta>,
+    /// The messages the user pinned
+    #[serde(default)]
+    pub pinned: BTreeSet<usize>,
+    /// When messages and code blocks are folded
+    #[serde(skip)]
+    pub folding: Folding,
+    /// Whether the user folded or unfolded each message they toggled, which wins over its length
+    #[serde(skip)]
+    pub folds: BTreeMap<usize, bool>,
+    /// Whether the user folded or unfolded the code blocks of each answer they toggled
+    #[serde(skip)]
+    pub code_folds: BTreeMap<usize, bool>,
+    /// The line each laid out message starts on
+    #[serde(skip)]
+    pub message_lines: Vec<usize>,
+    /// The rendered lines of each message laid out so far, with the version they were rendered
+    /// as
+    #[serde(skip)]
+    rendered: Vec<(u64, Vec<Line<'static>>)>,
+    /// How many times a message was rendered, which numbers the versions
+    #[serde(skip)]
+    renders: u64,
+    /// The laid out messages that changed and have to be rendered again
+    #[serde(skip)]
+    stale: BTreeSet<usize>,
+    /// The width the cached lines were wrapped to
+    #[serde(skip)]
+    rendered_width: u16,
+    #[serde(skip)]
+    revision: u64,
+}
+
+/// The messages are untagged so serde would read every text message as a system message. Use the
+/// role to pick the right one instead.
+fn deserialize_messages<'de, D>(
+    deserializer: D,
+) -> Result<Vec<ChatCompletionRequestMessage>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    let values = Vec::<Value>::deserialize(deserializer)?;
+    values
+        .into_iter()
+        .map(|value| {
+            let message = match value.get("role").and_then(Value::as_str) {
+                Some("system") => {
+                    serde_json::from_value(value).map(ChatCompletionRequestMessage::System)
+                }
+                Some("user") => {
+                    serde_json::from_value(value).map(ChatCompletionRequestMessage::User)
+                }
+                Some("assistant") => {
+                    serde_json::from_value(value).map(ChatCompletionRequestMessage::Assistant)
+                }
+                Some("tool") => {
+                    serde_json::from_value(value).map(ChatCompletionRequestMessage::Tool)
+                }
+                Some("function") => {
+                    serde_json::from_value(value).map(ChatCompletionRequestMessage::Function)
+                }
+                role => return Err(D::Error::custom(format!("unknown message role {role:?}"))),
+            };
+            message.map_err(D::Error::custom)
+        })
+        .collect()
 }
 
 impl History {
-    /// # Panics
+    /// Wrap the conversation to `text_width` and work out the line each message starts on.
     ///
-    /// Will panic if the response somehow contains something other than text
-    pub fn render_history(&mut self) -> Vec<Line> {
+    /// Each message's lines are cached, so only messages that were added or changed since the
+    /// last call are wrapped again. Changing `text_width` wraps everything again.
+    pub fn layout(&mut self) {
+        if self.rendered_width != self.text_width {
+            self.rendered.clear();
+            self.stale.clear();
+            self.rendered_width = self.text_width;
+        }
+        // Removing messages takes them out of `rendered` but leaves `message_lines` as it was
+        let laid_out = self.message_lines.len() == self.rendered.len();
+        if laid_out && self.rendered.len() == self.history.len() && self.stale.is_empty() {
+            return;
+        }
+
+        self.rendered.truncate(self.history.len());
+        for index in std::mem::take(&mut self.stale) {
+            if index < self.rendered.len() {
+                self.renders += 1;
+                self.rendered[index] = (self.renders, self.render_message(index));
+            }
+        }
+        while self.rendered.len() < self.history.len() {
+            self.renders += 1;
+            let lines = self.render_message(self.rendered.len());
+            self.rendered.push((self.renders, lines));
+        }
+
+        self.message_lines.clear();
+        self.text_lines = 0;
+        for (_, lines) in &self.rendered {
+            self.message_lines.push(self.text_lines);
+            self.text_lines += lines.len();
+        }
+        self.revision += 1;
+    }
+
+    /// Up to `count` laid out lines starting from line `start`.
+    pub fn lines(&self, start: usize, count: usize) -> impl Iterator<Item = &Line<'static>> {
+        let message = self.message_at(start).unwrap_or(0);
+        let skip = start
+            - self
+                .message_lines
+                .get(message)
+                .copied()
+                .unwrap_or(0)
+                .min(start);
+        self.rendered
+            .get(message..)
+            .unwrap_or_default()
+            .iter()
+            .flat_map(|(_, lines)| lines)
+            .skip(skip)
+            .take(count)
+    }
+
+    /// The message laid out line `line` belongs to.
+    #[must_use]
+    pub fn message_at(&self, line: usize) -> Option<usize> {
+        if line >= self.text_lines {
+            return None;
+        }
+        self.message_lines
+            .partition_point(|&start| start <= line)
+            .checked_sub(1)
+    }
+
+    /// The laid out lines of the message at `index`, empty if it isn't laid out.
+    #[must_use]
+    pub fn message_range(&self, index: usize) -> Range<usize> {
+        let Some(&start) = self.message_lines.get(index) else {
+            return 0..0;
+        };
+        let end = self
+            .message_lines
+            .get(index + 1)
+            .copied()
+            .unwrap_or(self.text_lines);
+        start..end
+    }
+
+    /// Whether the message at `index` shows up in the chat. System prompts don't.
+    #[must_use]
+    pub fn is_shown(&self, index: usize) -> bool {
+        matches!(
+            self.history.get(index),
+            Some(
+                ChatCompletionRequestMessage::User(_)
+                    | ChatCompletionRequestMessage::Assistant(_)
+                    | ChatCompletionRequestMessage::Tool(_)
+            )
+        )
+    }
+
+    /// The messages that go with the one at `index`: a tool call and its outputs stay together,
+    /// since the API won't take one without the other.
+    #[must_use]
+    pub fn group(&self, index: usize) -> Range<usize> {
+        let is_tool = |i: usize| matches!(self.history[i], ChatCompletionRequestMessage::Tool(_));
+        let mut start = index;
+        while start > 0 && is_tool(start) {
+            start -= 1;
+        }
+        let calls = match &self.history[start] {
+            ChatCompletionRequestMessage::Assistant(message) => message.tool_calls.is_some(),
+            _ => false,
+        };
+        if !calls {
+            return index..index + 1;
+        }
+        let mut end = start + 1;
+        while end < self.history.len() && is_tool(end) {
+            end += 1;
+        }
+        start..end
+    }
+
+    /// Remove the messages in `range`, along with what's known about them.
+    pub fn remove(&mut self, range: Range<usize>) {
+        let removed = range.len();
+        self.renumber(|i| {
+            if i < range.start {
+                Some(i)
+            } else if i >= range.end {
+                Some(i - removed)
+            } else {
+                None
+            }
+        });
+        let cached = range.start.min(self.rendered.len())..range.end.min(self.rendered.len());
+        self.rendered.drain(cached);
+        self.history.drain(range.clone());
+        self.invalidate_tool_outputs(range.start);
+    }
+
+    /// Keep the first `len` messages.
+    pub fn truncate(&mut self, len: usize) {
+        self.remove(len..self.history.len().max(len));
+    }
+
+    /// Move what's known about each message to the index `index` gives it, forgetting the ones
+    /// it gives none.
+    fn renumber(&mut self, index: impl Fn(usize) -> Option<usize>) {
+        fn moved<T>(map: &mut BTreeMap<usize, T>, index: &impl Fn(usize) -> Option<usize>) {
+            *map = std::mem::take(map)
+                .into_iter()
+                .filter_map(|(i, value)| Some((index(i)?, value)))
+                .collect();
+        }
+        moved(&mut self.sources, &index);
+        moved(&mut self.meta, &index);
+        self.pinned = self.pinned.iter().filter_map(|&i| index(i)).collect();
+        moved(&mut self.folds, &index);
+        moved(&mut self.code_folds, &index);
+        self.stale = self.stale.iter().filter_map(|&i| index(i)).collect();
+    }
+
+    /// Pin the message at `index`, or unpin it if it's pinned.
+    pub fn toggle_pin(&mut self, index: usize) {
+        if !self.pinned.remove(&index) {
+            self.pinned.insert(index);
+        }
+    }
+
+    /// Fold the message at `index` down to its first lines, or unfold it if it's folded.
+    pub fn toggle_fold(&mut self, index: usize) {
+        self.folds.insert(index, !self.is_folded(index));
+        self.invalidate(index);
+    }
+
+    /// Fold the code blocks in the answer at `index`, or unfold them if they're folded.
+    pub fn toggle_code_fold(&mut self, index: usize) {
+        self.code_folds.insert(index, !self.is_code_folded(index));
+        self.invalidate(index);
+    }
+
+    /// Whether the message at `index` is folded. Unless the user says otherwise, user messages
+    /// are when they're long. That counts the lines as typed, so resizing doesn't change it.
+    #[must_use]
+    pub fn is_folded(&self, index: usize) -> bool {
+        if let Some(&folded) = self.folds.get(&index) {
+            return folded;
+        }
+        let Some(ChatCompletionRequestMessage::User(message)) = self.history.get(index) else {
+            return false;
+        };
+        let (text, images) = Self::user_content(message);
+        let lines: usize = attach::segments(&text)
+            .into_iter()
+            .map(|segment| match segment {
+                Segment::Text(text) => text.lines().count(),
+                Segment::File { .. } | Segment::Output { .. } => 1,
+            })
+            .sum();
+        self.folding
+            .user
+            .is_some_and(|max| lines + images.len() > max)
+    }
+
+    /// Whether the long code blocks in the answer at `index` are folded.
+    #[must_use]
+    pub fn is_code_folded(&self, index: usize) -> bool {
+        self.code_folds
+            .get(&index)
+            .copied()
+            .unwrap_or_else(|| self.folding.code.is_some())
+    }
+
+    /// The first lines of `lines` and a marker saying how many more there are.
+    fn fold(mut lines: Vec<Line<'static>>, keep: usize) -> Vec<Line<'static>> {
+        if lines.len() > keep {
+            let hidden = lines.len() - keep;
+            lines.truncate(keep);
+            lines.push(Line::styled(
+                format!("… {hidden} more lines"),
+                Style::new().fg(Color::DarkGray),
+            ));
+        }
+        lines
+    }
+
+    /// Every laid out line of the conversation.
+    pub fn all_lines(&self) -> impl Iterator<Item = &Line<'static>> {
+        self.rendered.iter().flat_map(|(_, lines)| lines)
+    }
+
+    /// The laid out lines of each message with their version, which changes whenever they do.
+    pub fn rendered_messages(&self) -> impl Iterator<Item = (u64, &[Line<'static>])> {
+        self.rendered
+            .iter()
+            .map(|(version, lines)| (*version, lines.as_slice()))
+    }
+
+    /// Goes up every time the laid out lines change.
+    #[must_use]
+    pub const fn revision(&self) -> u64 {
+        self.revision
+    }
+
+    /// Render the message at `index` again the next time the conversation is laid out.
+    fn invalidate(&mut self, index: usize) {
+        if index < self.rendered.len() {
+            self.stale.insert(index);
+        }
+    }
+
+    /// Render the tool outputs from `index` on again. Their labels name the call they answer,
+    /// which is found in the messages before them.
+    fn invalidate_tool_outputs(&mut self, index: usize) {
+        for i in index..self.rendered.len().min(self.history.len()) {
+            if matches!(self.history[i], ChatCompletionRequestMessage::Tool(_)) {
+                self.stale.insert(i);
+            }
+        }
+    }
+
+    /// The lines of the message at `index`.
+    fn render_message(&self, index: usize) -> Vec<Line<'static>> {
         let mut message_text = vec![];
-        for message in &self.history {
-            match message {
-                ChatCompletionRequestMessage::User(message) => {
+        match &self.history[index] {
+            ChatCompletionRequestMessage::User(message) => {
+                message_text.extend(self.user_lines(message));
+            }
+            ChatCompletionRequestMessage::Assistant(message) => {
+                let tool_calls = message.tool_calls.as_deref().unwrap_or_default();
+                if message.content.is_some() || tool_calls.is_empty() {
                     let text = message
                         .content
-                        .as_ref()
-                        .map_or_else(String::new, |content| match content {
-                            Text(text) => text.clone(),
-                            Array(_) => panic!("GPTrs only supports text."),
-                        });
-                    let wrapped = wrap(&text, self.text_width as usize);
+                        .clone()
+                        .unwrap_or_else(|| "No content".to_string());
+                    message_text.extend(self.answer_lines(index, &text));
+                }
+                for call in tool_calls {
+                    let name = call.function.name.as_str();
+                    let (chip, style) = if self.remote_tools.contains(name) {
+                        let name = name.replacen(SEPARATOR, " › ", 1);
+                        let chip = format!("[mcp: {name}({})]", call.function.arguments);
+                        (chip, Self::remote_style())
+                    } else {
+                        let chip = format!("[tool: {name}({})]", call.function.arguments);
+                        (chip, Self::tool_style())
+                    };
+                    let wrapped = wrap(&chip, self.text_width as usize);
+                    for line in wrapped {
+                        message_text.push(Line::styled(line.to_string(), style));
+                    }
+                }
+                message_text.extend(self.source_lines(index));
+            }
+            ChatCompletionRequestMessage::Tool(message) => {
+                let lines = message
+                    .content
+                    .as_deref()
+                    .unwrap_or_default()
+                    .lines()
+                    .count();
+                let line = match self.tool_name(index, &message.tool_call_id) {
+                    Some(name) if self.remote_tools.contains(name) => {
+                        let name = name.replacen(SEPARATOR, " › ", 1);
+                        Line::styled(
+                            format!("[mcp: {name} result · {lines} lines]"),
+                            Self::remote_style(),
+                        )
+                    }
+                    Some(name) => Line::styled(
+                        format!("[{name} output · {lines} lines]"),
+                        Self::tool_style(),
+                    ),
+                    None => {
+                        Line::styled(format!("[tool output · {lines} lines]"), Self::tool_style())
+                    }
+                };
+                message_text.push(line);
+            }
+            _ => {}
+        }
+        if self.is_folded(index) {
+            message_text = Self::fold(message_text, self.folding.lines);
+        }
+        message_text
+    }
+
+    /// The text of the answer at `index`, with its long code blocks folded.
+    fn answer_lines(&self, index: usize, text: &str) -> Vec<Line<'static>> {
+        // Without a threshold, code blocks the user folds are the ones longer than a fold keeps
+        let fold_code = self.is_code_folded(index);
+        let max = self.folding.code.unwrap_or(self.folding.lines);
+
+        let mut lines = vec![];
+        let mut block: Option<Vec<&str>> = None;
+        for line in text.split('\n') {
+            let fence = line.trim_start().starts_with("```");
+            match (block.as_mut(), fence) {
+                (None, true) => {
+                    lines.extend(self.answer_text(line));
+                    block = Some(vec![]);
+                }
+                (None, false) => lines.extend(self.answer_text(line)),
+                (Some(code), false) => code.push(line),
+                (Some(_), true) => {
+                    let code = block.take().unwrap_or_default();
+                    lines.extend(self.code_lines(&code, fold_code && code.len() > max));
+                    lines.extend(self.answer_text(line));
+                }
+            }
+        }
+        // The closing fence of a block that's still streaming hasn't arrived yet
+        if let Some(code) = block {
+            lines.extend(self.code_lines(&code, fold_code && code.len() > max));
+        }
+        lines
+    }
+
+    /// The lines of a code block, with only its first lines if it's `folded`.
+    fn code_lines(&self, code: &[&str], folded: bool) -> Vec<Line<'static>> {
+        if !folded {
+            return code
+                .iter()
+                .flat_map(|line| self.answer_text(line))
+                .collect();
+        }
+        let shown = self.folding.lines.min(code.len());
+        let mut lines: Vec<_> = code[..shown]
+            .iter()
+            .flat_map(|line| self.answer_text(line))
+            .collect();
+        lines.push(Line::styled(
+            format!("… {} more lines", code.len() - shown),
+            Style::new().fg(Color::DarkGray),
+        ));
+        lines
+    }
+
+    /// A line of an answer wrapped to the width of the chat.
+    fn answer_text(&self, text: &str) -> Vec<Line<'static>> {
+        wrap(text, self.text_width as usize)
+            .into_iter()
+            .map(|line| Line::styled(line.to_string(), Style::new().bg(Color::Red)))
+            .collect()
+    }
+
+    /// The name of the tool called with `id` before the message at `index`.
+    fn tool_name(&self, index: usize, id: &str) -> Option<&str> {
+        self.history[..index]
+            .iter()
+            .rev()
+            .find_map(|message| match message {
+                ChatCompletionRequestMessage::Assistant(message) => message
+                    .tool_calls
+                    .iter()
+                    .flatten()
+                    .find(|call| call.id == id)
+                    .map(|call| call.function.name.as_str()),
+                _ => None,
+            })
+    }
+
+    /// A user message with its attachments shown as chips.
+    fn user_lines(&self, message: &ChatCompletionRequestUserMessage) -> Vec<Line<'static>> {
+        let mut rendered = vec![];
+        let (text, images) = Self::user_content(message);
+        for segment in attach::segments(&text) {
+            match segment {
+                Segment::Text(text) => {
+                    let wrapped = wrap(text, self.text_width as usize);
                     for line in wrapped {
-                        message_text.push(Line::styled(
+                        rendered.push(Line::styled(
                             line.to_string(),
                             Style::new().bg(Color::Blue).add_modifier(Modifier::BOLD),
                         ));
                     }
                 }
-                ChatCompletionRequestMessage::Assistant(message) => {
-                    let text = message
-                        .content
-                        .clone()
-                        .unwrap_or_else(|| "No content".to_string());
-                    let wrapped = wrap(&text, self.text_width as usize);
-                    for line in wrapped {
-                        message_text
-                            .push(Line::styled(line.to_string(), Style::new().bg(Color::Red)));
-                    }
+                Segment::File { path, lines } => {
+                    rendered.push(Line::styled(
+                        format!("[@{path} · {lines} lines]"),
+                        Style::new().fg(Color::Black).bg(Color::Cyan),
+                    ));
+                }
+                Segment::Output { command, lines } => {
+                    rendered.push(Line::styled(
+                        format!("[!{command} · {lines} lines]"),
+                        Style::new().fg(Color::Black).bg(Color::Cyan),
+                    ));
                 }
-                _ => {}
             }
         }
+        for name in images {
+            rendered.push(Line::styled(
+                format!("[image: {name}]"),
+                Style::new().fg(Color::Black).bg(Color::Magenta),
+            ));
+        }
+        rendered
+    }
 
-        self.text_lines = message_text.len();
+    /// The files the answer at `index` drew on, if any.
+    fn source_lines(&self, index: usize) -> Vec<Line<'static>> {
+        let Some(sources) = self.sources.get(&index) else {
+            return vec![];
+        };
+        let sources = format!("Sources: {}", sources.join(", "));
+        wrap(&sources, self.text_width as usize)
+            .into_iter()
+            .map(|line| Line::styled(line.to_string(), Style::new().fg(Color::DarkGray)))
+            .collect()
+    }
 
-        message_text
+    const fn tool_style() -> Style {
+        Style::new().fg(Color::Black).bg(Color::Yellow)
+    }
+
+    const fn remote_style() -> Style {
+        Style::new().fg(Color::Black).bg(Color::Green)
     }
 
     fn prompt_to_message(prompt: Prompt) -> ChatCompletionRequestMessage {
@@ -97,6 +640,102 @@ impl History {
         self.history.push(Self::prompt_to_message(prompt));
     }
 
+    /// # Panics
+    ///
+    /// Will panic if the message parts cannot be created
+    pub fn push_user(&mut self, text: String, images: Vec<Image>) {
+        if images.is_empty() {
+            self.push(Prompt {
+                role: Role::User,
+                content: text,
+            });
+            return;
+        }
+
+        let mut parts = vec![ChatCompletionRequestMessageContentPart::Text(
+            ChatCompletionRequestMessageContentPartTextArgs::default()
+                .text(text)
+                .build()
+                .unwrap(),
+        )];
+        for image in images {
+            parts.push(ChatCompletionRequestMessageContentPart::Text(
+                ChatCompletionRequestMessageContentPartTextArgs::default()
+                    .text(format!("{IMAGE_HEADER}{}", image.name))
+                    .build()
+                    .unwrap(),
+            ));
+            parts.push(ChatCompletionRequestMessageContentPart::Image(
+                ChatCompletionRequestMessageContentPartImageArgs::default()
+                    .image_url(ImageUrlArgs::default().url(image.url).build().unwrap())
+                    .build()
+                    .unwrap(),
+            ));
+        }
+
+        self.history.push(ChatCompletionRequestMessage::User(
+            ChatCompletionRequestUserMessageArgs::default()
+                .content(parts)
+                .build()
+                .unwrap(),
+        ));
+    }
+
+    /// The contents of the system prompts in the order they're sent.
+    #[must_use]
+    pub fn system_prompts(&self) -> Vec<String> {
+        self.history
+            .iter()
+            .filter_map(|message| match message {
+                ChatCompletionRequestMessage::System(message) => {
+                    Some(message.content.clone().unwrap_or_default())
+                }
+                _ => None,
+            })
+            .collect()
+    }
+
+    /// Replace the system prompts with `prompts`, which go at the start of the conversation.
+    pub fn set_system_prompts(&mut self, prompts: Vec<String>) {
+        // Keep the sources and the rest with their messages as the messages move
+        let mut removed = 0;
+        let index: Vec<_> = self
+            .history
+            .iter()
+            .enumerate()
+            .map(|(i, message)| {
+                if matches!(message, ChatCompletionRequestMessage::System(_)) {
+                    removed += 1;
+                    None
+                } else {
+                    Some(i - removed + prompts.len())
+                }
+            })
+            .collect();
+        self.renumber(|i| index.get(i).copied().flatten());
+
+        // System prompts aren't shown, so the other messages keep their lines
+        let mut i = 0;
+        self.rendered.retain(|_| {
+            let system = matches!(self.history[i], ChatCompletionRequestMessage::System(_));
+            i += 1;
+            !system
+        });
+        let versions = self.renders + 1..=self.renders + prompts.len() as u64;
+        self.renders += prompts.len() as u64;
+        self.rendered
+            .splice(0..0, versions.map(|version| (version, vec![])));
+        self.history
+            .retain(|message| !matches!(message, ChatCompletionRequestMessage::System(_)));
+        let prompts = prompts.into_iter().map(|content| {
+            Self::prompt_to_message(Prompt {
+                role: Role::System,
+                content,
+            })
+        });
+        self.history.splice(0..0, prompts);
+    }
+
     pub fn extend(&mut self, prompts: Vec<Prompt>) {
         let messages = prompts.into_iter().map(Self::prompt_to_message);
         self.history.extend(messages);
@@ -109,6 +748,7 @@ impl History {
         self.current_response += &text;
         if !first {
             self.history.pop();
+            self.invalidate(self.history.len());
         }
         self.history.push(ChatCompletionRequestMessage::Assistant(
             ChatCompletionRequestAssistantMessageArgs::default()
@@ -118,6 +758,82 @@ impl History {
         ));
     }
 
+    /// Record the tools the model called, along with anything it said before calling them.
+    ///
+    /// # Panics
+    ///
+    /// Will panic if the ``ChatCompletionRequestAssistantMessageArgs`` cannot be created
+    pub fn push_tool_calls(&mut self, calls: &[ToolCall]) {
+        let mut message = ChatCompletionRequestAssistantMessageArgs::default();
+        message.tool_calls(calls.iter().map(ToolCall::to_openai).collect::<Vec<_>>());
+        if !self.current_response.is_empty() {
+            // Replace the streamed message with one that also has the calls
+            self.history.pop();
+            self.invalidate(self.history.len());
+            message.content(self.current_response.clone());
+        }
+        self.history.push(ChatCompletionRequestMessage::Assistant(
+            message.build().unwrap(),
+        ));
+    }
+
+    /// # Panics
+    ///
+    /// Will panic if the ``ChatCompletionRequestToolMessageArgs`` cannot be created
+    pub fn push_tool_output(&mut self, id: String, output: String) {
+        self.history.push(ChatCompletionRequestMessage::Tool(
+            ChatCompletionRequestToolMessageArgs::default()
+                .tool_call_id(id)
+                .content(output)
+                .build()
+                .unwrap(),
+        ));
+    }
+
+    /// Note the files the last answer drew on.
+    pub fn set_last_sources(&mut self, sources: Vec<String>) {
+        if sources.is_empty() || self.history.is_empty() {
+            return;
+        }
+        self.sources.insert(self.history.len() - 1, sources);
+        self.invalidate(self.history.len() - 1);
+    }
+
+    /// Note how the last answer was generated.
+    pub fn set_last_meta(&mut self, meta: Meta) {
+        if let Some(ChatCompletionRequestMessage::Assistant(_)) = self.history.last() {
+            self.meta.insert(self.history.len() - 1, meta);
+        }
+    }
+
+    /// The user message at `index` as it was typed, with its attachments turned back into
+    /// references.
+    #[must_use]
+    pub fn draft(&self, index: usize) -> Option<String> {
+        match self.history.get(index)? {
+            ChatCompletionRequestMessage::User(message) => {
+                let (text, images) = Self::user_content(message);
+                Some(attach::unexpand(&text, &images))
+            }
+            _ => None,
+        }
+    }
+
+    /// The position and text of the last message the user sent.
+    #[must_use]
+    pub fn last_user_message(&self) -> Option<(usize, String)> {
+        self.history
+            .iter()
+            .enumerate()
+            .rev()
+            .find_map(|(i, message)| match message {
+                ChatCompletionRequestMessage::User(message) => {
+                    Some((i, Self::user_content(message).0))
+                }
+                _ => None,
+            })
+    }
+
     pub fn is_empty(&mut self) -> bool {
         self.history.is_empty()
     }
@@ -127,11 +843,56 @@ impl History {
     }
 
     pub fn num_tokens(&mut self, model: &str) -> usize {
+        Self::count_tokens(&self.history, model)
+    }
+
+    /// Roughly how many tokens the message at `index` costs on its own.
+    #[must_use]
+    pub fn message_tokens(&self, index: usize, model: &str) -> usize {
+        Self::count_tokens(&self.history[index..=index], model)
+    }
+
+    fn count_tokens(history: &[ChatCompletionRequestMessage], model: &str) -> usize {
         let mut messages = vec![];
-        for message in &self.history {
+        let mut images = 0;
+        for message in history {
+            if let ChatCompletionRequestMessage::User(message) = message {
+                images += Self::user_content(message).1.len();
+            }
             messages.push(Self::message_to_token_message(message));
         }
-        tiktoken_rs::num_tokens_from_messages(model, &messages).unwrap()
+        // Models without a known tokenizer (e.g. local models) get the GPT-4 count as an estimate
+        tiktoken_rs::num_tokens_from_messages(model, &messages)
+            .or_else(|_| tiktoken_rs::num_tokens_from_messages("gpt-4", &messages))
+            .unwrap()
+            + images * IMAGE_TOKENS
+    }
+
+    /// The text of a user message and the names of the images attached to it.
+    fn user_content(message: &ChatCompletionRequestUserMessage) -> (String, Vec<String>) {
+        match &message.content {
+            None => (String::new(), vec![]),
+            Some(Text(text)) => (text.clone(), vec![]),
+            Some(Array(parts)) => {
+                let mut texts = vec![];
+                let mut images = vec![];
+                let mut name = None;
+                for part in parts {
+                    match part {
+                        ChatCompletionRequestMessageContentPart::Text(part) => {
+                            match part.text.strip_prefix(IMAGE_HEADER) {
+                                Some(image) => name = Some(image.to_string()),
+                                None => texts.push(part.text.as_str()),
+                            }
+                        }
+                        ChatCompletionRequestMessageContentPart::Image(_) => {
+                            images.push(name.take().unwrap_or_else(|| "image".to_string()));
+                        }
+                    }
+                }
+                (texts.join("\n\n"), images)
+            }
+        }
     }
 
     fn message_to_token_message(
@@ -139,13 +900,20 @@ impl History {
     ) -> TokenChatCompletionRequestMessage {
         match message {
             ChatCompletionRequestMessage::User(message) => {
-                let content = message
-                    .content
-                    .as_ref()
-                    .map_or_else(String::new, |content| match content {
-                        Text(text) => text.clone(),
-                        Array(_) => panic!("GPTrs only supports text."),
-                    });
+                let (content, _) = Self::user_content(message);
+                TokenChatCompletionRequestMessage {
+                    role: message.role.to_string(),
+                    content: Some(content),
+                    name: None,
+                    function_call: None,
+                }
+            }
+            ChatCompletionRequestMessage::Assistant(message) => {
+                let mut content = message.content.clone().unwrap_or_default();
+                for call in message.tool_calls.iter().flatten() {
+                    content.push_str(&call.function.name);
+                    content.push_str(&call.function.arguments);
+                }
                 TokenChatCompletionRequestMessage {
                     role: message.role.to_string(),
                     content: Some(content),
@@ -153,37 +921,63 @@ impl History {
                     function_call: None,
                 }
             }
-            ChatCompletionRequestMessage::Assistant(message) => TokenChatCompletionRequestMessage {
+            ChatCompletionRequestMessage::System(message) => TokenChatCompletionRequestMessage {
                 role: message.role.to_string(),
                 content: message.content.clone(),
                 name: None,
                 function_call: None,
             },
-            ChatCompletionRequestMessage::System(message) => TokenChatCompletionRequestMessage {
+            ChatCompletionRequestMessage::Tool(message) => TokenChatCompletionRequestMessage {
                 role: message.role.to_string(),
                 content: message.content.clone(),
                 name: None,
                 function_call: None,
             },
-            _ => panic!("Unsupported message type"),
+            ChatCompletionRequestMessage::Function(message) => TokenChatCompletionRequestMessage {
+                role: message.role.to_string(),
+                content: message.content.clone(),
+                name: Some(message.name.clone()),
+                function_call: None,
+            },
         }
     }
 
+    #[must_use]
     pub fn message_to_string(message: &ChatCompletionRequestMessage) -> String {
         match message {
             ChatCompletionRequestMessage::User(message) => {
-                message
-                    .content
-                    .as_ref()
-                    .map_or_else(String::new, |content| match content {
-                        Text(text) => text.clone(),
-                        Array(_) => panic!("GPTrs only supports text."),
-                    })
-            }
-            ChatCompletionRequestMessage::Assistant(message) => message
-                .content
-                .clone()
-                .unwrap_or_else(|| "No content".to_string()),
+                let (mut text, images) = Self::user_content(message);
+                for name in images {
+                    text.push_str("\n[image: ");
+                    text.push_str(&name);
+                    text.push(']');
+                }
+                text
+            }
+            ChatCompletionRequestMessage::Assistant(message) => {
+                let calls = message.tool_calls.as_deref().unwrap_or_default();
+                let mut text = message.content.clone().unwrap_or_else(|| {
+                    if calls.is_empty() {
+                        "No content".to_string()
+                    } else {
+                        String::new()
+                    }
+                });
+                for call in calls {
+                    if !text.is_empty() {
+                        text.push('\n');
+                    }
+                    text.push_str("[tool: ");
+                    text.push_str(&call.function.name);
+                    text.push('(');
+                    text.push_str(&call.function.arguments);
+                    text.push_str(")]");
+                }
+                text
+            }
+            ChatCompletionRequestMessage::Tool(message) => {
+                message.content.clone().unwrap_or_default()
+            }
             _ => String::new(),
         }
     }
@@ -191,4 +985,52 @@ impl History {
     pub fn last(&mut self) -> Option<&ChatCompletionRequestMessage> {
         self.history.last()
     }
+
+    /// Write the conversation to `path` as JSON.
+    pub fn save(&self, path: &Path) -> AppResult<()> {
+        fs::write(path, serde_json::to_string_pretty(self)?)?;
+        Ok(())
+    }
+
+    /// Read a conversation written by [`History::save`].
+    pub fn load(path: &Path) -> AppResult<Self> {
+        let text = fs::read_to_string(path)?;
+        Ok(serde_json::from_str(&text)?)
+    }
+
+    /// The conversation as a Markdown document with a heading for each message.
+    #[must_use]
+    pub fn to_markdown(&self) -> String {
+        let mut markdown = String::new();
+        for (i, message) in self.history.iter().enumerate() {
+            let (heading, text) = match message {
+                ChatCompletionRequestMessage::System(message) => {
+                    ("System", message.content.clone().unwrap_or_default())
+                }
+                ChatCompletionRequestMessage::User(_) => ("User", Self::message_to_string(message)),
+                ChatCompletionRequestMessage::Assistant(_) => {
+                    ("Assistant", Self::message_to_string(message))
+                }
+                ChatCompletionRequestMessage::Tool(_) => ("Tool", {
+                    let fence = "`".repeat(3);
+                    format!(
+                        "{fence}\n{}\n{fence}",
+                        Self::message_to_string(message).trim_end()
+                    )
+                }),
+                ChatCompletionRequestMessage::Function(_) => continue,
+            };
+            markdown.push_str("## ");
+            markdown.push_str(heading);
+            markdown.push_str("\n\n");
+            markdown.push_str(text.trim_end());
+            markdown.push_str("\n\n");
+            if let Some(sources) = self.sources.get(&i) {
+                markdown.push_str("*Sources: ");
+                markdown.push_str(&sources.join(", "));
+                markdown.push_str("*\n\n");
+            }
+        }
+        markdown
+    }
 }
@@ -0,0 +1,213 @@
+use std::fmt;
+use std::path::PathBuf;
+
+/// A command that can be typed into the input box.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Spec {
+    pub name: &'static str,
+    pub args: &'static str,
+    pub help: &'static str,
+}
+
+impl fmt::Display for Spec {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.args.is_empty() {
+            write!(f, "/{} - {}", self.name, self.help)
+        } else {
+            write!(f, "/{} {} - {}", self.name, self.args, self.help)
+        }
+    }
+}
+
+/// Every command in the order they're listed in the help.
+pub const COMMANDS: &[Spec] = &[
+    Spec {
+        name: "model",
+        args: "<model>",
+        help: "Switch the model used for new responses",
+    },
+    Spec {
+        name: "system",
+        args: "[prompt]",
+        help: "Replace the system prompt or open the editor",
+    },
+    Spec {
+        name: "temp",
+        args: "<0.0-2.0>",
+        help: "Set the sampling temperature",
+    },
+    Spec {
+        name: "persona",
+        args: "[name]",
+        help: "Switch persona or list them",
+    },
+    Spec {
+        name: "template",
+        args: "[name]",
+        help: "Insert a prompt template",
+    },
+    Spec {
+        name: "recall",
+        args: "[query]",
+        help: "Search saved conversations by meaning",
+    },
+    Spec {
+        name: "save",
+        args: "[name]",
+        help: "Save the conversation to the data directory",
+    },
+    Spec {
+        name: "export",
+        args: "<md|json> [path]",
+        help: "Export the conversation to a file",
+    },
+    Spec {
+        name: "clear",
+        args: "",
+        help: "Clear the conversation",
+    },
+    Spec {
+        name: "help",
+        args: "",
+        help: "Show the available commands",
+    },
+];
+
+/// Formats a conversation can be exported as.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Format {
+    Markdown,
+    Json,
+}
+
+impl Format {
+    #[must_use]
+    pub const fn extension(self) -> &'static str {
+        match self {
+            Self::Markdown => "md",
+            Self::Json => "json",
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Command {
+    Model(String),
+    System(String),
+    EditSystem,
+    Temp(f32),
+    Persona(Option<String>),
+    Template(Option<String>),
+    Recall(Option<String>),
+    Save(Option<String>),
+    Export(Format, Option<PathBuf>),
+    Clear,
+    Help,
+}
+
+/// Parse a line of input beginning with `/`.
+pub fn parse(input: &str) -> Result<Command, String> {
+    let input = input.strip_prefix('/').unwrap_or(input);
+    let (name, args) = input
+        .split_once(char::is_whitespace)
+        .map_or((input, ""), |(name, args)| (name, args.trim()));
+    let usage = || {
+        COMMANDS
+            .iter()
+            .find(|spec| spec.name == name)
+            .map_or_else(String::new, |spec| {
+                format!("Usage: /{} {}", spec.name, spec.args)
+            })
+    };
+
+    match name {
+        "model" if !args.is_empty() => Ok(Command::Model(args.to_string())),
+        "system" if args.is_empty() => Ok(Command::EditSystem),
+        "system" => Ok(Command::System(args.to_string())),
+        "temp" => match args.parse::<f32>() {
+            Ok(temp) if (0.0..=2.0).contains(&temp) => Ok(Command::Temp(temp)),
+            _ => Err(usage()),
+        },
+        "persona" => Ok(Command::Persona(
+            Some(args.to_string()).filter(|name| !name.is_empty()),
+        )),
+        "template" => Ok(Command::Template(
+            Some(args.to_string()).filter(|name| !name.is_empty()),
+        )),
+        "recall" => Ok(Command::Recall(
+            Some(args.to_string()).filter(|query| !query.is_empty()),
+        )),
+        // The name is a file in the data directory, so it can't lead out of it
+        "save" if args.contains(['/', '\\']) || args.contains("..") => Err(format!(
+            "{args} can't be used as a name. Leave out slashes and \"..\"."
+        )),
+        "save" => Ok(Command::Save(
+            Some(args.to_string()).filter(|name| !name.is_empty()),
+        )),
+        "export" => {
+            let (format, path) = args
+                .split_once(char::is_whitespace)
+                .map_or((args, ""), |(format, path)| (format, path.trim()));
+            let format = match format {
+                "md" | "markdown" => Format::Markdown,
+                "json" => Format::Json,
+                _ => return Err(usage()),
+            };
+            Ok(Command::Export(
+                format,
+                Some(PathBuf::from(path)).filter(|_| !path.is_empty()),
+            ))
+        }
+        "clear" => Ok(Command::Clear),
+        "help" => Ok(Command::Help),
+        "model" => Err(usage()),
+        _ => Err(format!("Unknown command /{name}. Try /help.")),
+    }
+}
+
+/// Complete the name of the command being typed.
+///
+/// Returns the text that should be inserted after `partial` and all of the matching commands.
+#[must_use]
+pub fn complete(partial: &str) -> (String, Vec<&'static Spec>) {
+    let partial = partial.strip_prefix('/').unwrap_or(partial);
+    let candidates: Vec<&Spec> = COMMANDS
+        .iter()
+        .filter(|spec| spec.name.starts_with(partial))
+        .collect();
+
+    match candidates.as_slice() {
+        [] => (String::new(), candidates),
+        [spec] => (format!("{} ", &spec.name[partial.len()..]), candidates),
+        [first, rest @ ..] => {
+            let mut common = first.name;
+            for spec in rest {
+                while !spec.name.starts_with(common) {
+                    common = &common[..common.len() - 1];
+                }
+            }
+            (common[partial.len()..].to_string(), candidates)
+        }
+    }
+}
+
+/// The usage of the command that's been typed so far, if it's a known command.
+#[must_use]
+pub fn hint(input: &str) -> Option<String> {
+    let input = input.strip_prefix('/')?;
+    let name = input.split(char::is_whitespace).next()?;
+    COMMANDS
+        .iter()
+        .find(|spec| spec.name == name)
+        .map(ToString::to_string)
+}
+
+/// The text shown by `/help`.
+#[must_use]
+pub fn help() -> String {
+    COMMANDS
+        .iter()
+        .map(ToString::to_string)
+        .collect::<Vec<_>>()
+        .join("\n")
+}
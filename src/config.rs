@@ -1,10 +1,18 @@
+use crate::action::KeyBinding;
+use crate::app::AppResult;
+use crate::chat::Folding;
+use crate::mcp::Server;
+use crate::shell::Shell;
+use crate::tool::Tool;
 use clap::ArgAction;
 use clap::Parser;
 use core::panic;
 use serde::{Deserialize, Serialize};
 use serde_json::Result;
-use std::fs::read_to_string;
-use std::path::PathBuf;
+use serde_json::Value;
+use std::collections::BTreeMap;
+use std::fs::{read_to_string, write};
+use std::path::{Path, PathBuf};
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 #[serde(rename_all = "lowercase")]
@@ -20,6 +28,19 @@ pub struct Prompt {
     pub content: String,
 }
 
+/// A named preset of system prompt, model and generation parameters.
+///
+/// Anything a persona leaves out is taken from the rest of the config.
+#[derive(Clone, Default, Deserialize, Serialize, Debug)]
+pub struct Persona {
+    #[serde(default)]
+    pub prompt: Vec<Prompt>,
+    pub model: Option<String>,
+    pub temperature: Option<f32>,
+    pub top_p: Option<f32>,
+    pub max_tokens: Option<u16>,
+}
+
 #[derive(Deserialize, Serialize, Parser, Debug)]
 #[clap(
     author,
@@ -45,8 +66,75 @@ struct Config {
     offline: Option<bool>,
     #[clap(short, long, action = ArgAction::SetTrue, help = "Use Vim keybindings for text input.")]
     vim: Option<bool>,
+    #[clap(long, action = ArgAction::SetTrue, help = "Use Emacs keybindings for text input.")]
+    emacs: Option<bool>,
+    #[arg(
+        long,
+        help = "Key to press before the app's shortcuts, like C-c. Defaults to C-c with --emacs."
+    )]
+    leader: Option<String>,
     #[clap(short, long, help = "The base URL for the OpenAI API.")]
     api_base: Option<String>,
+    #[arg(long, help = "Maximum size in bytes of a file attached with @path.")]
+    attachment_limit: Option<usize>,
+    #[arg(long, help = "Sampling temperature between 0 and 2.")]
+    temperature: Option<f32>,
+    // Only taken from the command line, or every launch would open the template
+    #[arg(
+        short,
+        long,
+        help = "Start with a prompt template from the templates directory."
+    )]
+    #[serde(skip)]
+    template: Option<String>,
+    #[arg(long, help = "Start as one of the personas in the config file.")]
+    persona: Option<String>,
+    #[arg(
+        long,
+        help = "Answer using the text files in this directory, retrieved from a local index."
+    )]
+    context_dir: Option<PathBuf>,
+    #[arg(
+        long,
+        help = "Model used to embed saved conversations for searching them."
+    )]
+    embedding_model: Option<String>,
+    #[arg(
+        long,
+        help = "Base URL of the embeddings API. Defaults to the API base URL."
+    )]
+    embedding_base: Option<String>,
+    #[arg(long, help = "Most times a second the screen is redrawn.")]
+    fps: Option<u16>,
+    #[arg(
+        long,
+        help = "How many lines of a folded message or code block are shown."
+    )]
+    fold_lines: Option<usize>,
+    #[arg(
+        long,
+        help = "Fold your messages longer than this many lines. 0 never folds them."
+    )]
+    fold_user: Option<usize>,
+    #[arg(
+        long,
+        help = "Fold code blocks in answers longer than this many lines. 0 never folds them."
+    )]
+    fold_code: Option<usize>,
+    #[arg(skip)]
+    archive: Option<bool>,
+    #[arg(skip)]
+    personas: Option<BTreeMap<String, Persona>>,
+    #[arg(skip)]
+    tools: Option<Vec<Tool>>,
+    #[arg(skip)]
+    mcp_servers: Option<BTreeMap<String, Server>>,
+    #[arg(skip)]
+    shell: Option<Shell>,
+    // What was given on the command line, which wins over the persona
+    #[arg(skip)]
+    #[serde(skip)]
+    overrides: Persona,
 }
 
 #[allow(clippy::unnecessary_wraps)]
@@ -55,10 +143,26 @@ fn parse_system_prompt(arg: &str) -> Result<Vec<Prompt>> {
     Ok(prompt)
 }
 
+/// While the other options will either have a value or be None the flags will either be True if
+/// set or False if not, so a flag that isn't set falls back to the config file.
+fn merge_flag(cli: Option<bool>, file: Option<bool>) -> Option<bool> {
+    if cli == Some(true) {
+        cli
+    } else {
+        file
+    }
+}
+
 impl Default for Config {
     fn default() -> Self {
         // Parse cli args
         let mut config_cli = Self::parse();
+        config_cli.overrides = Persona {
+            prompt: config_cli.prompt.clone().unwrap_or_default(),
+            model: config_cli.model.clone(),
+            temperature: config_cli.temperature,
+            ..Persona::default()
+        };
 
         let config_dir = dirs::config_dir().unwrap().join("gptrs");
         // If the CLI config file doesn't end in .json, assume it's in the config directory
@@ -109,23 +213,26 @@ impl Default for Config {
         config_cli.model = config_cli.model.or(config_file.model);
         config_cli.prompt = config_cli.prompt.or(config_file.prompt);
         config_cli.api_base = config_cli.api_base.or(config_file.api_base);
-        // While the above options will either have a value or be None the flags will
-        // either be True if set or False if not
-        config_cli.debug = if config_cli.debug.unwrap() {
-            config_cli.debug
-        } else {
-            config_file.debug
-        };
-        config_cli.offline = if config_cli.offline.unwrap() {
-            config_cli.offline
-        } else {
-            config_file.offline
-        };
-        config_cli.vim = if config_cli.vim.unwrap() {
-            config_cli.vim
-        } else {
-            config_file.vim
-        };
+        config_cli.attachment_limit = config_cli.attachment_limit.or(config_file.attachment_limit);
+        config_cli.temperature = config_cli.temperature.or(config_file.temperature);
+        config_cli.persona = config_cli.persona.or(config_file.persona);
+        config_cli.context_dir = config_cli.context_dir.or(config_file.context_dir);
+        config_cli.embedding_model = config_cli.embedding_model.or(config_file.embedding_model);
+        config_cli.embedding_base = config_cli.embedding_base.or(config_file.embedding_base);
+        config_cli.fps = config_cli.fps.or(config_file.fps);
+        config_cli.fold_lines = config_cli.fold_lines.or(config_file.fold_lines);
+        config_cli.fold_user = config_cli.fold_user.or(config_file.fold_user);
+        config_cli.fold_code = config_cli.fold_code.or(config_file.fold_code);
+        config_cli.archive = config_file.archive;
+        config_cli.personas = config_file.personas;
+        config_cli.tools = config_file.tools;
+        config_cli.mcp_servers = config_file.mcp_servers;
+        config_cli.shell = config_file.shell;
+        config_cli.debug = merge_flag(config_cli.debug, config_file.debug);
+        config_cli.offline = merge_flag(config_cli.offline, config_file.offline);
+        config_cli.vim = merge_flag(config_cli.vim, config_file.vim);
+        config_cli.emacs = merge_flag(config_cli.emacs, config_file.emacs);
+        config_cli.leader = config_cli.leader.or(config_file.leader);
 
         // Check if we're missing info and panic if we are
         if config_cli.api_key.is_none() {
@@ -159,11 +266,16 @@ impl Default for Config {
             config_cli.api_base = Some("https://api.openai.com/v1".to_string());
         }
 
+        if config_cli.attachment_limit.is_none() {
+            config_cli.attachment_limit = Some(100_000);
+        }
+
         config_cli
     }
 }
 
 #[derive(Debug, Clone)]
+#[allow(clippy::struct_excessive_bools)]
 pub struct Final {
     pub api_key: String,
     pub model: String,
@@ -171,21 +283,193 @@ pub struct Final {
     pub debug: bool,
     pub offline: bool,
     pub vim: bool,
+    pub emacs: bool,
+    /// The key the app's shortcuts follow, if they aren't pressed on their own
+    pub leader: Option<KeyBinding>,
     pub api_base: String,
+    pub attachment_limit: usize,
+    pub temperature: Option<f32>,
+    pub top_p: Option<f32>,
+    pub max_tokens: Option<u16>,
+    pub config_path: PathBuf,
+    pub template: Option<String>,
+    /// The active persona
+    pub persona: Option<String>,
+    pub personas: BTreeMap<String, Persona>,
+    /// The prompt, model and parameters to use when there's no persona
+    pub defaults: Persona,
+    /// Local commands the model can call, including the shell if it's set up
+    pub tools: Vec<Tool>,
+    /// MCP servers whose tools the model can call
+    pub mcp_servers: BTreeMap<String, Server>,
+    /// The directory to retrieve context from
+    pub context_dir: Option<PathBuf>,
+    pub embedding_model: String,
+    pub embedding_base: String,
+    /// Whether conversations are saved automatically so they can be searched later
+    pub archive: bool,
+    /// The most frames drawn a second, which is also how often streamed text is shown
+    pub fps: u16,
+    /// When messages and code blocks are folded
+    pub folding: Folding,
 }
 
-impl Default for Final {
-    fn default() -> Self {
+impl Final {
+    /// Read the command line and the config file.
+    pub fn load() -> AppResult<Self> {
         let config = Config::default();
+        let embedding_base = config
+            .embedding_base
+            .clone()
+            .or_else(|| config.api_base.clone());
 
-        Self {
+        let mut config_final = Self {
             api_key: config.api_key.unwrap(),
             model: config.model.unwrap(),
             prompt: config.prompt.unwrap(),
             debug: config.debug.unwrap(),
             offline: config.offline.unwrap(),
             vim: config.vim.unwrap(),
+            emacs: config.emacs.unwrap_or(false),
+            leader: config
+                .leader
+                .as_deref()
+                .map(|key| key.parse().unwrap_or_else(|err| panic!("{err}")))
+                .or_else(|| config.emacs.unwrap_or(false).then(|| KeyBinding::ctrl('c'))),
             api_base: config.api_base.unwrap(),
+            attachment_limit: config.attachment_limit.unwrap(),
+            temperature: config.temperature,
+            top_p: None,
+            max_tokens: None,
+            config_path: config.config_path.unwrap(),
+            template: config.template,
+            persona: None,
+            personas: config.personas.unwrap_or_default(),
+            defaults: Persona::default(),
+            tools: config
+                .tools
+                .unwrap_or_default()
+                .into_iter()
+                .chain(config.shell.as_ref().map(Shell::tool))
+                .collect(),
+            mcp_servers: config.mcp_servers.unwrap_or_default(),
+            context_dir: config.context_dir,
+            embedding_model: config
+                .embedding_model
+                .unwrap_or_else(|| "text-embedding-3-small".to_string()),
+            embedding_base: embedding_base.unwrap(),
+            archive: config.archive.unwrap_or(false),
+            fps: config.fps.unwrap_or(30).max(1),
+            folding: {
+                let default = Folding::default();
+                let threshold = |max: Option<usize>, default| {
+                    max.map_or(default, |max| (max > 0).then_some(max))
+                };
+                Folding {
+                    lines: config.fold_lines.unwrap_or(default.lines).max(1),
+                    user: threshold(config.fold_user, default.user),
+                    code: threshold(config.fold_code, default.code),
+                }
+            },
+        };
+        config_final.defaults = Persona {
+            prompt: config_final.prompt.clone(),
+            model: Some(config_final.model.clone()),
+            temperature: config_final.temperature,
+            top_p: None,
+            max_tokens: None,
+        };
+
+        if let Some(name) = config.persona {
+            config_final.set_persona(&name)?;
+            let overrides = config.overrides;
+            if !overrides.prompt.is_empty() {
+                config_final.prompt = overrides.prompt;
+            }
+            if let Some(model) = overrides.model {
+                config_final.model = model;
+            }
+            if overrides.temperature.is_some() {
+                config_final.temperature = overrides.temperature;
+            }
         }
+
+        Ok(config_final)
     }
 }
+
+impl Final {
+    /// Switch to the persona called `name`. The persona `default` is the config without one.
+    pub fn set_persona(&mut self, name: &str) -> AppResult<()> {
+        let persona = if name == "default" {
+            self.defaults.clone()
+        } else {
+            self.personas.get(name).cloned().ok_or_else(|| {
+                format!(
+                    "There's no persona called {name}. Add it to the personas in {}",
+                    self.config_path.display()
+                )
+            })?
+        };
+
+        self.prompt = if persona.prompt.is_empty() {
+            self.defaults.prompt.clone()
+        } else {
+            persona.prompt
+        };
+        self.model = persona
+            .model
+            .or_else(|| self.defaults.model.clone())
+            .unwrap_or_default();
+        self.temperature = persona.temperature.or(self.defaults.temperature);
+        self.top_p = persona.top_p.or(self.defaults.top_p);
+        self.max_tokens = persona.max_tokens.or(self.defaults.max_tokens);
+        self.persona = (name != "default").then(|| name.to_string());
+        Ok(())
+    }
+
+    /// The names of every persona in the order they're cycled through, starting with `default`.
+    #[must_use]
+    pub fn persona_names(&self) -> Vec<String> {
+        std::iter::once("default".to_string())
+            .chain(self.personas.keys().cloned())
+            .collect()
+    }
+}
+
+/// Save `prompt` as the default prompt in the config file at `path`, or as the prompt of the
+/// persona called `persona`, leaving the other options as they are.
+pub fn save_prompt(path: &Path, persona: Option<&str>, prompt: &[Prompt]) -> AppResult<()> {
+    let prompt = serde_json::to_value(prompt)?;
+    let Some(name) = persona else {
+        return save_value(path, "prompt", prompt);
+    };
+
+    let mut config: Value = serde_json::from_str(&read_to_string(path)?)?;
+    config
+        .pointer_mut(&format!(
+            "/personas/{}",
+            name.replace('~', "~0").replace('/', "~1")
+        ))
+        .and_then(Value::as_object_mut)
+        .ok_or_else(|| format!("There's no persona called {name} in the config file"))?
+        .insert("prompt".to_string(), prompt);
+    write(path, serde_json::to_string_pretty(&config)? + "\n")?;
+    Ok(())
+}
+
+/// Save the settings of the shell tool in the config file at `path`.
+pub fn save_shell(path: &Path, shell: &Shell) -> AppResult<()> {
+    save_value(path, "shell", serde_json::to_value(shell)?)
+}
+
+/// Set `key` in the config file at `path` to `value`, leaving the other options as they are.
+fn save_value(path: &Path, key: &str, value: Value) -> AppResult<()> {
+    let mut config: Value = serde_json::from_str(&read_to_string(path)?)?;
+    config
+        .as_object_mut()
+        .ok_or("The config file isn't a JSON object")?
+        .insert(key.to_string(), value);
+    write(path, serde_json::to_string_pretty(&config)? + "\n")?;
+    Ok(())
+}
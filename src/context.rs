@@ -0,0 +1,276 @@
+use crate::app::AppResult;
+use crate::utils;
+use log::info;
+use serde::{Deserialize, Serialize};
+use std::collections::hash_map::DefaultHasher;
+use std::collections::{BTreeMap, HashMap};
+use std::fmt::Write;
+use std::fs;
+use std::hash::{Hash, Hasher};
+use std::path::{Path, PathBuf};
+use std::time::UNIX_EPOCH;
+
+/// How many chunks are given to the model with each message.
+pub const RESULTS: usize = 5;
+/// Lines in a chunk.
+const CHUNK_LINES: usize = 40;
+/// Lines shared by neighbouring chunks so matches on a boundary aren't split.
+const CHUNK_OVERLAP: usize = 10;
+/// Files bigger than this are left out of the index.
+const FILE_LIMIT: u64 = 512 * 1024;
+/// Directories that are never worth indexing.
+const SKIPPED: &[&str] = &["target", "node_modules"];
+/// BM25 term frequency saturation.
+const K1: f32 = 1.2;
+/// BM25 length normalization.
+const B: f32 = 0.75;
+
+/// A piece of a file in the index.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Chunk {
+    pub path: String,
+    /// The first line of the chunk, counting from 1
+    pub start: usize,
+    /// The last line of the chunk
+    pub end: usize,
+    pub text: String,
+}
+
+impl Chunk {
+    /// Where the chunk came from, like `src/app.rs:1-40`.
+    #[must_use]
+    pub fn citation(&self) -> String {
+        format!("{}:{}-{}", self.path, self.start, self.end)
+    }
+}
+
+/// A BM25 index of the text files in a directory.
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct Index {
+    pub root: PathBuf,
+    pub chunks: Vec<Chunk>,
+    /// The chunks each term appears in and how many times
+    postings: HashMap<String, Vec<(usize, u32)>>,
+    /// The number of terms in each chunk
+    lengths: Vec<u32>,
+    /// When each file was last modified, to tell when the index is out of date
+    files: BTreeMap<String, u64>,
+}
+
+impl Index {
+    /// Load the saved index of `root`, rebuilding it if any files changed since it was saved.
+    pub fn open(root: &Path) -> AppResult<Self> {
+        let root = root
+            .canonicalize()
+            .map_err(|err| format!("Couldn't open {}: {err}", root.display()))?;
+        let path = Self::path(&root)?;
+        let files = list_files(&root);
+
+        if let Ok(text) = fs::read_to_string(&path) {
+            if let Ok(index) = serde_json::from_str::<Self>(&text) {
+                if index.files == files {
+                    return Ok(index);
+                }
+            }
+        }
+
+        info!("Indexing {}", root.display());
+        let index = Self::build(root, files);
+        fs::write(&path, serde_json::to_string(&index)?)?;
+        Ok(index)
+    }
+
+    /// Where the index of `root` is saved.
+    fn path(root: &Path) -> AppResult<PathBuf> {
+        let mut hasher = DefaultHasher::new();
+        root.hash(&mut hasher);
+        Ok(utils::data_dir("context")?.join(format!("{:016x}.json", hasher.finish())))
+    }
+
+    fn build(root: PathBuf, files: BTreeMap<String, u64>) -> Self {
+        let mut index = Self {
+            root,
+            files,
+            ..Self::default()
+        };
+
+        for path in index.files.keys() {
+            let Ok(text) = fs::read_to_string(index.root.join(path)) else {
+                continue;
+            };
+            if text.contains('\0') {
+                continue;
+            }
+            let lines: Vec<&str> = text.lines().collect();
+            let mut start = 0;
+            while start < lines.len() {
+                let end = (start + CHUNK_LINES).min(lines.len());
+                index.chunks.push(Chunk {
+                    path: path.clone(),
+                    start: start + 1,
+                    end,
+                    text: lines[start..end].join("\n"),
+                });
+                if end == lines.len() {
+                    break;
+                }
+                start = end - CHUNK_OVERLAP;
+            }
+        }
+
+        for (i, chunk) in index.chunks.iter().enumerate() {
+            let terms = terms(&chunk.text);
+            index
+                .lengths
+                .push(u32::try_from(terms.len()).unwrap_or(u32::MAX));
+            let mut counts: HashMap<String, u32> = HashMap::new();
+            for term in terms {
+                *counts.entry(term).or_default() += 1;
+            }
+            for (term, count) in counts {
+                index.postings.entry(term).or_default().push((i, count));
+            }
+        }
+
+        index
+    }
+
+    /// The `count` chunks that best match `query`, best first.
+    #[must_use]
+    #[allow(clippy::cast_precision_loss)]
+    pub fn search(&self, query: &str, count: usize) -> Vec<&Chunk> {
+        if self.chunks.is_empty() {
+            return vec![];
+        }
+
+        let total = self.chunks.len() as f32;
+        let average = self.lengths.iter().map(|&len| len as f32).sum::<f32>() / total;
+        let mut scores: HashMap<usize, f32> = HashMap::new();
+        let mut query_terms = terms(query);
+        query_terms.sort();
+        query_terms.dedup();
+
+        for term in query_terms {
+            let Some(postings) = self.postings.get(&term) else {
+                continue;
+            };
+            let found = postings.len() as f32;
+            let idf = ((total - found + 0.5) / (found + 0.5)).ln_1p();
+            for &(chunk, tf) in postings {
+                let tf = tf as f32;
+                let length = self.lengths[chunk] as f32;
+                *scores.entry(chunk).or_default() +=
+                    idf * tf * (K1 + 1.0) / K1.mul_add(1.0 - B + B * length / average, tf);
+            }
+        }
+
+        let mut ranked: Vec<(usize, f32)> = scores.into_iter().collect();
+        ranked.sort_by(|a, b| b.1.total_cmp(&a.1));
+        ranked
+            .into_iter()
+            .take(count)
+            .map(|(chunk, _)| &self.chunks[chunk])
+            .collect()
+    }
+}
+
+/// The system message giving the model the chunks that matched.
+#[must_use]
+pub fn message(root: &Path, chunks: &[&Chunk]) -> String {
+    let mut message = format!(
+        "Excerpts from the project in {} that may help answer the next message. Mention the \
+        files you use.",
+        root.display()
+    );
+    for chunk in chunks {
+        let _ = write!(
+            message,
+            "\n\n{}\n```\n{}\n```",
+            chunk.citation(),
+            chunk.text
+        );
+    }
+    message
+}
+
+/// Lowercase words and identifiers, plus the parts of `snake_case` identifiers.
+fn terms(text: &str) -> Vec<String> {
+    let mut terms = vec![];
+    for word in text
+        .split(|c: char| !c.is_alphanumeric() && c != '_')
+        .filter(|word| word.len() > 1)
+    {
+        let word = word.to_lowercase();
+        if word.contains('_') {
+            terms.extend(
+                word.split('_')
+                    .filter(|part| part.len() > 1)
+                    .map(ToString::to_string),
+            );
+        }
+        terms.push(word);
+    }
+    terms
+}
+
+/// Every file under `root` that should be indexed, with when it was last modified.
+fn list_files(root: &Path) -> BTreeMap<String, u64> {
+    let ignored = ignored(root);
+    let mut files = BTreeMap::new();
+    let mut directories = vec![root.to_path_buf()];
+
+    while let Some(directory) = directories.pop() {
+        let Ok(entries) = fs::read_dir(&directory) else {
+            continue;
+        };
+        for entry in entries.filter_map(Result::ok) {
+            let path = entry.path();
+            let name = entry.file_name().to_string_lossy().to_string();
+            let relative = path
+                .strip_prefix(root)
+                .unwrap_or(&path)
+                .to_string_lossy()
+                .to_string();
+            if name.starts_with('.')
+                || SKIPPED.contains(&name.as_str())
+                || ignored
+                    .iter()
+                    .any(|pattern| *pattern == name || *pattern == relative)
+            {
+                continue;
+            }
+
+            let Ok(metadata) = entry.metadata() else {
+                continue;
+            };
+            if metadata.is_dir() {
+                directories.push(path);
+            } else if metadata.is_file() && metadata.len() <= FILE_LIMIT {
+                let modified = metadata
+                    .modified()
+                    .ok()
+                    .and_then(|time| time.duration_since(UNIX_EPOCH).ok())
+                    .map_or(0, |time| time.as_secs());
+                files.insert(relative, modified);
+            }
+        }
+    }
+
+    files
+}
+
+/// The plain names and paths in the `.gitignore` at `root`. Patterns with wildcards are skipped.
+fn ignored(root: &Path) -> Vec<String> {
+    fs::read_to_string(root.join(".gitignore"))
+        .unwrap_or_default()
+        .lines()
+        .map(str::trim)
+        .filter(|line| {
+            !line.is_empty()
+                && !line.starts_with('#')
+                && !line.starts_with('!')
+                && !line.contains(['*', '?', '['])
+        })
+        .map(|line| line.trim_matches('/').to_string())
+        .collect()
+}
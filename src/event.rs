@@ -1,10 +1,14 @@
 use crate::app::AppResult;
+use crate::archive::Hit;
+use crate::tool::ToolCall;
 use crate::widgets::error::Severity;
-use crossterm::event::{self, Event as CrosstermEvent, KeyEvent, MouseEvent};
-use log::error;
-use std::time::{Duration, Instant};
+use crossterm::event::{Event as CrosstermEvent, EventStream, KeyEvent, MouseEvent};
+use futures::future::poll_fn;
+use futures::StreamExt;
+use std::task::Poll;
+use std::time::Duration;
 use tokio::sync::mpsc;
-use tokio::task;
+use tokio::time::{self, Interval, MissedTickBehavior};
 
 /// Terminal events.
 #[derive(Clone, Debug, PartialEq, Eq)]
@@ -15,14 +19,26 @@ pub enum Event {
     Key(KeyEvent),
     /// Mouse click/scroll.
     Mouse(MouseEvent),
+    /// Text pasted into the terminal.
+    Paste(String),
     /// Append new user message
     Message,
     /// Begin generating Assistant response
     StartGeneration,
-    /// New token
-    Token(String, bool),
+    /// New text from the stream. Holds the text, how many tokens it came in and whether it starts
+    /// the response.
+    Token(String, usize, bool),
     /// End the assistant generation
     EndGeneration,
+    /// The assistant response ended by calling tools
+    ToolCalls(Vec<ToolCall>),
+    /// A tool finished running. Holds the ID of the call and the output.
+    ToolOutput(String, String),
+    /// The `!{command}`s in a message finished running. Holds the message with the markers
+    /// replaced and the outputs to attach.
+    CommandOutputs(String, Vec<String>),
+    /// A search of the saved conversations finished. Holds the query and what was found.
+    RecallResults(String, Result<Vec<Hit>, String>),
     /// Terminal resize.
     Resize(u16, u16),
     /// Show an error popup.
@@ -32,67 +48,87 @@ pub enum Event {
 }
 
 /// Terminal event handler.
-#[allow(dead_code)]
+///
+/// Terminal events are read from crossterm's event stream and everything else comes in on a
+/// channel, so one loop can wait on both.
 #[derive(Debug)]
 pub struct Handler {
     /// Event sender channel.
     sender: mpsc::Sender<Event>,
     /// Event receiver channel.
     receiver: mpsc::Receiver<Event>,
-    /// Event handler thread.
-    handler: task::JoinHandle<()>,
+    /// Terminal events.
+    terminal: EventStream,
+    /// Fires every tick.
+    ticks: Interval,
 }
 
 impl Handler {
     #[must_use]
     /// Constructs a new instance of [`EventHandler`].
     pub fn new(tick_rate: u64) -> Self {
-        let tick_rate = Duration::from_millis(tick_rate);
         let (sender, receiver) = mpsc::channel(256);
-        let handler = {
-            let sender = sender.clone();
-            tokio::spawn(async move {
-                let mut last_tick = Instant::now();
-                loop {
-                    let timeout = tick_rate
-                        .checked_sub(last_tick.elapsed())
-                        .unwrap_or(tick_rate);
-
-                    if event::poll(timeout).expect("no events available") {
-                        match event::read().expect("unable to read event") {
-                            CrosstermEvent::Key(e) => sender.send(Event::Key(e)),
-                            CrosstermEvent::Mouse(e) => sender.send(Event::Mouse(e)),
-                            CrosstermEvent::Resize(w, h) => sender.send(Event::Resize(w, h)),
-                            _ => unimplemented!(),
-                        }
-                        .await
-                        .expect("failed to send terminal event");
-                    }
-
-                    if last_tick.elapsed() >= tick_rate {
-                        if matches!(sender.send(Event::Tick).await, Ok(())) {
-                            last_tick = Instant::now();
-                        } else {
-                            error!("Couldn't send the tick event. Assuming the app shut down.");
-                            return;
-                        }
-                    }
-                }
-            })
-        };
+        let mut ticks = time::interval(Duration::from_millis(tick_rate));
+        ticks.set_missed_tick_behavior(MissedTickBehavior::Skip);
         Self {
             sender,
             receiver,
-            handler,
+            terminal: EventStream::new(),
+            ticks,
         }
     }
 
-    /// Receive the next event from the handler thread.
-    ///
-    /// This function will always block the current thread if
-    /// there is no data available and it's possible for more data to be sent.
+    /// Receive the next event, waiting for one if there isn't any.
     pub async fn next(&mut self) -> AppResult<Event> {
-        Ok(self.receiver.recv().await.unwrap())
+        loop {
+            let event = tokio::select! {
+                event = self.terminal.next() => match event {
+                    Some(event) => Self::terminal_event(event?),
+                    None => return Err("The terminal closed".into()),
+                },
+                event = self.receiver.recv() => event,
+                _ = self.ticks.tick() => Some(Event::Tick),
+            };
+            if let Some(event) = event {
+                return Ok(event);
+            }
+        }
+    }
+
+    /// Receive an event that's already waiting, without waiting for one.
+    ///
+    /// The terminal is polled with the task's own waker. Polling it with a no-op one would
+    /// swallow the wakeup for the next key press, and `next` would sleep through it.
+    pub async fn try_next(&mut self) -> AppResult<Option<Event>> {
+        poll_fn(|cx| {
+            while let Poll::Ready(event) = self.terminal.poll_next_unpin(cx) {
+                let Some(event) = event else {
+                    return Poll::Ready(Err("The terminal closed".into()));
+                };
+                match event {
+                    Ok(event) => {
+                        if let Some(event) = Self::terminal_event(event) {
+                            return Poll::Ready(Ok(Some(event)));
+                        }
+                    }
+                    Err(err) => return Poll::Ready(Err(err.into())),
+                }
+            }
+            Poll::Ready(Ok(self.receiver.try_recv().ok()))
+        })
+        .await
+    }
+
+    /// The event for a terminal event, if it's one the app uses.
+    fn terminal_event(event: CrosstermEvent) -> Option<Event> {
+        match event {
+            CrosstermEvent::Key(e) => Some(Event::Key(e)),
+            CrosstermEvent::Mouse(e) => Some(Event::Mouse(e)),
+            CrosstermEvent::Paste(text) => Some(Event::Paste(text)),
+            CrosstermEvent::Resize(w, h) => Some(Event::Resize(w, h)),
+            // Focus changes aren't turned on
+            CrosstermEvent::FocusGained | CrosstermEvent::FocusLost => None,
+        }
     }
 
     #[must_use]
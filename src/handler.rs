@@ -1,10 +1,22 @@
+use crate::archive::Hit;
+use crate::command::{Command, Format};
 use crate::widgets::error::{PopupMessage, Severity};
+use crate::widgets::palette::Palette;
+use crate::widgets::preview::Preview;
+use crate::widgets::search::Search;
 use crate::{
-    app::{App, AppResult},
+    action::Action,
+    app::{App, AppResult, Focus},
+    attach::{self, Expanded},
+    chat::IMAGE_TOKENS,
+    command,
     event::Event,
     input::StyledTextArea,
+    shell,
+    tool::ToolCall,
 };
 use crossterm::event::{KeyCode, KeyEvent, KeyModifiers, MouseEvent, MouseEventKind};
+use log::{debug, error, warn};
 use tokio::sync::mpsc;
 
 /// Handles the key events and updates the state of [`App`].
@@ -18,60 +30,708 @@ pub async fn handle_key_events(
         sender.send(Event::ClearErrorPopup).await?;
         return Ok(());
     }
+    if app.palette.is_some() {
+        return handle_palette_keys(key_event, app, sender).await;
+    }
+    if app.system_editor.is_some() {
+        return handle_system_editor_keys(key_event, app, sender).await;
+    }
+    if app.template_picker.is_some() {
+        handle_template_picker_keys(key_event, app);
+        return Ok(());
+    }
+    if app.recall.is_some() {
+        return handle_recall_keys(key_event, app, sender).await;
+    }
+    if app.search.is_some() {
+        handle_search_keys(key_event, app);
+        return Ok(());
+    }
+    if app.reverse_search.is_some() {
+        handle_reverse_search_keys(key_event, app);
+        return Ok(());
+    }
+    if app.preview.is_some() {
+        return handle_preview_keys(key_event, app, sender).await;
+    }
+    if app.overwrite.is_some() {
+        return handle_overwrite_keys(key_event, app, sender).await;
+    }
+    if !app.pending_tools.is_empty() {
+        return handle_tool_confirm_keys(key_event, app, sender).await;
+    }
+    if app.leader_pending {
+        // Whatever follows the leader is for the app, not the editor
+        app.leader_pending = false;
+        app.input_hint = None;
+        if let Some(action) = Action::from_leader_key(&key_event) {
+            handle_action(action, app, sender).await?;
+        }
+    } else if let Some(leader) = app
+        .config
+        .leader
+        .filter(|leader| leader.matches(&key_event))
+    {
+        app.leader_pending = true;
+        app.input_hint = Some(format!("{leader} -"));
+        return Ok(());
+    } else {
+        app.input_hint = None;
+        handle_unbound_key(key_event, app, sender).await?;
+    }
+
+    if app.input_hint.is_none() {
+        app.input_hint = command::hint(&StyledTextArea::text(&mut app.input_editor));
+    }
+    Ok(())
+}
+
+/// Handles a key that isn't the leader or the key after it. With a leader set, the app's
+/// shortcuts only run after it, so the editor gets keys like `C-d` and `C-t`.
+async fn handle_unbound_key(
+    key_event: KeyEvent,
+    app: &mut App<'_>,
+    sender: mpsc::Sender<Event>,
+) -> AppResult<()> {
+    let action = Action::from_key(&key_event).filter(|_| app.config.leader.is_none());
+    if let Some(action) = action {
+        handle_action(action, app, sender).await?;
+    } else if app.focus == Focus::Chat {
+        handle_chat_keys(key_event, app, sender).await?;
+    } else if key_event.code == KeyCode::Tab {
+        if !app.complete_input() {
+            app.edit_input(key_event);
+        }
+    } else if !handle_history_keys(key_event, app) {
+        app.edit_input(key_event);
+    }
+    Ok(())
+}
+
+/// Runs an action from a keybinding or the command palette.
+pub async fn handle_action(
+    action: Action,
+    app: &mut App<'_>,
+    sender: mpsc::Sender<Event>,
+) -> AppResult<()> {
+    match action {
+        Action::Quit => app.quit(),
+        Action::Submit => sender.send(Event::Message).await?,
+        Action::Reset => app.reset_history(),
+        Action::Retry if app.generating => report_busy(sender).await?,
+        Action::Retry => {
+            app.generating = true;
+            sender.send(Event::StartGeneration).await?;
+        }
+        Action::Cancel => {
+            if app.cancel_generation() || app.cancel_tools() {
+                handle_end(app)?;
+            }
+        }
+        Action::CopyLast => app.copy_last_message(sender).await,
+        Action::Palette => app.palette = Some(Palette::new(app.config.leader)),
+        Action::Focus => app.toggle_focus(),
+        Action::Search => app.search = Some(Search::new()),
+        Action::HistorySearch => app.start_reverse_search(),
+        Action::SystemPrompt => {
+            let result = app.run_command(Command::EditSystem);
+            report_command(result.map_err(|err| err.to_string()), sender).await?;
+        }
+        Action::NextPersona => {
+            let names = app.config.persona_names();
+            let current = app.config.persona.as_deref().unwrap_or("default");
+            let next = names
+                .iter()
+                .position(|name| name == current)
+                .map_or(0, |i| (i + 1) % names.len());
+            let result = app.run_command(Command::Persona(Some(names[next].clone())));
+            report_command(result.map_err(|err| err.to_string()), sender).await?;
+        }
+        Action::Template => {
+            let result = app.run_command(Command::Template(None));
+            report_command(result.map_err(|err| err.to_string()), sender).await?;
+        }
+        Action::Recall => {
+            let result = app.run_command(Command::Recall(None));
+            report_command(result.map_err(|err| err.to_string()), sender).await?;
+        }
+        Action::Save => {
+            let result = app.run_command(Command::Save(None));
+            report_command(result.map_err(|err| err.to_string()), sender).await?;
+        }
+        Action::Export => {
+            let result = app.run_command(Command::Export(Format::Markdown, None));
+            report_command(result.map_err(|err| err.to_string()), sender).await?;
+        }
+        Action::Help => {
+            let result = app.run_command(Command::Help);
+            report_command(result.map_err(|err| err.to_string()), sender).await?;
+        }
+    }
+
+    Ok(())
+}
+
+async fn handle_palette_keys(
+    key_event: KeyEvent,
+    app: &mut App<'_>,
+    sender: mpsc::Sender<Event>,
+) -> AppResult<()> {
+    let Some(palette) = app.palette.as_mut() else {
+        return Ok(());
+    };
+    let ctrl = key_event.modifiers.contains(KeyModifiers::CONTROL);
     match key_event.code {
-        // Exit application on `Ctrl-c`
-        KeyCode::Char('c') => {
-            if key_event.modifiers == KeyModifiers::CONTROL {
-                app.quit();
-            } else {
-                app.edit_input(key_event);
+        KeyCode::Esc => app.palette = None,
+        KeyCode::Char('c') if ctrl => app.palette = None,
+        KeyCode::Enter => {
+            let action = palette.selected_action();
+            app.palette = None;
+            if let Some(action) = action {
+                handle_action(action, app, sender).await?;
             }
         }
+        KeyCode::Up => palette.previous(),
+        KeyCode::Char('p' | 'k') if ctrl => palette.previous(),
+        KeyCode::Down => palette.next(),
+        KeyCode::Char('n' | 'j') if ctrl => palette.next(),
+        KeyCode::Backspace => palette.pop(),
+        KeyCode::Char(c) if !ctrl => palette.push(c),
+        _ => {}
+    }
+
+    Ok(())
+}
+
+async fn handle_system_editor_keys(
+    key_event: KeyEvent,
+    app: &mut App<'_>,
+    sender: mpsc::Sender<Event>,
+) -> AppResult<()> {
+    let Some(system) = app.system_editor.as_mut() else {
+        return Ok(());
+    };
+
+    if let Some(editor) = system.editor.as_mut() {
+        if key_event.code == KeyCode::Esc {
+            system.finish_edit();
+            app.chat_text.set_system_prompts(system.prompts.clone());
+        } else {
+            editor.input(StyledTextArea::into_input(key_event));
+        }
+        return Ok(());
+    }
+
+    match key_event.code {
+        KeyCode::Esc | KeyCode::Char('q') => app.system_editor = None,
+        KeyCode::Up | KeyCode::Char('k') => system.previous(),
+        KeyCode::Down | KeyCode::Char('j') => system.next(),
+        KeyCode::Char('a') => system.add(),
+        KeyCode::Enter | KeyCode::Char('e') => system.edit(),
         KeyCode::Char('d') => {
-            if key_event.modifiers.contains(KeyModifiers::CONTROL) {
-                sender.send(Event::Message).await?;
-            } else {
-                app.edit_input(key_event);
+            system.remove();
+            app.chat_text.set_system_prompts(system.prompts.clone());
+        }
+        KeyCode::Char('s') => {
+            let result = app.save_system_prompts().map(Some);
+            report_command(result.map_err(|err| err.to_string()), sender).await?;
+        }
+        _ => {}
+    }
+
+    Ok(())
+}
+
+fn handle_template_picker_keys(key_event: KeyEvent, app: &mut App<'_>) {
+    let Some(picker) = app.template_picker.as_mut() else {
+        return;
+    };
+    let ctrl = key_event.modifiers.contains(KeyModifiers::CONTROL);
+
+    if let Some(form) = picker.form.as_mut() {
+        match key_event.code {
+            KeyCode::Esc => picker.form = None,
+            KeyCode::Enter if form.is_last() => {
+                let form = form.clone();
+                app.template_picker = None;
+                app.use_template(&form);
             }
+            KeyCode::Tab | KeyCode::Down | KeyCode::Enter => form.next(),
+            KeyCode::BackTab | KeyCode::Up => form.previous(),
+            _ => form.input(StyledTextArea::into_input(key_event)),
         }
-        KeyCode::Char('r') => {
-            if key_event.modifiers.contains(KeyModifiers::CONTROL) {
-                app.reset_history();
-            } else {
-                app.edit_input(key_event);
+        return;
+    }
+
+    match key_event.code {
+        KeyCode::Esc => app.template_picker = None,
+        KeyCode::Char('c') if ctrl => app.template_picker = None,
+        KeyCode::Enter => {
+            picker.choose();
+            if picker
+                .form
+                .as_ref()
+                .is_some_and(|form| form.fields.is_empty())
+            {
+                let form = picker.form.take().unwrap();
+                app.template_picker = None;
+                app.use_template(&form);
             }
         }
-        KeyCode::Char('t') => {
-            if key_event.modifiers.contains(KeyModifiers::CONTROL) {
+        KeyCode::Up => picker.previous(),
+        KeyCode::Char('p' | 'k') if ctrl => picker.previous(),
+        KeyCode::Down => picker.next(),
+        KeyCode::Char('n' | 'j') if ctrl => picker.next(),
+        KeyCode::Backspace => picker.pop(),
+        KeyCode::Char(c) if !ctrl => picker.push(c),
+        _ => {}
+    }
+}
+
+/// Inserts pasted text in one go, so none of it is taken as keys. Fields that only hold a line get
+/// the text with its line breaks turned into spaces.
+pub fn handle_paste(text: &str, app: &mut App<'_>) {
+    if app.error.is_some()
+        || app.preview.is_some()
+        || app.overwrite.is_some()
+        || !app.pending_tools.is_empty()
+    {
+        return;
+    }
+    // Terminals send the line breaks in a paste as carriage returns
+    let text = text.replace("\r\n", "\n").replace('\r', "\n");
+    let line = text.replace('\n', " ");
+
+    if let Some(search) = app.reverse_search.as_mut() {
+        search.push_str(&line, &app.input_history);
+        app.show_reverse_search();
+    } else if let Some(palette) = app.palette.as_mut() {
+        line.chars().for_each(|c| palette.push(c));
+    } else if let Some(system) = app.system_editor.as_mut() {
+        if let Some(editor) = system.editor.as_mut() {
+            editor.insert_str(&text);
+        }
+    } else if let Some(picker) = app.template_picker.as_mut() {
+        match picker.form.as_mut() {
+            Some(form) => {
+                if let Some((_, field)) = form.fields.get_mut(form.focused) {
+                    field.insert_str(&line);
+                }
+            }
+            None => line.chars().for_each(|c| picker.push(c)),
+        }
+    } else if let Some(recall) = app.recall.as_mut() {
+        line.chars().for_each(|c| recall.push(c));
+    } else if let Some(search) = app.search.as_mut() {
+        search.push_str(&line);
+    } else {
+        app.paste(&text);
+    }
+}
+
+/// Works on the selected message while the chat has focus.
+async fn handle_chat_keys(
+    key_event: KeyEvent,
+    app: &mut App<'_>,
+    sender: mpsc::Sender<Event>,
+) -> AppResult<()> {
+    let result = match key_event.code {
+        KeyCode::Char('y') => app.copy_selected().map(|()| None),
+        KeyCode::Char('d') => app.delete_selected().map(|()| None),
+        KeyCode::Char('e') => app.edit_selected().map(|()| None),
+        KeyCode::Char('r') => {
+            let result = app.regenerate_selected().map_err(|err| err.to_string());
+            if result == Ok(true) {
                 app.generating = true;
                 sender.send(Event::StartGeneration).await?;
-            } else {
-                app.edit_input(key_event);
             }
+            return report_command(result.map(|_| None), sender).await;
         }
-        KeyCode::Char('x') => {
-            if key_event.modifiers == KeyModifiers::CONTROL {
-                app.copy_last_message(sender).await;
-            } else {
-                app.edit_input(key_event);
+        KeyCode::Char('m') => Ok(app.selected_info()),
+        _ => {
+            handle_chat_navigation(key_event, app);
+            return Ok(());
+        }
+    };
+    report_command(result.map_err(|err| err.to_string()), sender).await
+}
+
+/// Scrolls the chat and moves between its messages.
+fn handle_chat_navigation(key_event: KeyEvent, app: &mut App<'_>) {
+    match key_event.code {
+        KeyCode::Esc | KeyCode::Char('i') => app.focus = Focus::Input,
+        KeyCode::Char('j') => app.select_next(),
+        KeyCode::Char('k') => app.select_previous(),
+        KeyCode::Char(']') => app.select_pinned(true),
+        KeyCode::Char('[') => app.select_pinned(false),
+        KeyCode::Down => app.scroll_down(1),
+        KeyCode::Up => app.scroll_up(1),
+        KeyCode::PageDown | KeyCode::Char(' ') => app.scroll_down(app.page()),
+        KeyCode::PageUp | KeyCode::Char('b') => app.scroll_up(app.page()),
+        KeyCode::Home | KeyCode::Char('g') => app.scroll_to(0),
+        KeyCode::End | KeyCode::Char('G') => app.scroll_to_bottom(),
+        KeyCode::Char('/') => app.search = Some(Search::new()),
+        KeyCode::Char('p') => {
+            if let Some(index) = app.selected() {
+                app.chat_text.toggle_pin(index);
+            }
+        }
+        KeyCode::Enter | KeyCode::Char('z') => {
+            if let Some(index) = app.selected() {
+                app.chat_text.toggle_fold(index);
+            }
+        }
+        KeyCode::Char('Z') => {
+            if let Some(index) = app.selected() {
+                app.chat_text.toggle_code_fold(index);
             }
         }
-        _ => app.edit_input(key_event),
+        _ => {}
+    }
+}
+
+/// Edits the search query and moves between matches. Like isearch, `C-s` and `C-r` also go to the
+/// next and previous match.
+fn handle_search_keys(key_event: KeyEvent, app: &mut App<'_>) {
+    let Some(search) = app.search.as_mut() else {
+        return;
+    };
+    let ctrl = key_event.modifiers.contains(KeyModifiers::CONTROL);
+    let shift = key_event.modifiers.contains(KeyModifiers::SHIFT);
+
+    match key_event.code {
+        KeyCode::Esc => app.search = None,
+        KeyCode::Char('c' | 'g') if ctrl => app.search = None,
+        KeyCode::Enter if shift => search.previous(),
+        KeyCode::Enter | KeyCode::Down => search.next(),
+        KeyCode::Char('s' | 'n') if ctrl => search.next(),
+        KeyCode::Up => search.previous(),
+        KeyCode::Char('r' | 'p') if ctrl => search.previous(),
+        KeyCode::Backspace => search.pop(),
+        KeyCode::Char(c) if !ctrl => search.push(c),
+        _ => {}
+    }
+}
+
+/// Goes through the prompt history with Up and Down (or `C-p` and `C-n`) once the cursor can't
+/// move any further that way. Returns false if the key is for the editor.
+fn handle_history_keys(key_event: KeyEvent, app: &mut App<'_>) -> bool {
+    let ctrl = key_event.modifiers.contains(KeyModifiers::CONTROL);
+    let (row, _) = app.input_editor.cursor();
+    let last = app.input_editor.lines().len().saturating_sub(1);
+
+    match key_event.code {
+        KeyCode::Up if row == 0 => app.history_previous(),
+        KeyCode::Char('p') if ctrl && row == 0 => app.history_previous(),
+        KeyCode::Down if row == last => app.history_next(),
+        KeyCode::Char('n') if ctrl && row == last => app.history_next(),
+        _ => false,
+    }
+}
+
+/// Searches back through the prompt history as you type. Like readline, any key the search
+/// doesn't use accepts the prompt found and goes on to the editor.
+fn handle_reverse_search_keys(key_event: KeyEvent, app: &mut App<'_>) {
+    let Some(search) = app.reverse_search.as_mut() else {
+        return;
+    };
+    let ctrl = key_event.modifiers.contains(KeyModifiers::CONTROL);
+
+    match key_event.code {
+        KeyCode::Esc => app.cancel_reverse_search(),
+        KeyCode::Char('c' | 'g') if ctrl => app.cancel_reverse_search(),
+        KeyCode::Char('r') if ctrl => search.older(&app.input_history),
+        KeyCode::Backspace => search.pop(&app.input_history),
+        KeyCode::Char(c) if !ctrl => search.push(c, &app.input_history),
+        KeyCode::Enter => app.reverse_search = None,
+        _ => {
+            app.reverse_search = None;
+            app.edit_input(key_event);
+        }
     }
+    app.show_reverse_search();
+}
+
+async fn handle_recall_keys(
+    key_event: KeyEvent,
+    app: &mut App<'_>,
+    sender: mpsc::Sender<Event>,
+) -> AppResult<()> {
+    let Some(recall) = app.recall.as_mut() else {
+        return Ok(());
+    };
+    let ctrl = key_event.modifiers.contains(KeyModifiers::CONTROL);
+
+    match key_event.code {
+        KeyCode::Esc => app.recall = None,
+        KeyCode::Char('c') if ctrl => app.recall = None,
+        KeyCode::Enter if recall.is_stale() => app.search_archive(sender),
+        KeyCode::Enter => {
+            let Some(hit) = recall.selected_hit().cloned() else {
+                return Ok(());
+            };
+            let result = app.open_conversation(&hit).map_err(|err| err.to_string());
+            if result.is_ok() {
+                app.recall = None;
+            }
+            report_command(result.map(|()| None), sender).await?;
+        }
+        KeyCode::Up => recall.previous(),
+        KeyCode::Char('p' | 'k') if ctrl => recall.previous(),
+        KeyCode::Down => recall.next(),
+        KeyCode::Char('n' | 'j') if ctrl => recall.next(),
+        KeyCode::Backspace => recall.pop(),
+        KeyCode::Char(c) if !ctrl => recall.push(c),
+        _ => {}
+    }
+
     Ok(())
 }
 
+/// Saves over the file `/save` asked about, or leaves it alone.
+async fn handle_overwrite_keys(
+    key_event: KeyEvent,
+    app: &mut App<'_>,
+    sender: mpsc::Sender<Event>,
+) -> AppResult<()> {
+    let ctrl = key_event.modifiers.contains(KeyModifiers::CONTROL);
+    match key_event.code {
+        KeyCode::Char('y') | KeyCode::Enter => {
+            let Some(path) = app.overwrite.take() else {
+                return Ok(());
+            };
+            let result = app.save_conversation(&path).map_err(|err| err.to_string());
+            report_command(result.map(Some), sender).await?;
+        }
+        KeyCode::Char('n') | KeyCode::Esc => app.overwrite = None,
+        KeyCode::Char('c') if ctrl => app.overwrite = None,
+        _ => {}
+    }
+    Ok(())
+}
+
+/// Runs or skips the tool call at the front of the queue.
+async fn handle_tool_confirm_keys(
+    key_event: KeyEvent,
+    app: &mut App<'_>,
+    sender: mpsc::Sender<Event>,
+) -> AppResult<()> {
+    let ctrl = key_event.modifiers.contains(KeyModifiers::CONTROL);
+    let allow_entry = (app.pending_tools[0].name == shell::NAME)
+        .then(|| shell::command(&app.pending_tools[0].arguments).ok())
+        .flatten()
+        .and_then(|command| shell::allow_entry(&command).map(ToString::to_string));
+    let run = match key_event.code {
+        KeyCode::Char('y') | KeyCode::Enter => true,
+        KeyCode::Char('a') if allow_entry.is_some() => {
+            let entry = allow_entry.unwrap_or_default();
+            let allowed = app
+                .allow_shell_command(entry)
+                .map_err(|err| err.to_string());
+            if let Err(err) = allowed {
+                let message = format!("Couldn't save the allowlist: {err}");
+                sender
+                    .send(Event::ErrorPopup(Severity::Error, message))
+                    .await?;
+            }
+            true
+        }
+        KeyCode::Char('n') | KeyCode::Esc => false,
+        KeyCode::Char('c') if ctrl => false,
+        _ => return Ok(()),
+    };
+
+    let call = app.pending_tools.remove(0);
+    if run {
+        app.run_tool(call, sender.clone());
+    } else {
+        app.chat_text
+            .push_tool_output(call.id, "The user declined to run this tool.".to_string());
+    }
+    // Allowing a command might have approved some of the others
+    let (approved, pending) = std::mem::take(&mut app.pending_tools)
+        .into_iter()
+        .partition(|call| app.is_preapproved(call));
+    app.pending_tools = pending;
+    for call in approved {
+        app.run_tool(call, sender.clone());
+    }
+    continue_after_tools(app, sender).await
+}
+
 pub async fn handle_new_message(app: &mut App<'_>, sender: mpsc::Sender<Event>) -> AppResult<()> {
     if app.input_editor.is_empty() {
         return Ok(());
     }
-    let text = StyledTextArea::text(&mut app.input_editor);
-    app.append_message();
+    let mut text = StyledTextArea::text(&mut app.input_editor);
+    app.input_history.add(&text);
+    if text.starts_with("//") {
+        // A doubled slash sends a message that starts with a slash
+        text.remove(0);
+    } else if text.starts_with('/') {
+        return handle_command(app, &text, sender).await;
+    }
+    if app.generating {
+        return report_busy(sender).await;
+    }
+    if attach::has_commands(&text) {
+        // Commands can take a while, so run them in the background and keep drawing
+        if !app.running_commands {
+            app.running_commands = true;
+            let shell = app.shell();
+            tokio::spawn(async move {
+                let (text, outputs) = attach::run_commands(&text, &shell).await;
+                if let Err(err) = sender.send(Event::CommandOutputs(text, outputs)).await {
+                    error!("Couldn't send the command outputs because of this error: {err:?}. Assuming we shut down.");
+                }
+            });
+        }
+        return Ok(());
+    }
+    expand_and_send(app, text, vec![], sender).await
+}
+
+/// Shows the message with what its commands printed so the user can check it before it's sent.
+pub async fn handle_command_outputs(
+    app: &mut App<'_>,
+    text: String,
+    outputs: Vec<String>,
+    sender: mpsc::Sender<Event>,
+) -> AppResult<()> {
+    app.running_commands = false;
+    expand_and_send(app, text, outputs, sender).await
+}
+
+/// Attaches the files referenced in `text` and sends it, or previews it first if there are
+/// command outputs to attach as well.
+async fn expand_and_send(
+    app: &mut App<'_>,
+    text: String,
+    outputs: Vec<String>,
+    sender: mpsc::Sender<Event>,
+) -> AppResult<()> {
+    let expanded = attach::expand(&text, app.config.attachment_limit, &app.config.model)
+        .map_err(|err| err.to_string());
+    let mut message = match expanded {
+        Ok(message) => message,
+        Err(message) => {
+            sender
+                .send(Event::ErrorPopup(Severity::Error, message))
+                .await?;
+            return Ok(());
+        }
+    };
+
+    // Let the user check what the commands printed before it's sent
+    if !outputs.is_empty() {
+        for output in outputs {
+            message.text.push_str("\n\n");
+            message.text.push_str(&output);
+        }
+        let tokens = message_tokens(&message);
+        app.preview = Some(Preview::new(message, tokens));
+        return Ok(());
+    }
+
+    send_message(app, message, sender).await
+}
+
+/// Roughly how many tokens a user message costs.
+fn message_tokens(message: &Expanded) -> usize {
+    let enc = tiktoken_rs::cl100k_base().unwrap();
+    enc.encode_with_special_tokens(&message.text).len() + message.images.len() * IMAGE_TOKENS
+}
+
+/// Adds a user message to the chat and starts generating the response.
+async fn send_message(
+    app: &mut App<'_>,
+    message: Expanded,
+    sender: mpsc::Sender<Event>,
+) -> AppResult<()> {
+    if app.generating {
+        return report_busy(sender).await;
+    }
+    let tokens = message_tokens(&message);
+    app.append_message(message.text, message.images);
     app.generating = true;
     sender.send(Event::StartGeneration).await?;
-    let enc = tiktoken_rs::cl100k_base().unwrap();
-    let tokens = enc.encode_with_special_tokens(&text);
-    app.chat_text.tokens += tokens.len();
+    app.chat_text.tokens += tokens;
+    Ok(())
+}
+
+async fn handle_preview_keys(
+    key_event: KeyEvent,
+    app: &mut App<'_>,
+    sender: mpsc::Sender<Event>,
+) -> AppResult<()> {
+    let Some(preview) = app.preview.as_mut() else {
+        return Ok(());
+    };
+    let ctrl = key_event.modifiers.contains(KeyModifiers::CONTROL);
+
+    match key_event.code {
+        KeyCode::Enter => {
+            let preview = app.preview.take().unwrap();
+            let message = Expanded {
+                text: preview.text,
+                images: preview.images,
+            };
+            send_message(app, message, sender).await?;
+        }
+        KeyCode::Esc => app.preview = None,
+        KeyCode::Char('c') if ctrl => app.preview = None,
+        KeyCode::Up | KeyCode::Char('k') => preview.scroll_up(),
+        KeyCode::Down | KeyCode::Char('j') => preview.scroll_down(),
+        _ => {}
+    }
+    Ok(())
+}
+
+/// Runs the slash command typed into the input editor instead of sending it.
+pub async fn handle_command(
+    app: &mut App<'_>,
+    text: &str,
+    sender: mpsc::Sender<Event>,
+) -> AppResult<()> {
+    let command = command::parse(text);
+    // `/recall` with a query searches straight away
+    let search = matches!(command, Ok(Command::Recall(Some(_))));
+    let result =
+        command.and_then(|command| app.run_command(command).map_err(|err| err.to_string()));
+    if result.is_ok() {
+        app.clear_input();
+        if search {
+            app.search_archive(sender.clone());
+        }
+    }
+    report_command(result, sender).await
+}
+
+/// Tells the user a new response can't start until the one being generated ends.
+async fn report_busy(sender: mpsc::Sender<Event>) -> AppResult<()> {
+    let message = "Wait for the response to finish first.".to_string();
+    report_command(Err(message), sender).await
+}
+
+/// Shows the outcome of a command in a popup.
+async fn report_command(
+    result: Result<Option<String>, String>,
+    sender: mpsc::Sender<Event>,
+) -> AppResult<()> {
+    match result {
+        Ok(Some(message)) => {
+            sender
+                .send(Event::ErrorPopup(Severity::Info, message))
+                .await?;
+        }
+        Ok(None) => {}
+        Err(message) => {
+            sender
+                .send(Event::ErrorPopup(Severity::Error, message))
+                .await?;
+        }
+    }
+
     Ok(())
 }
 
@@ -83,6 +743,7 @@ pub async fn handle_start_generation(
         sender
             .send(Event::Token(
                 "Running in **offline** mode.".to_string(),
+                1,
                 true,
             ))
             .await?;
@@ -90,61 +751,132 @@ pub async fn handle_start_generation(
     } else {
         app.start_generation(sender)?;
     }
+    app.start_answer();
 
     Ok(())
 }
 
-pub fn handle_token(app: &mut App<'_>, token: &str, first: bool) -> AppResult<()> {
+pub fn handle_token(app: &mut App<'_>, token: &str, count: usize, first: bool) -> AppResult<()> {
+    // Text still on its way when the response was cancelled
+    if !app.generating {
+        return Ok(());
+    }
     app.chat_text.push_stream(token, first);
-    app.chat_text.tokens += 1;
+    app.chat_text.tokens += count;
+    if let Some((started, meta)) = app.answer.as_mut() {
+        if first {
+            meta.latency = started.elapsed();
+        }
+        meta.tokens += count;
+    }
 
     Ok(())
 }
 
 pub fn handle_end(app: &mut App<'_>) -> AppResult<()> {
+    if !app.generating {
+        return Ok(());
+    }
+    app.generation = None;
     app.chat_text.clear_message();
+    let sources = std::mem::take(&mut app.pending_sources);
+    app.chat_text.set_last_sources(sources);
+    app.finish_answer();
+    if let Err(err) = app.archive_conversation() {
+        warn!("Couldn't archive the conversation: {err}");
+    }
     app.generating = false;
 
     Ok(())
 }
 
-pub fn handle_mouse_events(
-    mouse_event: MouseEvent,
-    second_event: Option<MouseEvent>,
+/// Shows the messages found by a search of the saved conversations.
+pub fn handle_recall_results(
     app: &mut App<'_>,
+    query: String,
+    hits: Result<Vec<Hit>, String>,
 ) -> AppResult<()> {
-    // Don't handle mouse events if the error popup is visible
-    if app.error.is_some() {
-        return Ok(());
+    if let Some(recall) = app.recall.as_mut() {
+        recall.finish(query, hits);
     }
 
-    match second_event {
-        Some(second) => {
-            if mouse_event.kind == MouseEventKind::ScrollUp
-                && second.kind == MouseEventKind::ScrollDown
-            {
-                return Ok(()); // If you scroll up once and down once that cancels out and we don't
-                               // move
-            } else if mouse_event.kind == MouseEventKind::ScrollDown
-                && second.kind == MouseEventKind::ScrollDown
-            {
-                app.scroll_down(2);
-            } else if mouse_event.kind == MouseEventKind::ScrollUp
-                && second.kind == MouseEventKind::ScrollUp
-            {
-                app.scroll_up(2);
-            }
+    Ok(())
+}
+
+/// Records the tools the model called and asks to run them.
+pub async fn handle_tool_calls(
+    app: &mut App<'_>,
+    calls: Vec<ToolCall>,
+    sender: mpsc::Sender<Event>,
+) -> AppResult<()> {
+    if !app.generating {
+        return Ok(());
+    }
+    app.generation = None;
+    app.chat_text.push_tool_calls(&calls);
+    app.chat_text.clear_message();
+    app.finish_answer();
+    for call in calls {
+        if app.is_preapproved(&call) {
+            app.run_tool(call, sender.clone());
+        } else if app.tool(&call.name).is_some() {
+            app.pending_tools.push(call);
+        } else {
+            app.chat_text
+                .push_tool_output(call.id, format!("There's no tool called {}.", call.name));
         }
-        None => match mouse_event.kind {
-            MouseEventKind::ScrollUp => app.scroll_up(1),
-            MouseEventKind::ScrollDown => app.scroll_down(1),
-            _ => {}
-        },
     }
+    continue_after_tools(app, sender).await
+}
+
+pub async fn handle_tool_output(
+    app: &mut App<'_>,
+    id: String,
+    output: String,
+    sender: mpsc::Sender<Event>,
+) -> AppResult<()> {
+    // Calls that were stopped or belong to a conversation that's gone have nothing to answer
+    if app.running_tools.remove(&id).is_none() {
+        debug!("Dropping the output of the tool call {id}, which isn't running anymore");
+        return Ok(());
+    }
+    app.chat_text.push_tool_output(id, output);
+    continue_after_tools(app, sender).await
+}
 
+/// Sends the tool outputs back to the model once every call has been answered.
+async fn continue_after_tools(app: &mut App<'_>, sender: mpsc::Sender<Event>) -> AppResult<()> {
+    if app.pending_tools.is_empty() && app.running_tools.is_empty() {
+        app.generating = true;
+        sender.send(Event::StartGeneration).await?;
+    }
     Ok(())
 }
 
+/// How many lines a mouse event scrolls the chat, with down positive.
+#[must_use]
+pub const fn mouse_scroll(mouse_event: &MouseEvent) -> isize {
+    match mouse_event.kind {
+        MouseEventKind::ScrollUp => -1,
+        MouseEventKind::ScrollDown => 1,
+        _ => 0,
+    }
+}
+
+/// Scrolls the chat by the lines the mouse wheel moved since the last frame.
+pub fn handle_scroll(app: &mut App<'_>, lines: isize) {
+    // Don't handle mouse events if the error popup is visible
+    if app.error.is_some() {
+        return;
+    }
+
+    if lines > 0 {
+        app.scroll_down(lines.unsigned_abs());
+    } else if lines < 0 {
+        app.scroll_up(lines.unsigned_abs());
+    }
+}
+
 pub fn handle_error_popup(app: &mut App<'_>, severity: Severity, message: String) -> AppResult<()> {
     app.error = Some(PopupMessage::new(message, severity));
 
@@ -1,375 +1,52 @@
-use core::fmt;
-
 use crossterm::event::{KeyCode, KeyEvent, KeyModifiers};
+use motion::Pos;
 use ratatui::{
     text::Line,
     widgets::{Block, BorderType, Borders},
 };
-use tui_textarea::{CursorMove, Input, Key, Scrolling, TextArea};
-
-#[derive(Debug, Clone, Copy, PartialEq, Eq)]
-pub enum Mode {
-    Normal,
-    Insert,
-    Visual,
-    Operator(char),
-}
-
-impl fmt::Display for Mode {
-    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        match self {
-            Self::Normal => write!(f, "Normal"),
-            Self::Insert => write!(f, "Insert"),
-            Self::Visual => write!(f, "Visual"),
-            Self::Operator(c) => write!(f, "Operator ({c})"),
-        }
-    }
-}
-
-pub enum Transition {
-    Nop,
-    Mode(Mode),
-    Pending(Input),
+use tui_textarea::{CursorMove, Input, Key, TextArea};
+
+mod emacs;
+mod motion;
+mod vim;
+
+pub use emacs::Emacs;
+pub use vim::{Mode, Transition, Vim};
+
+/// The lines of the editor split into characters.
+fn lines(textarea: &TextArea<'_>) -> Vec<Vec<char>> {
+    textarea
+        .lines()
+        .iter()
+        .map(|line| line.chars().collect())
+        .collect()
 }
 
-#[derive(Debug, Clone)]
-pub struct Vim {
-    pub mode: Mode,
-    pub pending: Input,
+/// Move the cursor to a row and column in characters.
+fn jump(textarea: &mut TextArea<'_>, (row, col): Pos) {
+    textarea.move_cursor(CursorMove::Jump(
+        u16::try_from(row).unwrap_or(u16::MAX),
+        u16::try_from(col).unwrap_or(u16::MAX),
+    ));
 }
 
-impl Vim {
-    #[must_use]
-    pub fn new(mode: Mode) -> Self {
-        Self {
-            mode,
-            pending: Input::default(),
-        }
-    }
-
-    #[must_use]
-    pub const fn with_pending(self, pending: Input) -> Self {
-        Self {
-            mode: self.mode,
-            pending,
-        }
+/// Cut the text from `from` up to `to`, leaving the cursor at `from`.
+fn cut(textarea: &mut TextArea<'_>, from: Pos, to: Pos) {
+    textarea.cancel_selection();
+    jump(textarea, from);
+    if from != to {
+        textarea.start_selection();
+        jump(textarea, to);
+        textarea.cut();
     }
+}
 
-    #[allow(clippy::too_many_lines)]
-    pub fn transition(&self, input: Input, textarea: &mut TextArea<'_>) -> Transition {
-        if input.key == Key::Null {
-            return Transition::Nop;
-        }
-
-        match self.mode {
-            Mode::Normal | Mode::Visual | Mode::Operator(_) => {
-                match input {
-                    Input {
-                        key: Key::Char('h'),
-                        ..
-                    } => textarea.move_cursor(CursorMove::Back),
-                    Input {
-                        key: Key::Char('j'),
-                        ..
-                    } => textarea.move_cursor(CursorMove::Down),
-                    Input {
-                        key: Key::Char('k'),
-                        ..
-                    } => textarea.move_cursor(CursorMove::Up),
-                    Input {
-                        key: Key::Char('l'),
-                        ..
-                    } => textarea.move_cursor(CursorMove::Forward),
-                    Input {
-                        key: Key::Char('w'),
-                        ..
-                    } => textarea.move_cursor(CursorMove::WordForward),
-                    Input {
-                        key: Key::Char('b'),
-                        ctrl: false,
-                        ..
-                    } => textarea.move_cursor(CursorMove::WordBack),
-                    Input {
-                        key: Key::Char('^'),
-                        ..
-                    } => textarea.move_cursor(CursorMove::Head),
-                    Input {
-                        key: Key::Char('$'),
-                        ..
-                    } => textarea.move_cursor(CursorMove::End),
-                    Input {
-                        key: Key::Char('D'),
-                        ..
-                    } => {
-                        textarea.delete_line_by_end();
-                        return Transition::Mode(Mode::Normal);
-                    }
-                    Input {
-                        key: Key::Char('C'),
-                        ..
-                    } => {
-                        textarea.delete_line_by_end();
-                        textarea.cancel_selection();
-                        return Transition::Mode(Mode::Insert);
-                    }
-                    Input {
-                        key: Key::Char('p'),
-                        ..
-                    } => {
-                        textarea.paste();
-                        return Transition::Mode(Mode::Normal);
-                    }
-                    Input {
-                        key: Key::Char('u'),
-                        ctrl: false,
-                        ..
-                    } => {
-                        textarea.undo();
-                        return Transition::Mode(Mode::Normal);
-                    }
-                    Input {
-                        key: Key::Char('r'),
-                        ctrl: true,
-                        ..
-                    } => {
-                        textarea.redo();
-                        return Transition::Mode(Mode::Normal);
-                    }
-                    Input {
-                        key: Key::Char('x'),
-                        ..
-                    } => {
-                        textarea.delete_next_char();
-                        return Transition::Mode(Mode::Normal);
-                    }
-                    Input {
-                        key: Key::Char('i'),
-                        ..
-                    } => {
-                        textarea.cancel_selection();
-                        return Transition::Mode(Mode::Insert);
-                    }
-                    Input {
-                        key: Key::Char('a'),
-                        ..
-                    } => {
-                        textarea.cancel_selection();
-                        textarea.move_cursor(CursorMove::Forward);
-                        return Transition::Mode(Mode::Insert);
-                    }
-                    Input {
-                        key: Key::Char('A'),
-                        ..
-                    } => {
-                        textarea.cancel_selection();
-                        textarea.move_cursor(CursorMove::End);
-                        return Transition::Mode(Mode::Insert);
-                    }
-                    Input {
-                        key: Key::Char('o'),
-                        ..
-                    } => {
-                        textarea.move_cursor(CursorMove::End);
-                        textarea.insert_newline();
-                        return Transition::Mode(Mode::Insert);
-                    }
-                    Input {
-                        key: Key::Char('O'),
-                        ..
-                    } => {
-                        textarea.move_cursor(CursorMove::Head);
-                        textarea.insert_newline();
-                        textarea.move_cursor(CursorMove::Up);
-                        return Transition::Mode(Mode::Insert);
-                    }
-                    Input {
-                        key: Key::Char('I'),
-                        ..
-                    } => {
-                        textarea.cancel_selection();
-                        textarea.move_cursor(CursorMove::Head);
-                        return Transition::Mode(Mode::Insert);
-                    }
-                    Input {
-                        key: Key::Char('e'),
-                        ctrl: true,
-                        ..
-                    } => textarea.scroll((1, 0)),
-                    Input {
-                        key: Key::Char('y'),
-                        ctrl: true,
-                        ..
-                    } => textarea.scroll((-1, 0)),
-                    Input {
-                        key: Key::Char('d'),
-                        ctrl: true,
-                        ..
-                    } => textarea.scroll(Scrolling::HalfPageDown),
-                    Input {
-                        key: Key::Char('u'),
-                        ctrl: true,
-                        ..
-                    } => textarea.scroll(Scrolling::HalfPageUp),
-                    Input {
-                        key: Key::Char('f'),
-                        ctrl: true,
-                        ..
-                    } => textarea.scroll(Scrolling::PageDown),
-                    Input {
-                        key: Key::Char('b'),
-                        ctrl: true,
-                        ..
-                    } => textarea.scroll(Scrolling::PageUp),
-                    Input {
-                        key: Key::Char('v'),
-                        ctrl: false,
-                        ..
-                    } if self.mode == Mode::Normal => {
-                        textarea.start_selection();
-                        return Transition::Mode(Mode::Visual);
-                    }
-                    Input {
-                        key: Key::Char('V'),
-                        ctrl: false,
-                        ..
-                    } if self.mode == Mode::Normal => {
-                        textarea.move_cursor(CursorMove::Head);
-                        textarea.start_selection();
-                        textarea.move_cursor(CursorMove::End);
-                        return Transition::Mode(Mode::Visual);
-                    }
-                    Input { key: Key::Esc, .. }
-                    | Input {
-                        key: Key::Char('v'),
-                        ctrl: false,
-                        ..
-                    } => {
-                        if self.mode == Mode::Visual {
-                            textarea.cancel_selection();
-                        }
-                        return Transition::Mode(Mode::Normal);
-                    }
-                    Input {
-                        key: Key::Char('g'),
-                        ctrl: false,
-                        ..
-                    } if matches!(
-                        self.pending,
-                        Input {
-                            key: Key::Char('g'),
-                            ctrl: false,
-                            ..
-                        }
-                    ) =>
-                    {
-                        textarea.move_cursor(CursorMove::Top);
-                    }
-                    Input {
-                        key: Key::Char('G'),
-                        ctrl: false,
-                        ..
-                    } => textarea.move_cursor(CursorMove::Bottom),
-                    Input {
-                        key: Key::Char(c),
-                        ctrl: false,
-                        ..
-                    } if self.mode == Mode::Operator(c) => {
-                        // Handle yy, dd, cc. (This is not strictly the same behavior as Vim)
-                        textarea.move_cursor(CursorMove::Head);
-                        textarea.start_selection();
-                        let cursor = textarea.cursor();
-                        textarea.move_cursor(CursorMove::Down);
-                        if cursor == textarea.cursor() {
-                            textarea.move_cursor(CursorMove::End); // At the last line, move to end of the line instead
-                        }
-                    }
-                    Input {
-                        key: Key::Char(op @ ('y' | 'd' | 'c')),
-                        ctrl: false,
-                        ..
-                    } if self.mode == Mode::Normal => {
-                        textarea.start_selection();
-                        return Transition::Mode(Mode::Operator(op));
-                    }
-                    Input {
-                        key: Key::Char('y'),
-                        ctrl: false,
-                        ..
-                    } if self.mode == Mode::Visual => {
-                        textarea.copy();
-                        return Transition::Mode(Mode::Normal);
-                    }
-                    Input {
-                        key: Key::Char('d'),
-                        ctrl: false,
-                        ..
-                    } if self.mode == Mode::Visual => {
-                        textarea.cut();
-                        return Transition::Mode(Mode::Normal);
-                    }
-                    Input {
-                        key: Key::Char('c'),
-                        ctrl: false,
-                        ..
-                    } if self.mode == Mode::Visual => {
-                        textarea.cut();
-                        return Transition::Mode(Mode::Insert);
-                    }
-                    input => return Transition::Pending(input),
-                }
-
-                // Handle the pending operator
-                match self.mode {
-                    Mode::Operator('y') => {
-                        textarea.copy();
-                        Transition::Mode(Mode::Normal)
-                    }
-                    Mode::Operator('d') => {
-                        textarea.cut();
-                        Transition::Mode(Mode::Normal)
-                    }
-                    Mode::Operator('c') => {
-                        textarea.cut();
-                        Transition::Mode(Mode::Insert)
-                    }
-                    _ => Transition::Nop,
-                }
-            }
-            Mode::Insert => match input {
-                Input { key: Key::Esc, .. } => Transition::Mode(Mode::Normal),
-                Input { key: Key::Up, .. } => {
-                    textarea.move_cursor(CursorMove::Up);
-                    Transition::Mode(Mode::Insert)
-                }
-                Input { key: Key::Down, .. } => {
-                    textarea.move_cursor(CursorMove::Down);
-                    Transition::Mode(Mode::Insert)
-                }
-                Input { key: Key::Left, .. } => {
-                    textarea.move_cursor(CursorMove::Back);
-                    Transition::Mode(Mode::Insert)
-                }
-                Input {
-                    key: Key::Right, ..
-                } => {
-                    textarea.move_cursor(CursorMove::Forward);
-                    Transition::Mode(Mode::Insert)
-                }
-                Input {
-                    key: Key::Char('w'),
-                    ctrl: true,
-                    ..
-                } => {
-                    // Delete one word back
-                    textarea.delete_word();
-                    Transition::Mode(Mode::Insert)
-                }
-                input => {
-                    textarea.input_without_shortcuts(input); // Don't use the default mappings
-                    Transition::Mode(Mode::Insert)
-                }
-            },
-        }
+/// The earlier of two positions first.
+const fn order(a: Pos, b: Pos) -> (Pos, Pos) {
+    if a.0 < b.0 || (a.0 == b.0 && a.1 <= b.1) {
+        (a, b)
+    } else {
+        (b, a)
     }
 }
 
@@ -0,0 +1,374 @@
+use core::fmt;
+
+use tui_textarea::{CursorMove, Input, Key, Scrolling, TextArea};
+
+use super::motion::{self, Pos, Range};
+use super::{cut, jump, lines, order};
+
+/// The most kills kept. The oldest are dropped past this.
+const KILL_RING_SIZE: usize = 60;
+
+/// What the last command did, for the commands that carry on from it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+enum Last {
+    #[default]
+    Other,
+    /// A kill, which the next kill adds to
+    Kill,
+    /// A yank of the text between these, which `M-y` swaps for an older kill
+    Yank(Pos, Pos),
+}
+
+/// The Emacs (and readline) bindings of the input editor, with a kill ring, the mark and the
+/// `C-x` prefix.
+#[derive(Debug, Clone, Default)]
+pub struct Emacs {
+    /// The newest kill last
+    kill_ring: Vec<String>,
+    /// How far back in the kill ring `M-y` has gone
+    yank_pop: usize,
+    last: Last,
+    /// Where the region starts when it's active
+    mark: Option<Pos>,
+    /// Whether `C-x` was pressed and the next key finishes the sequence
+    prefix: bool,
+}
+
+impl fmt::Display for Emacs {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.prefix {
+            write!(f, "C-x-")
+        } else if self.mark.is_some() {
+            write!(f, "Mark set")
+        } else {
+            Ok(())
+        }
+    }
+}
+
+impl Emacs {
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Drop the mark, for when the text it was in is replaced.
+    pub const fn forget_mark(&mut self) {
+        self.mark = None;
+    }
+
+    /// Whether `C-x` was pressed and the next key goes to it.
+    #[must_use]
+    pub const fn prefix_pending(&self) -> bool {
+        self.prefix
+    }
+
+    pub fn input(&mut self, input: &Input, textarea: &mut TextArea<'_>) {
+        let last = std::mem::take(&mut self.last);
+        if std::mem::take(&mut self.prefix) {
+            self.prefixed(input, textarea);
+            return;
+        }
+
+        let Input { key, ctrl, alt, .. } = *input;
+        match (key, ctrl, alt) {
+            (Key::Char('x'), true, false) => self.prefix = true,
+            (Key::Char('g'), true, _) => self.deactivate(textarea),
+            (Key::Char(' ' | '@'), true, _) => self.set_mark(textarea),
+
+            (Key::Char('f'), true, false) | (Key::Right, ..) => {
+                textarea.move_cursor(CursorMove::Forward);
+            }
+            (Key::Char('b'), true, false) | (Key::Left, ..) => {
+                textarea.move_cursor(CursorMove::Back);
+            }
+            (Key::Char('n'), true, false) | (Key::Down, ..) => {
+                textarea.move_cursor(CursorMove::Down);
+            }
+            (Key::Char('p'), true, false) | (Key::Up, ..) => textarea.move_cursor(CursorMove::Up),
+            (Key::Char('a'), true, false) | (Key::Home, ..) => {
+                textarea.move_cursor(CursorMove::Head);
+            }
+            (Key::Char('e'), true, false) | (Key::End, ..) => {
+                textarea.move_cursor(CursorMove::End);
+            }
+            (Key::Char('f'), false, true) => {
+                let text = lines(textarea);
+                jump(textarea, motion::forward_word(&text, textarea.cursor()));
+            }
+            (Key::Char('b'), false, true) => {
+                let text = lines(textarea);
+                jump(textarea, motion::backward_word(&text, textarea.cursor()));
+            }
+            (Key::Char('<'), false, true) => textarea.move_cursor(CursorMove::Top),
+            (Key::Char('>'), false, true) => textarea.move_cursor(CursorMove::Bottom),
+            (Key::Char('v'), true, false) | (Key::PageDown, ..) => {
+                textarea.scroll(Scrolling::PageDown);
+            }
+            (Key::Char('v'), false, true) | (Key::PageUp, ..) => {
+                textarea.scroll(Scrolling::PageUp);
+            }
+
+            (Key::Char('k'), true, false) => self.kill_line(textarea, last),
+            (Key::Char('u'), true, false) => {
+                let (row, col) = textarea.cursor();
+                self.kill(textarea, (row, col), (row, 0), last);
+            }
+            (Key::Char('d'), false, true) => {
+                let text = lines(textarea);
+                let cursor = textarea.cursor();
+                self.kill(textarea, cursor, motion::forward_word(&text, cursor), last);
+            }
+            (Key::Char('w'), true, false) if self.mark.is_some() => {
+                if let Some(mark) = self.mark {
+                    self.kill(textarea, mark, textarea.cursor(), Last::Other);
+                }
+            }
+            (Key::Char('w'), true, false) | (Key::Backspace, false, true) => {
+                let text = lines(textarea);
+                let cursor = textarea.cursor();
+                self.kill(textarea, cursor, motion::backward_word(&text, cursor), last);
+            }
+            (Key::Char('w'), false, true) => self.copy_region(textarea),
+            (Key::Char('y'), true, false) => self.yank(textarea),
+            (Key::Char('y'), false, true) => self.yank_pop(textarea, last),
+
+            (Key::Char('d'), true, false) | (Key::Delete, ..) => {
+                self.deactivate(textarea);
+                textarea.delete_next_char();
+            }
+            (Key::Char('h'), true, false) | (Key::Backspace, ..) => {
+                self.deactivate(textarea);
+                textarea.delete_char();
+            }
+            (Key::Char('t'), true, false) => self.transpose_chars(textarea),
+            (Key::Char('t'), false, true) => self.transpose_words(textarea),
+            (Key::Char('u'), false, true) => self.change_word(textarea, str::to_uppercase),
+            (Key::Char('l'), false, true) => self.change_word(textarea, str::to_lowercase),
+            (Key::Char('c'), false, true) => self.change_word(textarea, capitalize),
+            // Terminals send `C-/` as `C-_` or `C-7`
+            (Key::Char('/' | '_' | '7'), true, _) => {
+                self.deactivate(textarea);
+                textarea.undo();
+            }
+
+            (Key::Enter, ..) | (Key::Char('j' | 'm'), true, false) => {
+                self.deactivate(textarea);
+                textarea.insert_newline();
+            }
+            (Key::Tab, ..) => {
+                self.deactivate(textarea);
+                textarea.insert_tab();
+            }
+            (Key::Char(c), false, false) => {
+                self.deactivate(textarea);
+                textarea.insert_char(c);
+            }
+            _ => {}
+        }
+    }
+
+    /// Finish a `C-x` sequence.
+    fn prefixed(&mut self, input: &Input, textarea: &mut TextArea<'_>) {
+        match (input.key, input.ctrl) {
+            (Key::Char('x'), true) => {
+                // Exchange point and mark
+                if let Some(mark) = self.mark {
+                    let cursor = textarea.cursor();
+                    textarea.cancel_selection();
+                    jump(textarea, cursor);
+                    textarea.start_selection();
+                    jump(textarea, mark);
+                    self.mark = Some(cursor);
+                }
+            }
+            (Key::Char('h'), false) => {
+                // Mark the whole buffer
+                let end = motion::end(&lines(textarea));
+                textarea.cancel_selection();
+                jump(textarea, (0, 0));
+                textarea.start_selection();
+                jump(textarea, end);
+                self.mark = Some((0, 0));
+            }
+            (Key::Char('u'), false) => {
+                self.deactivate(textarea);
+                textarea.undo();
+            }
+            (Key::Char('u'), true) => self.change_region(textarea, str::to_uppercase),
+            (Key::Char('l'), true) => self.change_region(textarea, str::to_lowercase),
+            _ => {}
+        }
+    }
+
+    fn set_mark(&mut self, textarea: &mut TextArea<'_>) {
+        textarea.cancel_selection();
+        textarea.start_selection();
+        self.mark = Some(textarea.cursor());
+    }
+
+    fn deactivate(&mut self, textarea: &mut TextArea<'_>) {
+        textarea.cancel_selection();
+        self.mark = None;
+    }
+
+    /// Add text to the kill ring. A kill right after another one adds to it instead, before it
+    /// for a kill backwards.
+    fn push_kill(&mut self, text: String, last: Last, backwards: bool) {
+        match self.kill_ring.last_mut() {
+            Some(newest) if last == Last::Kill => {
+                if backwards {
+                    newest.insert_str(0, &text);
+                } else {
+                    newest.push_str(&text);
+                }
+            }
+            _ => {
+                self.kill_ring.push(text);
+                let excess = self.kill_ring.len().saturating_sub(KILL_RING_SIZE);
+                self.kill_ring.drain(..excess);
+            }
+        }
+        self.yank_pop = 0;
+    }
+
+    /// Kill the text between the cursor and `to`.
+    fn kill(&mut self, textarea: &mut TextArea<'_>, from: Pos, to: Pos, last: Last) {
+        let (start, end) = order(from, to);
+        self.deactivate(textarea);
+        if start != end {
+            let text = motion::text_in(&lines(textarea), Range::chars(start, end));
+            self.push_kill(text, last, to < from);
+            cut(textarea, start, end);
+        }
+        self.last = Last::Kill;
+    }
+
+    /// Kill to the end of the line, or the newline when already there.
+    fn kill_line(&mut self, textarea: &mut TextArea<'_>, last: Last) {
+        let (row, col) = textarea.cursor();
+        let text = lines(textarea);
+        let to = if col < text[row].len() {
+            (row, text[row].len())
+        } else if row + 1 < text.len() {
+            (row + 1, 0)
+        } else {
+            (row, col)
+        };
+        self.kill(textarea, (row, col), to, last);
+    }
+
+    fn copy_region(&mut self, textarea: &mut TextArea<'_>) {
+        let Some(mark) = self.mark else {
+            return;
+        };
+        let (start, end) = order(mark, textarea.cursor());
+        let text = motion::text_in(&lines(textarea), Range::chars(start, end));
+        self.push_kill(text, Last::Other, false);
+        self.deactivate(textarea);
+    }
+
+    fn yank(&mut self, textarea: &mut TextArea<'_>) {
+        let Some(text) = self.kill_ring.last().cloned() else {
+            return;
+        };
+        self.deactivate(textarea);
+        let start = textarea.cursor();
+        textarea.insert_str(text);
+        self.yank_pop = 0;
+        self.last = Last::Yank(start, textarea.cursor());
+    }
+
+    /// Swap the text just yanked for the kill before it.
+    fn yank_pop(&mut self, textarea: &mut TextArea<'_>, last: Last) {
+        let Last::Yank(start, end) = last else {
+            return;
+        };
+        self.yank_pop = (self.yank_pop + 1) % self.kill_ring.len();
+        let text = self.kill_ring[self.kill_ring.len() - 1 - self.yank_pop].clone();
+        cut(textarea, start, end);
+        textarea.insert_str(text);
+        self.last = Last::Yank(start, textarea.cursor());
+    }
+
+    /// Swap the characters around the cursor and move past them. At the end of a line it swaps
+    /// the two before the cursor.
+    fn transpose_chars(&mut self, textarea: &mut TextArea<'_>) {
+        self.deactivate(textarea);
+        let (row, col) = textarea.cursor();
+        let line = &lines(textarea)[row];
+        if col == 0 || line.len() < 2 {
+            return;
+        }
+        let at = col.min(line.len() - 1);
+        cut(textarea, (row, at - 1), (row, at + 1));
+        textarea.insert_str(String::from_iter([line[at], line[at - 1]]));
+    }
+
+    /// Swap the word at or before the cursor with the one after it, or with the one before it at
+    /// the end of the text.
+    fn transpose_words(&mut self, textarea: &mut TextArea<'_>) {
+        self.deactivate(textarea);
+        let text = lines(textarea);
+        let words = |from: Pos| {
+            let end1 = motion::forward_word(&text, from);
+            let start1 = motion::backward_word(&text, end1);
+            let end2 = motion::forward_word(&text, end1);
+            let start2 = motion::backward_word(&text, end2);
+            (start2 >= end1 && end2 > start2).then_some([start1, end1, start2, end2])
+        };
+        let start = motion::backward_word(&text, textarea.cursor());
+        let Some([start1, end1, start2, end2]) =
+            words(start).or_else(|| words(motion::backward_word(&text, start)))
+        else {
+            return;
+        };
+
+        let first = motion::text_in(&text, Range::chars(start1, end1));
+        let between = motion::text_in(&text, Range::chars(end1, start2));
+        let second = motion::text_in(&text, Range::chars(start2, end2));
+        cut(textarea, start1, end2);
+        textarea.insert_str(format!("{second}{between}{first}"));
+    }
+
+    /// Change the case of the text from the cursor to the end of the word and move past it.
+    fn change_word(&mut self, textarea: &mut TextArea<'_>, change: fn(&str) -> String) {
+        self.deactivate(textarea);
+        let cursor = textarea.cursor();
+        let end = motion::forward_word(&lines(textarea), cursor);
+        Self::replace(textarea, cursor, end, change);
+    }
+
+    fn change_region(&mut self, textarea: &mut TextArea<'_>, change: fn(&str) -> String) {
+        let Some(mark) = self.mark else {
+            return;
+        };
+        let cursor = textarea.cursor();
+        self.deactivate(textarea);
+        let (start, end) = order(mark, cursor);
+        Self::replace(textarea, start, end, change);
+        jump(textarea, cursor);
+    }
+
+    fn replace(textarea: &mut TextArea<'_>, start: Pos, end: Pos, change: fn(&str) -> String) {
+        let text = motion::text_in(&lines(textarea), Range::chars(start, end));
+        cut(textarea, start, end);
+        textarea.insert_str(change(&text));
+    }
+}
+
+/// Upper case the first letter of each word and lower case the rest, like `M-c`.
+fn capitalize(text: &str) -> String {
+    let mut start = true;
+    text.chars()
+        .flat_map(|c| {
+            let changed: Vec<char> = if start {
+                c.to_uppercase().collect()
+            } else {
+                c.to_lowercase().collect()
+            };
+            start = !c.is_alphanumeric();
+            changed
+        })
+        .collect()
+}
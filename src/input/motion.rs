@@ -0,0 +1,475 @@
+//! Where Vim motions and text objects go in a buffer, worked out on the lines of a `TextArea`
+//! split into characters.
+
+use regex::Regex;
+
+/// A row and a column, in characters.
+pub type Pos = (usize, usize);
+
+/// A stretch of the buffer an operator works on.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Range {
+    pub start: Pos,
+    /// Exclusive. For a linewise range only the rows matter and they're inclusive.
+    pub end: Pos,
+    pub linewise: bool,
+}
+
+impl Range {
+    #[must_use]
+    pub const fn chars(start: Pos, end: Pos) -> Self {
+        Self {
+            start,
+            end,
+            linewise: false,
+        }
+    }
+
+    #[must_use]
+    pub const fn lines(first: usize, last: usize) -> Self {
+        Self {
+            start: (first, 0),
+            end: (last, 0),
+            linewise: true,
+        }
+    }
+}
+
+/// The character at `pos`, with the end of each line read as a newline.
+fn char_at(text: &[Vec<char>], (row, col): Pos) -> char {
+    text[row].get(col).copied().unwrap_or('\n')
+}
+
+/// Blanks are 0, punctuation 1 and word characters 2. A WORD is anything that isn't blank.
+fn class(c: char, big: bool) -> u8 {
+    if c.is_whitespace() {
+        0
+    } else if big || c.is_alphanumeric() || c == '_' {
+        2
+    } else {
+        1
+    }
+}
+
+fn next_pos(text: &[Vec<char>], (row, col): Pos) -> Option<Pos> {
+    if col < text[row].len() {
+        Some((row, col + 1))
+    } else if row + 1 < text.len() {
+        Some((row + 1, 0))
+    } else {
+        None
+    }
+}
+
+fn prev_pos(text: &[Vec<char>], (row, col): Pos) -> Option<Pos> {
+    if col > 0 {
+        Some((row, col - 1))
+    } else if row > 0 {
+        Some((row - 1, text[row - 1].len()))
+    } else {
+        None
+    }
+}
+
+fn is_empty_line(text: &[Vec<char>], (row, col): Pos) -> bool {
+    col == 0 && text[row].is_empty()
+}
+
+/// The end of the last line.
+#[must_use]
+pub fn end(text: &[Vec<char>]) -> Pos {
+    let row = text.len() - 1;
+    (row, text[row].len())
+}
+
+#[must_use]
+pub fn first_non_blank(text: &[Vec<char>], row: usize) -> usize {
+    text[row]
+        .iter()
+        .position(|c| !c.is_whitespace())
+        .unwrap_or(0)
+}
+
+/// The start of the next word, like `w`. Empty lines count as words.
+#[must_use]
+pub fn word_forward(text: &[Vec<char>], pos: Pos, big: bool) -> Pos {
+    let mut p = pos;
+    let start = class(char_at(text, p), big);
+    if start != 0 {
+        while class(char_at(text, p), big) == start {
+            match next_pos(text, p) {
+                Some(next) => p = next,
+                None => return end(text),
+            }
+        }
+    }
+    while class(char_at(text, p), big) == 0 {
+        if p != pos && is_empty_line(text, p) {
+            return p;
+        }
+        match next_pos(text, p) {
+            Some(next) => p = next,
+            None => return end(text),
+        }
+    }
+    p
+}
+
+/// The end of the word, or of the next one if already at the end, like `e`.
+#[must_use]
+pub fn word_end(text: &[Vec<char>], pos: Pos, big: bool) -> Pos {
+    let Some(mut p) = next_pos(text, pos) else {
+        return pos;
+    };
+    while class(char_at(text, p), big) == 0 {
+        match next_pos(text, p) {
+            Some(next) => p = next,
+            None => return pos,
+        }
+    }
+    let word = class(char_at(text, p), big);
+    while let Some(next) = next_pos(text, p) {
+        if class(char_at(text, next), big) != word {
+            break;
+        }
+        p = next;
+    }
+    p
+}
+
+/// The start of the word, or of the one before if already at the start, like `b`.
+#[must_use]
+pub fn word_back(text: &[Vec<char>], pos: Pos, big: bool) -> Pos {
+    let Some(mut p) = prev_pos(text, pos) else {
+        return pos;
+    };
+    while class(char_at(text, p), big) == 0 {
+        if is_empty_line(text, p) {
+            return p;
+        }
+        match prev_pos(text, p) {
+            Some(prev) => p = prev,
+            None => return (0, 0),
+        }
+    }
+    let word = class(char_at(text, p), big);
+    while let Some(prev) = prev_pos(text, p) {
+        if class(char_at(text, prev), big) != word {
+            break;
+        }
+        p = prev;
+    }
+    p
+}
+
+/// Just past the end of the next word, like `M-f` in Emacs. Only letters, digits and `_` make
+/// up words here.
+#[must_use]
+pub fn forward_word(text: &[Vec<char>], pos: Pos) -> Pos {
+    let mut p = pos;
+    for in_word in [false, true] {
+        while (class(char_at(text, p), false) == 2) == in_word {
+            match next_pos(text, p) {
+                Some(next) => p = next,
+                None => return p,
+            }
+        }
+    }
+    p
+}
+
+/// The start of the word before the cursor, like `M-b` in Emacs.
+#[must_use]
+pub fn backward_word(text: &[Vec<char>], pos: Pos) -> Pos {
+    let mut p = pos;
+    for in_word in [false, true] {
+        while let Some(prev) = prev_pos(text, p) {
+            if (class(char_at(text, prev), false) == 2) != in_word {
+                break;
+            }
+            p = prev;
+        }
+    }
+    p
+}
+
+/// The column of the `count`th `c` after (or before) the cursor on its line.
+#[must_use]
+pub fn find_char(
+    text: &[Vec<char>],
+    (row, col): Pos,
+    c: char,
+    forward: bool,
+    count: usize,
+) -> Option<usize> {
+    let line = &text[row];
+    let mut found = None;
+    let mut from = col;
+    for _ in 0..count {
+        let next = if forward {
+            line.iter()
+                .enumerate()
+                .skip(from + 1)
+                .find(|(_, &x)| x == c)
+                .map(|(i, _)| i)
+        } else {
+            line[..from.min(line.len())].iter().rposition(|&x| x == c)
+        };
+        from = next?;
+        found = Some(from);
+    }
+    found
+}
+
+const PAIRS: [(char, char); 3] = [('(', ')'), ('[', ']'), ('{', '}')];
+
+/// The bracket matching the first one at or after the cursor on its line, like `%`.
+#[must_use]
+pub fn matching_bracket(text: &[Vec<char>], (row, col): Pos) -> Option<Pos> {
+    let line = &text[row];
+    let (start, bracket) = line.iter().enumerate().skip(col).find(|(_, c)| {
+        PAIRS
+            .iter()
+            .any(|&(open, close)| **c == open || **c == close)
+    })?;
+    let &(open, close) = PAIRS
+        .iter()
+        .find(|&&(open, close)| *bracket == open || *bracket == close)?;
+    let forward = *bracket == open;
+
+    let mut depth = 0;
+    let mut p = (row, start);
+    loop {
+        p = if forward {
+            next_pos(text, p)?
+        } else {
+            prev_pos(text, p)?
+        };
+        let c = char_at(text, p);
+        if c == *bracket {
+            depth += 1;
+        } else if c == open || c == close {
+            if depth == 0 {
+                return Some(p);
+            }
+            depth -= 1;
+        }
+    }
+}
+
+/// Where the `count`th match of `regex` after (or before) the cursor starts, wrapping around the
+/// buffer.
+#[must_use]
+pub fn search(
+    text: &[Vec<char>],
+    pos: Pos,
+    regex: &Regex,
+    forward: bool,
+    count: usize,
+) -> Option<Pos> {
+    let lines: Vec<String> = text.iter().map(|line| line.iter().collect()).collect();
+    // The column each match starts at, by row
+    let matches: Vec<Vec<usize>> = lines
+        .iter()
+        .map(|line| {
+            regex
+                .find_iter(line)
+                .filter(|found| !found.is_empty())
+                .map(|found| line[..found.start()].chars().count())
+                .collect()
+        })
+        .collect();
+    if matches.iter().all(Vec::is_empty) {
+        return None;
+    }
+
+    let mut p = pos;
+    for _ in 0..count {
+        p = if forward {
+            let rest = matches[p.0].iter().find(|&&col| col > p.1);
+            rest.map(|&col| (p.0, col)).or_else(|| {
+                (1..=text.len()).find_map(|i| {
+                    let row = (p.0 + i) % text.len();
+                    matches[row].first().map(|&col| (row, col))
+                })
+            })?
+        } else {
+            let rest = matches[p.0].iter().rev().find(|&&col| col < p.1);
+            rest.map(|&col| (p.0, col)).or_else(|| {
+                (1..=text.len()).find_map(|i| {
+                    let row = (p.0 + text.len() * 2 - i) % text.len();
+                    matches[row].last().map(|&col| (row, col))
+                })
+            })?
+        };
+    }
+    Some(p)
+}
+
+/// The word (or WORD) under the cursor, like `iw`. Around it also takes the blanks after the
+/// word, or before it if there aren't any after.
+#[must_use]
+pub fn word_object(text: &[Vec<char>], (row, col): Pos, big: bool, around: bool) -> Option<Range> {
+    let line = &text[row];
+    if line.is_empty() {
+        return None;
+    }
+    let col = col.min(line.len() - 1);
+    let word = class(line[col], big);
+    let mut start = col;
+    while start > 0 && class(line[start - 1], big) == word {
+        start -= 1;
+    }
+    let mut end = col + 1;
+    while end < line.len() && class(line[end], big) == word {
+        end += 1;
+    }
+
+    if around {
+        if word == 0 {
+            // On blanks, take the word after them too
+            if end < line.len() {
+                let next = class(line[end], big);
+                while end < line.len() && class(line[end], big) == next {
+                    end += 1;
+                }
+            }
+        } else {
+            let mut after = end;
+            while after < line.len() && class(line[after], big) == 0 {
+                after += 1;
+            }
+            if after > end {
+                end = after;
+            } else {
+                while start > 0 && class(line[start - 1], big) == 0 {
+                    start -= 1;
+                }
+            }
+        }
+    }
+    Some(Range::chars((row, start), (row, end)))
+}
+
+/// The quoted string around the cursor, or the next one on its line, like `i"`.
+#[must_use]
+pub fn quote_object(
+    text: &[Vec<char>],
+    (row, col): Pos,
+    quote: char,
+    around: bool,
+) -> Option<Range> {
+    let line = &text[row];
+    let quotes: Vec<usize> = (0..line.len())
+        .filter(|&i| line[i] == quote && (i == 0 || line[i - 1] != '\\'))
+        .collect();
+    let (open, close) = quotes
+        .chunks_exact(2)
+        .map(|pair| (pair[0], pair[1]))
+        .find(|&(open, close)| open <= col && col <= close)
+        .or_else(|| {
+            quotes
+                .chunks_exact(2)
+                .map(|pair| (pair[0], pair[1]))
+                .find(|&(open, _)| open > col)
+        })?;
+
+    if !around {
+        return Some(Range::chars((row, open + 1), (row, close)));
+    }
+    let (mut start, mut end) = (open, close + 1);
+    while end < line.len() && line[end].is_whitespace() {
+        end += 1;
+    }
+    if end == close + 1 {
+        while start > 0 && line[start - 1].is_whitespace() {
+            start -= 1;
+        }
+    }
+    Some(Range::chars((row, start), (row, end)))
+}
+
+/// The block between `open` and `close` around the cursor, like `i(`. When the inside of the
+/// block is whole lines it's taken linewise, so `ci{` keeps the braces on their own lines.
+#[must_use]
+pub fn bracket_object(
+    text: &[Vec<char>],
+    pos: Pos,
+    open: char,
+    close: char,
+    around: bool,
+) -> Option<Range> {
+    let mut start = pos;
+    let mut depth = 0;
+    loop {
+        let c = char_at(text, start);
+        if c == close && start != pos {
+            depth += 1;
+        } else if c == open {
+            if depth == 0 {
+                break;
+            }
+            depth -= 1;
+        }
+        start = prev_pos(text, start)?;
+    }
+
+    let mut end = start;
+    let mut depth = 0;
+    loop {
+        end = next_pos(text, end)?;
+        let c = char_at(text, end);
+        if c == open {
+            depth += 1;
+        } else if c == close {
+            if depth == 0 {
+                break;
+            }
+            depth -= 1;
+        }
+    }
+
+    if around {
+        return Some(Range::chars(start, (end.0, end.1 + 1)));
+    }
+    let inner = next_pos(text, start)?;
+    let whole_lines =
+        inner.1 == text[inner.0].len() && text[end.0][..end.1].iter().all(|c| c.is_whitespace());
+    if whole_lines && end.0 > inner.0 + 1 {
+        return Some(Range::lines(inner.0 + 1, end.0 - 1));
+    }
+    Some(Range::chars(inner, end))
+}
+
+/// The text in `range`. Linewise text ends with a newline.
+#[must_use]
+pub fn text_in(text: &[Vec<char>], range: Range) -> String {
+    if range.linewise {
+        let mut lines: String = text[range.start.0..=range.end.0]
+            .iter()
+            .map(|line| line.iter().collect::<String>())
+            .collect::<Vec<_>>()
+            .join("\n");
+        lines.push('\n');
+        return lines;
+    }
+
+    let (start, end) = (range.start, range.end);
+    if start.0 == end.0 {
+        let line = &text[start.0];
+        return line[start.1.min(line.len())..end.1.min(line.len())]
+            .iter()
+            .collect();
+    }
+    let mut result: String = text[start.0][start.1.min(text[start.0].len())..]
+        .iter()
+        .collect();
+    for line in &text[start.0 + 1..end.0] {
+        result.push('\n');
+        result.extend(line);
+    }
+    result.push('\n');
+    let last = &text[end.0];
+    result.extend(&last[..end.1.min(last.len())]);
+    result
+}
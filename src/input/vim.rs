@@ -0,0 +1,1033 @@
+use core::fmt;
+use std::collections::HashMap;
+
+use clippers::Clipboard;
+use log::warn;
+use regex::Regex;
+use tui_textarea::{CursorMove, Input, Key, Scrolling, TextArea};
+
+use super::motion::{self, Pos, Range};
+use super::{cut, jump, lines, order};
+
+/// The longest count taken, so a stray run of digits can't hang the editor.
+const MAX_COUNT: usize = 9999;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Mode {
+    #[default]
+    Normal,
+    Insert,
+    Visual,
+    Operator(char),
+    /// Typing a pattern after `/` or `?`
+    Search,
+}
+
+impl fmt::Display for Mode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Normal => write!(f, "Normal"),
+            Self::Insert => write!(f, "Insert"),
+            Self::Visual => write!(f, "Visual"),
+            Self::Operator(c) => write!(f, "Operator ({c})"),
+            Self::Search => write!(f, "Search"),
+        }
+    }
+}
+
+pub enum Transition {
+    Nop,
+    Mode(Mode),
+}
+
+/// A key of a Normal or Visual mode command. The arrows and the like read as the keys they
+/// stand for.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Token {
+    Char(char),
+    Ctrl(char),
+    Esc,
+    Other,
+}
+
+const fn token(input: &Input) -> Token {
+    match input.key {
+        Key::Char(c) if input.ctrl => Token::Ctrl(c),
+        Key::Char(c) if !input.alt => Token::Char(c),
+        Key::Left | Key::Backspace => Token::Char('h'),
+        Key::Right => Token::Char('l'),
+        Key::Up => Token::Char('k'),
+        Key::Down | Key::Enter => Token::Char('j'),
+        Key::Home => Token::Char('0'),
+        Key::End => Token::Char('$'),
+        Key::Delete => Token::Char('x'),
+        Key::Esc => Token::Esc,
+        _ => Token::Other,
+    }
+}
+
+const fn key(c: char) -> Input {
+    Input {
+        key: Key::Char(c),
+        ctrl: false,
+        alt: false,
+        shift: false,
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Find {
+    c: char,
+    forward: bool,
+    /// Stop next to the character, like `t`
+    till: bool,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Motion {
+    Left,
+    Right,
+    Up,
+    Down,
+    /// `w`, or `W` when `true`
+    WordForward(bool),
+    WordBack(bool),
+    WordEnd(bool),
+    LineStart,
+    FirstNonBlank,
+    LineEnd,
+    /// `gg`, or `G` when `true`. A count picks the line.
+    Goto(bool),
+    Find(Find),
+    /// `;`, or `,` when `true`
+    RepeatFind(bool),
+    Bracket,
+    /// `n`, or `N` when `true`
+    SearchNext(bool),
+}
+
+impl Motion {
+    const fn linewise(self) -> bool {
+        matches!(self, Self::Up | Self::Down | Self::Goto(_))
+    }
+
+    /// Whether an operator takes the character the motion lands on too.
+    const fn inclusive(self) -> bool {
+        matches!(
+            self,
+            Self::WordEnd(_) | Self::LineEnd | Self::Find(_) | Self::RepeatFind(_) | Self::Bracket
+        )
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Target {
+    Motion(Motion),
+    /// A text object, by its key, and whether it's the `a` one rather than the `i` one
+    Object(char, bool),
+    /// The operator doubled, like `dd`
+    Line,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Kind {
+    Move(Motion),
+    Operate(char, Target),
+    Edit(char),
+    Replace(char),
+    Ctrl(char),
+    Escape,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Command {
+    register: Option<char>,
+    count: Option<usize>,
+    kind: Kind,
+}
+
+impl Command {
+    /// Whether `.` repeats the command.
+    fn is_change(&self) -> bool {
+        match self.kind {
+            Kind::Operate(op, _) => op != 'y',
+            Kind::Edit(c) => "xXsSDCpPiaIAoO~J".contains(c),
+            Kind::Replace(_) => true,
+            _ => false,
+        }
+    }
+}
+
+enum Parse {
+    /// More keys are needed, for the operator if there is one
+    Incomplete(Option<char>),
+    Invalid,
+    /// `/` or `?`, forward or not, to search for the pattern typed next
+    Search(bool),
+    Done(Command),
+}
+
+enum Step<T> {
+    Done(T),
+    Incomplete,
+    No,
+}
+
+fn is_register(c: char) -> bool {
+    c.is_ascii_alphanumeric() || "\"+*_-".contains(c)
+}
+
+fn is_object(c: char) -> bool {
+    "wW\"'`()b[]{}B<>".contains(c)
+}
+
+/// A count at the start of `tokens`, and how many tokens it takes. A `0` on its own is a motion.
+fn parse_count(tokens: &[Token]) -> (Option<usize>, usize) {
+    let mut count: Option<usize> = None;
+    let mut used = 0;
+    for token in tokens {
+        match *token {
+            Token::Char(digit @ '0'..='9') if count.is_some() || digit != '0' => {
+                let digit = digit.to_digit(10).unwrap_or_default() as usize;
+                count = Some((count.unwrap_or(0) * 10 + digit).min(MAX_COUNT));
+                used += 1;
+            }
+            _ => break,
+        }
+    }
+    (count, used)
+}
+
+/// The count for `2d3w` is six.
+fn multiply(a: Option<usize>, b: Option<usize>) -> Option<usize> {
+    match (a, b) {
+        (None, None) => None,
+        _ => Some((a.unwrap_or(1) * b.unwrap_or(1)).min(MAX_COUNT)),
+    }
+}
+
+fn parse_motion(tokens: &[Token]) -> Step<Motion> {
+    let Some(&first) = tokens.first() else {
+        return Step::Incomplete;
+    };
+    let Token::Char(c) = first else {
+        return Step::No;
+    };
+    let motion = match c {
+        'h' => Motion::Left,
+        'l' | ' ' => Motion::Right,
+        'j' => Motion::Down,
+        'k' => Motion::Up,
+        'w' | 'W' => Motion::WordForward(c == 'W'),
+        'b' | 'B' => Motion::WordBack(c == 'B'),
+        'e' | 'E' => Motion::WordEnd(c == 'E'),
+        '0' => Motion::LineStart,
+        '^' => Motion::FirstNonBlank,
+        '$' => Motion::LineEnd,
+        'G' => Motion::Goto(true),
+        '%' => Motion::Bracket,
+        ';' | ',' => Motion::RepeatFind(c == ','),
+        'n' | 'N' => Motion::SearchNext(c == 'N'),
+        'g' | 'f' | 'F' | 't' | 'T' => {
+            return match (c, tokens.get(1)) {
+                (_, None) => Step::Incomplete,
+                ('g', Some(Token::Char('g'))) => Step::Done(Motion::Goto(false)),
+                ('g', _) => Step::No,
+                (_, Some(&Token::Char(target))) => Step::Done(Motion::Find(Find {
+                    c: target,
+                    forward: c == 'f' || c == 't',
+                    till: c == 't' || c == 'T',
+                })),
+                _ => Step::No,
+            };
+        }
+        _ => return Step::No,
+    };
+    Step::Done(motion)
+}
+
+/// Parse `["x][count](operator[count](motion|text object|operator)|motion|command)`.
+fn parse(tokens: &[Token], visual: bool) -> Parse {
+    let mut i = 0;
+    let mut register = None;
+    if tokens.first() == Some(&Token::Char('"')) {
+        match tokens.get(1) {
+            None => return Parse::Incomplete(None),
+            Some(&Token::Char(c)) if is_register(c) => register = Some(c),
+            Some(_) => return Parse::Invalid,
+        }
+        i = 2;
+    }
+    let (count, used) = parse_count(&tokens[i..]);
+    i += used;
+    let done = |kind| {
+        Parse::Done(Command {
+            register,
+            count,
+            kind,
+        })
+    };
+
+    let c = match tokens.get(i) {
+        None => return Parse::Incomplete(None),
+        Some(Token::Esc) => return done(Kind::Escape),
+        Some(&Token::Ctrl(c)) => return done(Kind::Ctrl(c)),
+        Some(Token::Other) => return Parse::Invalid,
+        Some(&Token::Char(c)) => c,
+    };
+
+    // Visual mode already has what the operator works on
+    if matches!(c, 'd' | 'c' | 'y') && !visual {
+        let rest = &tokens[i + 1..];
+        let (inner, used) = parse_count(rest);
+        let rest = &rest[used..];
+        let operate = |target| {
+            Parse::Done(Command {
+                register,
+                count: multiply(count, inner),
+                kind: Kind::Operate(c, target),
+            })
+        };
+        return match rest.first() {
+            None => Parse::Incomplete(Some(c)),
+            Some(&Token::Char(op)) if op == c => operate(Target::Line),
+            Some(&Token::Char(around @ ('i' | 'a'))) => match rest.get(1) {
+                None => Parse::Incomplete(Some(c)),
+                Some(&Token::Char(object)) if is_object(object) => {
+                    operate(Target::Object(object, around == 'a'))
+                }
+                Some(_) => Parse::Invalid,
+            },
+            Some(&Token::Char(search @ ('/' | '?'))) => Parse::Search(search == '/'),
+            Some(_) => match parse_motion(rest) {
+                Step::Done(motion) => operate(Target::Motion(motion)),
+                Step::Incomplete => Parse::Incomplete(Some(c)),
+                Step::No => Parse::Invalid,
+            },
+        };
+    }
+
+    match c {
+        '/' | '?' => return Parse::Search(c == '/'),
+        'r' => {
+            return match tokens.get(i + 1) {
+                None => Parse::Incomplete(None),
+                Some(&Token::Char(with)) => done(Kind::Replace(with)),
+                Some(_) => Parse::Invalid,
+            }
+        }
+        _ => {}
+    }
+    match parse_motion(&tokens[i..]) {
+        Step::Done(motion) => done(Kind::Move(motion)),
+        Step::Incomplete => Parse::Incomplete(None),
+        Step::No if "dcyxXsSDCYpPuiaIAoOvV~J.".contains(c) => done(Kind::Edit(c)),
+        Step::No => Parse::Invalid,
+    }
+}
+
+/// The text of a register, and whether it's whole lines.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+struct Register {
+    text: String,
+    linewise: bool,
+}
+
+/// The Vim emulation of the input editor. Normal mode commands take a register, counts, an
+/// operator and a motion or text object, and the last change can be repeated with `.`.
+#[derive(Debug, Clone, Default)]
+pub struct Vim {
+    pub mode: Mode,
+    /// The keys of the command being typed
+    keys: Vec<Input>,
+    registers: HashMap<char, Register>,
+    /// The keys of the last change, for `.`
+    last_change: Vec<Input>,
+    /// The keys of the change going on in Insert mode
+    change: Option<Vec<Input>>,
+    /// Whether `.` is playing the last change back
+    repeating: bool,
+    /// Where Visual mode started, and whether it's linewise
+    visual: Option<(Pos, bool)>,
+    /// The pattern being typed in Search mode
+    pattern: String,
+    search_forward: bool,
+    /// The mode to go back to after typing the pattern
+    before_search: Mode,
+    /// The last pattern searched for and whether it was forward
+    last_search: Option<(String, bool)>,
+    last_find: Option<Find>,
+}
+
+impl fmt::Display for Vim {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.mode == Mode::Search {
+            let prefix = if self.search_forward { '/' } else { '?' };
+            return write!(f, "{prefix}{}", self.pattern);
+        }
+        write!(f, "{}", self.mode)?;
+        if !self.keys.is_empty() {
+            write!(f, " ")?;
+            for input in &self.keys {
+                match token(input) {
+                    Token::Char(c) => write!(f, "{c}")?,
+                    Token::Ctrl(c) => write!(f, "^{c}")?,
+                    Token::Esc | Token::Other => {}
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+impl Vim {
+    #[must_use]
+    pub fn new(mode: Mode) -> Self {
+        Self {
+            mode,
+            ..Self::default()
+        }
+    }
+
+    pub fn transition(&mut self, input: Input, textarea: &mut TextArea<'_>) -> Transition {
+        if input.key == Key::Null {
+            return Transition::Nop;
+        }
+
+        let mode = self.mode;
+        match self.mode {
+            Mode::Insert => self.insert(input, textarea),
+            Mode::Search => self.search(&input, textarea),
+            Mode::Normal | Mode::Visual | Mode::Operator(_) => self.command(input, textarea),
+        }
+        if self.mode == mode {
+            Transition::Nop
+        } else {
+            Transition::Mode(self.mode)
+        }
+    }
+
+    fn insert(&mut self, input: Input, textarea: &mut TextArea<'_>) {
+        if let Some(change) = &mut self.change {
+            change.push(input.clone());
+        }
+        match input {
+            Input { key: Key::Esc, .. } => {
+                if let Some(change) = self.change.take() {
+                    self.last_change = change;
+                }
+                self.mode = Mode::Normal;
+                let (row, col) = textarea.cursor();
+                if col > 0 {
+                    jump(textarea, (row, col - 1));
+                }
+            }
+            Input { key: Key::Up, .. } => textarea.move_cursor(CursorMove::Up),
+            Input { key: Key::Down, .. } => textarea.move_cursor(CursorMove::Down),
+            Input { key: Key::Left, .. } => textarea.move_cursor(CursorMove::Back),
+            Input {
+                key: Key::Right, ..
+            } => textarea.move_cursor(CursorMove::Forward),
+            Input {
+                key: Key::Char('w'),
+                ctrl: true,
+                ..
+            } => {
+                // Delete one word back
+                textarea.delete_word();
+            }
+            input => {
+                textarea.input_without_shortcuts(input); // Don't use the default mappings
+            }
+        }
+    }
+
+    /// Type the pattern after `/` or `?`. Enter searches for it like `n`.
+    fn search(&mut self, input: &Input, textarea: &mut TextArea<'_>) {
+        match input.key {
+            Key::Esc => self.cancel_search(),
+            Key::Backspace if self.pattern.is_empty() => self.cancel_search(),
+            Key::Backspace => {
+                self.pattern.pop();
+            }
+            Key::Enter => {
+                if self.pattern.is_empty() {
+                    // An empty pattern searches for the last one again
+                    if let Some((_, forward)) = &mut self.last_search {
+                        *forward = self.search_forward;
+                    }
+                } else {
+                    let pattern = std::mem::take(&mut self.pattern);
+                    self.last_search = Some((pattern, self.search_forward));
+                }
+                self.mode = self.before_search;
+                self.command(key('n'), textarea);
+            }
+            Key::Char(c) if !input.ctrl && !input.alt => self.pattern.push(c),
+            _ => {}
+        }
+    }
+
+    fn cancel_search(&mut self) {
+        self.pattern.clear();
+        self.keys.clear();
+        self.mode = if self.before_search == Mode::Visual {
+            Mode::Visual
+        } else {
+            Mode::Normal
+        };
+    }
+
+    fn command(&mut self, input: Input, textarea: &mut TextArea<'_>) {
+        let visual = self.mode == Mode::Visual;
+        self.keys.push(input);
+        let tokens: Vec<Token> = self.keys.iter().map(token).collect();
+        match parse(&tokens, visual) {
+            Parse::Incomplete(operator) => {
+                if !visual {
+                    self.mode = operator.map_or(Mode::Normal, Mode::Operator);
+                }
+            }
+            Parse::Invalid => {
+                self.keys.clear();
+                if !visual {
+                    self.mode = Mode::Normal;
+                }
+            }
+            Parse::Search(forward) => {
+                self.keys.pop();
+                self.pattern.clear();
+                self.search_forward = forward;
+                self.before_search = self.mode;
+                self.mode = Mode::Search;
+            }
+            Parse::Done(command) => {
+                let keys = std::mem::take(&mut self.keys);
+                if !visual {
+                    self.mode = Mode::Normal;
+                }
+                let change = !visual && command.is_change();
+                self.execute(command, textarea);
+                if change && !self.repeating {
+                    if self.mode == Mode::Insert {
+                        self.change = Some(keys);
+                    } else {
+                        self.last_change = keys;
+                    }
+                }
+                if self.mode != Mode::Insert {
+                    Self::clamp(textarea);
+                }
+            }
+        }
+    }
+
+    /// Keep the cursor on a character, as it can't sit past the end of the line outside Insert
+    /// mode.
+    fn clamp(textarea: &mut TextArea<'_>) {
+        let (row, col) = textarea.cursor();
+        let len = textarea.lines()[row].chars().count();
+        if len > 0 && col >= len {
+            jump(textarea, (row, len - 1));
+        }
+    }
+
+    fn execute(&mut self, command: Command, textarea: &mut TextArea<'_>) {
+        let Command {
+            register,
+            count,
+            kind,
+        } = command;
+        let text = lines(textarea);
+        let cursor = textarea.cursor();
+        match kind {
+            Kind::Escape => {
+                if self.mode == Mode::Visual {
+                    self.leave_visual(textarea);
+                }
+            }
+            Kind::Ctrl(c) => Self::control(c, count.unwrap_or(1), textarea),
+            Kind::Move(motion) => {
+                if let Some(target) = self.target(motion, count, &text, cursor) {
+                    jump(textarea, target);
+                }
+            }
+            Kind::Operate(op, target) => {
+                if let Some(range) = self.range(op, target, count, &text, cursor) {
+                    self.operate(op, range, register, &text, textarea);
+                }
+            }
+            Kind::Replace(with) => {
+                let n = count.unwrap_or(1);
+                if cursor.1 + n <= text[cursor.0].len() {
+                    cut(textarea, cursor, (cursor.0, cursor.1 + n));
+                    textarea.insert_str(with.to_string().repeat(n));
+                    jump(textarea, (cursor.0, cursor.1 + n - 1));
+                }
+            }
+            Kind::Edit(c) if self.mode == Mode::Visual => {
+                self.visual_edit(c, register, &text, textarea);
+            }
+            Kind::Edit(c) => self.edit(c, register, count, &text, textarea),
+        }
+    }
+
+    fn control(c: char, count: usize, textarea: &mut TextArea<'_>) {
+        match c {
+            'r' => {
+                for _ in 0..count {
+                    textarea.redo();
+                }
+            }
+            'e' => textarea.scroll((1, 0)),
+            'y' => textarea.scroll((-1, 0)),
+            'd' => textarea.scroll(Scrolling::HalfPageDown),
+            'u' => textarea.scroll(Scrolling::HalfPageUp),
+            'f' => textarea.scroll(Scrolling::PageDown),
+            'b' => textarea.scroll(Scrolling::PageUp),
+            _ => {}
+        }
+    }
+
+    /// Where `motion` goes from `cursor`, if anywhere.
+    fn target(
+        &mut self,
+        motion: Motion,
+        count: Option<usize>,
+        text: &[Vec<char>],
+        (row, col): Pos,
+    ) -> Option<Pos> {
+        let n = count.unwrap_or(1);
+        let last = text.len() - 1;
+        let repeat = |step: &dyn Fn(Pos) -> Pos| (0..n).fold((row, col), |pos, _| step(pos));
+        let target = match motion {
+            Motion::Left => (row, col.saturating_sub(n)),
+            Motion::Right => (row, (col + n).min(text[row].len())),
+            Motion::Up => (row.saturating_sub(n), col),
+            Motion::Down => ((row + n).min(last), col),
+            Motion::WordForward(big) => repeat(&|pos| motion::word_forward(text, pos, big)),
+            Motion::WordBack(big) => repeat(&|pos| motion::word_back(text, pos, big)),
+            Motion::WordEnd(big) => repeat(&|pos| motion::word_end(text, pos, big)),
+            Motion::LineStart => (row, 0),
+            Motion::FirstNonBlank => (row, motion::first_non_blank(text, row)),
+            Motion::LineEnd => {
+                let row = (row + n - 1).min(last);
+                (row, text[row].len().saturating_sub(1))
+            }
+            Motion::Goto(bottom) => {
+                let default = if bottom { last } else { 0 };
+                let row = count.map_or(default, |line| line.saturating_sub(1).min(last));
+                (row, motion::first_non_blank(text, row))
+            }
+            Motion::Find(find) => {
+                self.last_find = Some(find);
+                Self::find(find, n, text, (row, col), false)?
+            }
+            Motion::RepeatFind(reverse) => {
+                let mut find = self.last_find?;
+                find.forward ^= reverse;
+                Self::find(find, n, text, (row, col), true)?
+            }
+            Motion::Bracket => motion::matching_bracket(text, (row, col))?,
+            Motion::SearchNext(reverse) => {
+                let (pattern, forward) = self.last_search.as_ref()?;
+                let regex = Regex::new(pattern).ok()?;
+                motion::search(text, (row, col), &regex, *forward ^ reverse, n)?
+            }
+        };
+        Some(target)
+    }
+
+    fn find(
+        find: Find,
+        count: usize,
+        text: &[Vec<char>],
+        (row, col): Pos,
+        repeat: bool,
+    ) -> Option<Pos> {
+        // Repeating a till would find the character next to the cursor again, so skip it
+        let from = match (repeat && find.till, find.forward) {
+            (false, _) => col,
+            (true, true) => col + 1,
+            (true, false) => col.saturating_sub(1),
+        };
+        let found = motion::find_char(text, (row, from), find.c, find.forward, count)?;
+        let col = match (find.till, find.forward) {
+            (false, _) => found,
+            (true, true) => found - 1,
+            (true, false) => found + 1,
+        };
+        Some((row, col))
+    }
+
+    /// What an operator works on from `cursor`.
+    fn range(
+        &mut self,
+        op: char,
+        target: Target,
+        count: Option<usize>,
+        text: &[Vec<char>],
+        cursor: Pos,
+    ) -> Option<Range> {
+        let motion = match target {
+            Target::Line => {
+                let last = (cursor.0 + count.unwrap_or(1) - 1).min(text.len() - 1);
+                return Some(Range::lines(cursor.0, last));
+            }
+            Target::Object(object, around) => {
+                return match object {
+                    'w' | 'W' => motion::word_object(text, cursor, object == 'W', around),
+                    '"' | '\'' | '`' => motion::quote_object(text, cursor, object, around),
+                    '(' | ')' | 'b' => motion::bracket_object(text, cursor, '(', ')', around),
+                    '[' | ']' => motion::bracket_object(text, cursor, '[', ']', around),
+                    '{' | '}' | 'B' => motion::bracket_object(text, cursor, '{', '}', around),
+                    '<' | '>' => motion::bracket_object(text, cursor, '<', '>', around),
+                    _ => None,
+                };
+            }
+            Target::Motion(motion) => motion,
+        };
+
+        let to = self.target(motion, count, text, cursor)?;
+        let (start, mut end) = order(cursor, to);
+        if motion.linewise() {
+            return Some(Range::lines(start.0, end.0));
+        }
+        if motion.inclusive() {
+            end.1 = (end.1 + 1).min(text[end.0].len());
+            return Some(Range::chars(start, end));
+        }
+
+        if let Motion::WordForward(_) = motion {
+            // A word motion that runs onto the next line stops at the end of this one
+            if end.0 > start.0 && end.1 <= motion::first_non_blank(text, end.0) {
+                end = (end.0 - 1, text[end.0 - 1].len());
+            }
+            // `cw` on a word changes up to the end of it, like `ce`
+            let on_word = text[cursor.0]
+                .get(cursor.1)
+                .is_some_and(|c| !c.is_whitespace());
+            if op == 'c' && on_word {
+                while end > start && end.1 > 0 && text[end.0][end.1 - 1].is_whitespace() {
+                    end.1 -= 1;
+                }
+            }
+        }
+        Some(Range::chars(start, end))
+    }
+
+    /// Delete, change or yank `range`.
+    fn operate(
+        &mut self,
+        op: char,
+        range: Range,
+        register: Option<char>,
+        text: &[Vec<char>],
+        textarea: &mut TextArea<'_>,
+    ) {
+        self.store(
+            register,
+            motion::text_in(text, range),
+            range.linewise,
+            op == 'y',
+        );
+        let (first, last) = (range.start.0, range.end.0);
+        match op {
+            'y' if range.linewise => {
+                let (row, col) = textarea.cursor();
+                if row > first {
+                    jump(textarea, (first, col));
+                }
+            }
+            'y' => jump(textarea, range.start),
+            'd' if range.linewise => {
+                // Take the newline after the lines, or before them for the last line
+                let end = text.len() - 1;
+                if last < end {
+                    cut(textarea, (first, 0), (last + 1, 0));
+                } else if first > 0 {
+                    cut(
+                        textarea,
+                        (first - 1, text[first - 1].len()),
+                        (last, text[last].len()),
+                    );
+                } else {
+                    cut(textarea, (0, 0), (last, text[last].len()));
+                }
+                let text = lines(textarea);
+                let row = first.min(text.len() - 1);
+                jump(textarea, (row, motion::first_non_blank(&text, row)));
+            }
+            'c' if range.linewise => {
+                cut(textarea, (first, 0), (last, text[last].len()));
+                self.mode = Mode::Insert;
+            }
+            'd' => cut(textarea, range.start, range.end),
+            'c' => {
+                cut(textarea, range.start, range.end);
+                self.mode = Mode::Insert;
+            }
+            _ => {}
+        }
+    }
+
+    fn edit(
+        &mut self,
+        c: char,
+        register: Option<char>,
+        count: Option<usize>,
+        text: &[Vec<char>],
+        textarea: &mut TextArea<'_>,
+    ) {
+        let n = count.unwrap_or(1);
+        let (row, col) = textarea.cursor();
+        let len = text[row].len();
+        let below = (row + n - 1).min(text.len() - 1);
+        match c {
+            'x' | 's' => {
+                let op = if c == 'x' { 'd' } else { 'c' };
+                let range = Range::chars((row, col), (row, (col + n).min(len)));
+                self.operate(op, range, register, text, textarea);
+            }
+            'X' => {
+                let range = Range::chars((row, col.saturating_sub(n)), (row, col));
+                self.operate('d', range, register, text, textarea);
+            }
+            'D' | 'C' => {
+                let op = c.to_ascii_lowercase();
+                let range = Range::chars((row, col), (below, text[below].len()));
+                self.operate(op, range, register, text, textarea);
+            }
+            'S' => self.operate('c', Range::lines(row, below), register, text, textarea),
+            'Y' => self.operate('y', Range::lines(row, below), register, text, textarea),
+            'p' | 'P' => self.put(c == 'P', register, n, textarea),
+            'u' => {
+                for _ in 0..n {
+                    textarea.undo();
+                }
+            }
+            'i' => self.mode = Mode::Insert,
+            'a' => {
+                jump(textarea, (row, (col + 1).min(len)));
+                self.mode = Mode::Insert;
+            }
+            'I' => {
+                jump(textarea, (row, motion::first_non_blank(text, row)));
+                self.mode = Mode::Insert;
+            }
+            'A' => {
+                jump(textarea, (row, len));
+                self.mode = Mode::Insert;
+            }
+            'o' => {
+                textarea.move_cursor(CursorMove::End);
+                textarea.insert_newline();
+                self.mode = Mode::Insert;
+            }
+            'O' => {
+                textarea.move_cursor(CursorMove::Head);
+                textarea.insert_newline();
+                textarea.move_cursor(CursorMove::Up);
+                self.mode = Mode::Insert;
+            }
+            'v' => {
+                textarea.start_selection();
+                self.visual = Some(((row, col), false));
+                self.mode = Mode::Visual;
+            }
+            'V' => {
+                textarea.move_cursor(CursorMove::Head);
+                textarea.start_selection();
+                textarea.move_cursor(CursorMove::End);
+                self.visual = Some(((row, col), true));
+                self.mode = Mode::Visual;
+            }
+            '~' => {
+                let end = (col + n).min(len);
+                let toggled: String = text[row][col.min(end)..end]
+                    .iter()
+                    .flat_map(|c| {
+                        if c.is_uppercase() {
+                            c.to_lowercase().collect::<Vec<_>>()
+                        } else {
+                            c.to_uppercase().collect()
+                        }
+                    })
+                    .collect();
+                cut(textarea, (row, col), (row, end));
+                textarea.insert_str(toggled);
+            }
+            'J' => {
+                for _ in 0..n.max(2) - 1 {
+                    let text = lines(textarea);
+                    if row + 1 >= text.len() {
+                        break;
+                    }
+                    let join = text[row].len();
+                    let next = motion::first_non_blank(&text, row + 1);
+                    cut(textarea, (row, join), (row + 1, next));
+                    if join > 0 && next < text[row + 1].len() {
+                        textarea.insert_char(' ');
+                    }
+                    jump(textarea, (row, join));
+                }
+            }
+            '.' => self.repeat(count, textarea),
+            _ => {}
+        }
+    }
+
+    /// Delete, change or yank the selection.
+    fn visual_edit(
+        &mut self,
+        c: char,
+        register: Option<char>,
+        text: &[Vec<char>],
+        textarea: &mut TextArea<'_>,
+    ) {
+        let Some((start, linewise)) = self.visual else {
+            return;
+        };
+        let (first, last) = order(start, textarea.cursor());
+        let range = if linewise || matches!(c, 'D' | 'X' | 'Y' | 'C' | 'S') {
+            Range::lines(first.0, last.0)
+        } else {
+            Range::chars(first, (last.0, (last.1 + 1).min(text[last.0].len())))
+        };
+        let op = match c {
+            'd' | 'x' | 'D' | 'X' => 'd',
+            'y' | 'Y' => 'y',
+            'c' | 's' | 'C' | 'S' => 'c',
+            'v' | 'V' => {
+                self.leave_visual(textarea);
+                return;
+            }
+            _ => return,
+        };
+        self.leave_visual(textarea);
+        self.operate(op, range, register, text, textarea);
+    }
+
+    fn leave_visual(&mut self, textarea: &mut TextArea<'_>) {
+        textarea.cancel_selection();
+        self.visual = None;
+        self.mode = Mode::Normal;
+    }
+
+    /// Put a register after the cursor, or before it, `count` times.
+    fn put(&self, before: bool, register: Option<char>, count: usize, textarea: &mut TextArea<'_>) {
+        let Some(Register { text, linewise }) = self.register(register) else {
+            return;
+        };
+        textarea.cancel_selection();
+        let (row, col) = textarea.cursor();
+        if linewise {
+            let body = vec![text.strip_suffix('\n').unwrap_or(&text); count].join("\n");
+            let row = if before {
+                jump(textarea, (row, 0));
+                textarea.insert_str(format!("{body}\n"));
+                row
+            } else {
+                textarea.move_cursor(CursorMove::End);
+                textarea.insert_str(format!("\n{body}"));
+                row + 1
+            };
+            let text = lines(textarea);
+            jump(textarea, (row, motion::first_non_blank(&text, row)));
+        } else {
+            if !before && !textarea.lines()[row].is_empty() {
+                jump(textarea, (row, col + 1));
+            }
+            textarea.insert_str(text.repeat(count));
+            // Leave the cursor on the last character put
+            let (row, col) = textarea.cursor();
+            if col > 0 {
+                jump(textarea, (row, col - 1));
+            }
+        }
+    }
+
+    /// Play the last change back, with `count` in place of its own if there is one.
+    fn repeat(&mut self, count: Option<usize>, textarea: &mut TextArea<'_>) {
+        if self.repeating {
+            return;
+        }
+        let mut keys = self.last_change.clone();
+        if let Some(count) = count {
+            let tokens: Vec<Token> = keys.iter().map(token).collect();
+            let start = if tokens.first() == Some(&Token::Char('"')) {
+                2.min(keys.len())
+            } else {
+                0
+            };
+            let (_, digits) = parse_count(&tokens[start..]);
+            // The count after an operator multiplies the one before it, so `2.` after `d3w`
+            // deletes two words rather than six
+            let operator = start + digits;
+            if matches!(tokens.get(operator), Some(Token::Char('d' | 'c' | 'y'))) {
+                let (_, inner) = parse_count(&tokens[operator + 1..]);
+                keys.drain(operator + 1..operator + 1 + inner);
+            }
+            keys.splice(start..start + digits, count.to_string().chars().map(key));
+        }
+        self.repeating = true;
+        for input in keys {
+            self.transition(input, textarea);
+        }
+        self.repeating = false;
+    }
+
+    /// Save text that was yanked or deleted. Without a register it goes in the unnamed one, and
+    /// yanks in `0` too. An upper case register appends to its lower case one, `_` drops the text
+    /// and `+` is the system clipboard.
+    fn store(&mut self, register: Option<char>, text: String, linewise: bool, yank: bool) {
+        let name = register.unwrap_or('"');
+        let mut entry = Register { text, linewise };
+        match name {
+            '_' => return,
+            '+' | '*' => {
+                let written = Clipboard::try_get()
+                    .map(|mut clipboard| clipboard.write_text(&entry.text).is_ok());
+                if written != Some(true) {
+                    warn!("Couldn't copy to the clipboard");
+                }
+            }
+            'A'..='Z' => {
+                let name = name.to_ascii_lowercase();
+                if let Some(old) = self.registers.get(&name) {
+                    let separator = if linewise && !old.text.ends_with('\n') {
+                        "\n"
+                    } else {
+                        ""
+                    };
+                    entry = Register {
+                        text: format!("{}{separator}{}", old.text, entry.text),
+                        linewise: old.linewise || linewise,
+                    };
+                }
+                self.registers.insert(name, entry.clone());
+            }
+            _ => {
+                self.registers.insert(name, entry.clone());
+            }
+        }
+        if yank && register.is_none() {
+            self.registers.insert('0', entry.clone());
+        }
+        self.registers.insert('"', entry);
+    }
+
+    fn register(&self, register: Option<char>) -> Option<Register> {
+        match register.unwrap_or('"') {
+            '+' | '*' => {
+                let mut clipboard = Clipboard::try_get()?;
+                let text = clipboard.read()?.as_text()?.to_string();
+                Some(Register {
+                    linewise: text.ends_with('\n'),
+                    text,
+                })
+            }
+            name => self.registers.get(&name.to_ascii_lowercase()).cloned(),
+        }
+    }
+}
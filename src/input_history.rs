@@ -0,0 +1,119 @@
+use crate::app::AppResult;
+use crate::utils;
+use log::warn;
+use std::fs;
+use std::io;
+use std::path::PathBuf;
+
+/// The most prompts kept. The oldest are dropped past this.
+const LIMIT: usize = 1000;
+
+/// The prompts sent before, oldest first. It's read again and saved after every prompt, so
+/// sessions running at the same time share it without dropping each other's prompts.
+#[derive(Clone, Debug, Default)]
+pub struct InputHistory {
+    entries: Vec<String>,
+    /// The prompt in the editor while going through the history
+    position: Option<usize>,
+    /// What was in the editor before going through the history
+    draft: String,
+}
+
+impl InputHistory {
+    fn path() -> io::Result<PathBuf> {
+        Ok(utils::data_dir("history")?.join("prompts.json"))
+    }
+
+    /// The saved history, or an empty one if there isn't any.
+    #[must_use]
+    pub fn load() -> Self {
+        Self {
+            entries: Self::read().unwrap_or_default(),
+            ..Self::default()
+        }
+    }
+
+    fn read() -> Option<Vec<String>> {
+        Self::path()
+            .and_then(fs::read_to_string)
+            .ok()
+            .and_then(|text| serde_json::from_str(&text).ok())
+    }
+
+    fn save(&self) -> AppResult<()> {
+        fs::write(Self::path()?, serde_json::to_string(&self.entries)?)?;
+        Ok(())
+    }
+
+    /// Add a sent prompt and save the history. A prompt that's already there moves to the end.
+    pub fn add(&mut self, text: &str) {
+        self.position = None;
+        self.draft.clear();
+        if text.trim().is_empty() {
+            return;
+        }
+        // Start from what's saved, which has the prompts other sessions sent since
+        if let Some(entries) = Self::read() {
+            self.entries = entries;
+        }
+        self.entries.retain(|entry| entry != text);
+        self.entries.push(text.to_string());
+        let excess = self.entries.len().saturating_sub(LIMIT);
+        self.entries.drain(..excess);
+        if let Err(err) = self.save() {
+            warn!("Couldn't save the prompt history: {err}");
+        }
+    }
+
+    /// The prompt before the one in the editor, if there is one. `current` is what's in the
+    /// editor, which is kept to come back to after the newest prompt.
+    pub fn older(&mut self, current: &str) -> Option<&str> {
+        let position = match self.position {
+            Some(0) => return None,
+            Some(position) => position - 1,
+            None if self.entries.is_empty() => return None,
+            None => {
+                self.draft = current.to_string();
+                self.entries.len() - 1
+            }
+        };
+        self.position = Some(position);
+        Some(&self.entries[position])
+    }
+
+    /// The prompt after the one in the editor, or what was there before going through the
+    /// history once past the newest prompt.
+    pub fn newer(&mut self) -> Option<&str> {
+        let position = self.position?;
+        if position + 1 < self.entries.len() {
+            self.position = Some(position + 1);
+            Some(&self.entries[position + 1])
+        } else {
+            self.position = None;
+            Some(&self.draft)
+        }
+    }
+
+    /// The index of the newest prompt before `before` that contains `query`.
+    #[must_use]
+    pub fn find(&self, query: &str, before: usize) -> Option<usize> {
+        self.entries[..before.min(self.entries.len())]
+            .iter()
+            .rposition(|entry| entry.contains(query))
+    }
+
+    #[must_use]
+    pub fn get(&self, index: usize) -> Option<&str> {
+        self.entries.get(index).map(String::as_str)
+    }
+
+    #[must_use]
+    pub const fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    #[must_use]
+    pub const fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+}
@@ -125,5 +125,135 @@ pub mod utils;
     clippy::nursery
 )]
 #[allow(clippy::missing_panics_doc, clippy::missing_errors_doc)]
-/// Error widget
+/// Popup widgets
 pub mod widgets;
+
+#[warn(
+    clippy::pedantic,
+    clippy::perf,
+    clippy::complexity,
+    clippy::suspicious,
+    clippy::style,
+    clippy::correctness,
+    clippy::nursery
+)]
+#[allow(clippy::missing_panics_doc, clippy::missing_errors_doc)]
+/// File attachments
+pub mod attach;
+
+#[warn(
+    clippy::pedantic,
+    clippy::perf,
+    clippy::complexity,
+    clippy::suspicious,
+    clippy::style,
+    clippy::correctness,
+    clippy::nursery
+)]
+#[allow(clippy::missing_panics_doc, clippy::missing_errors_doc)]
+/// Slash commands
+pub mod command;
+
+#[warn(
+    clippy::pedantic,
+    clippy::perf,
+    clippy::complexity,
+    clippy::suspicious,
+    clippy::style,
+    clippy::correctness,
+    clippy::nursery
+)]
+#[allow(clippy::missing_panics_doc, clippy::missing_errors_doc)]
+/// App actions and their keybindings
+pub mod action;
+
+#[warn(
+    clippy::pedantic,
+    clippy::perf,
+    clippy::complexity,
+    clippy::suspicious,
+    clippy::style,
+    clippy::correctness,
+    clippy::nursery
+)]
+#[allow(clippy::missing_panics_doc, clippy::missing_errors_doc)]
+/// Prompt templates
+pub mod template;
+
+#[warn(
+    clippy::pedantic,
+    clippy::perf,
+    clippy::complexity,
+    clippy::suspicious,
+    clippy::style,
+    clippy::correctness,
+    clippy::nursery
+)]
+#[allow(clippy::missing_panics_doc, clippy::missing_errors_doc)]
+/// Tool calling
+pub mod tool;
+
+#[warn(
+    clippy::pedantic,
+    clippy::perf,
+    clippy::complexity,
+    clippy::suspicious,
+    clippy::style,
+    clippy::correctness,
+    clippy::nursery
+)]
+#[allow(clippy::missing_panics_doc, clippy::missing_errors_doc)]
+/// Model Context Protocol client
+pub mod mcp;
+
+#[warn(
+    clippy::pedantic,
+    clippy::perf,
+    clippy::complexity,
+    clippy::suspicious,
+    clippy::style,
+    clippy::correctness,
+    clippy::nursery
+)]
+#[allow(clippy::missing_panics_doc, clippy::missing_errors_doc)]
+/// The built-in shell tool
+pub mod shell;
+
+#[warn(
+    clippy::pedantic,
+    clippy::perf,
+    clippy::complexity,
+    clippy::suspicious,
+    clippy::style,
+    clippy::correctness,
+    clippy::nursery
+)]
+#[allow(clippy::missing_panics_doc, clippy::missing_errors_doc)]
+/// Project context retrieval
+pub mod context;
+
+#[warn(
+    clippy::pedantic,
+    clippy::perf,
+    clippy::complexity,
+    clippy::suspicious,
+    clippy::style,
+    clippy::correctness,
+    clippy::nursery
+)]
+#[allow(clippy::missing_panics_doc, clippy::missing_errors_doc)]
+/// Archive of past conversations and semantic search over it
+pub mod archive;
+
+#[warn(
+    clippy::pedantic,
+    clippy::perf,
+    clippy::complexity,
+    clippy::suspicious,
+    clippy::style,
+    clippy::correctness,
+    clippy::nursery
+)]
+#[allow(clippy::missing_panics_doc, clippy::missing_errors_doc)]
+/// History of the prompts sent
+pub mod input_history;
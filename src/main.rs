@@ -1,8 +1,9 @@
 use gptrs::app::{App, AppResult};
 use gptrs::event::{Event, Handler};
 use gptrs::handler::{
-    handle_end, handle_error_popup, handle_key_events, handle_mouse_events, handle_new_message,
-    handle_start_generation, handle_token,
+    handle_command_outputs, handle_end, handle_error_popup, handle_key_events, handle_new_message,
+    handle_paste, handle_recall_results, handle_scroll, handle_start_generation, handle_token,
+    handle_tool_calls, handle_tool_output, mouse_scroll,
 };
 use gptrs::tui::Tui;
 use gptrs::utils::initialize_logger;
@@ -10,11 +11,15 @@ use log::debug;
 use ratatui::backend::CrosstermBackend;
 use ratatui::Terminal;
 use std::io;
+use std::time::Instant;
+use tokio::sync::mpsc;
+use tokio::time::timeout;
 
 #[tokio::main]
 async fn main() -> AppResult<()> {
     // Create an application.
-    let mut app = App::new();
+    let mut app = App::new()?;
+    app.connect_mcp().await;
 
     if app.config.debug {
         initialize_logger();
@@ -29,66 +34,90 @@ async fn main() -> AppResult<()> {
 
     debug!("Finished with the initialization. Time to start the event loop.");
 
-    let mut peeked = false; // A hack to get around having to actually implement peeking
-    let mut peeked_event = Event::Tick;
+    // Draw at most once a frame, and only when something changed. Events that come in sooner
+    // are handled straight away and drawn together.
+    let frame = app.frame_time();
+    let mut drawn = Instant::now();
+    let mut dirty = true;
 
     // Start the main loop.
     while app.running {
         // Render the user interface.
-        tui.draw(&mut app)?;
-
-        let mut event = Event::Tick;
-
-        // Handle events.
-        if peeked {
-            peeked = false;
-            event = peeked_event.clone();
-        } else {
-            event = tui.events.next().await?;
-        }
-
-        if event != Event::Tick {
-            debug!("Handling event {:?}", event);
+        if dirty && drawn.elapsed() >= frame {
+            tui.draw(&mut app)?;
+            drawn = Instant::now();
+            dirty = false;
         }
 
-        match event {
-            Event::Tick => app.tick(),
-            Event::Key(key_event) => {
-                handle_key_events(key_event, &mut app, tui.events.sender()).await?;
-            }
-            Event::Mouse(first) => {
-                // If there are two mouse events we can handle both of them at once.
-                let second = tui.events.next().await?;
-                peeked = true;
-                peeked_event = second.clone();
-                match second {
-                    Event::Mouse(second_mouse) => {
-                        debug!(
-                            "Handling two mouse events at once, {:?} and {:?}",
-                            first, second_mouse
-                        );
-                        handle_mouse_events(first, Some(second_mouse), &mut app)?
-                    }
-                    _ => handle_mouse_events(first, None, &mut app)?,
-                }
-            }
-            Event::Message => handle_new_message(&mut app, tui.events.sender()).await?,
-            Event::StartGeneration => {
-                handle_start_generation(&mut app, tui.events.sender()).await?;
-            }
-            Event::Token(token, first) => handle_token(&mut app, &token, first)?,
-            Event::EndGeneration => handle_end(&mut app)?,
-            Event::Resize(_, _) => {}
-            Event::ErrorPopup(severity, message) => {
-                handle_error_popup(&mut app, severity, message)?
-            }
-            Event::ClearErrorPopup => {
-                app.error = None;
+        // Wait for an event, or until the next frame is due if there's something to draw.
+        let event = if dirty {
+            let wait = frame.saturating_sub(drawn.elapsed());
+            match timeout(wait, tui.events.next()).await {
+                Ok(event) => event?,
+                Err(_) => continue,
             }
+        } else {
+            tui.events.next().await?
         };
+
+        // Handle every event that's already waiting before drawing, adding up the mouse wheel
+        // so a burst of scrolling moves the chat once.
+        let mut scroll = 0;
+        let mut next = Some(event);
+        while let Some(event) = next {
+            if event != Event::Tick {
+                debug!("Handling event {:?}", event);
+            }
+            dirty |= handle_event(event, &mut app, &mut scroll, tui.events.sender()).await?;
+            next = if app.running {
+                tui.events.try_next().await?
+            } else {
+                None
+            };
+        }
+        handle_scroll(&mut app, scroll);
     }
 
     // Exit the user interface.
     tui.exit()?;
     Ok(())
 }
+
+/// Handle an event other than scrolling the mouse wheel, which is added to `scroll`.
+///
+/// Returns whether the screen needs drawing again.
+async fn handle_event(
+    event: Event,
+    app: &mut App<'_>,
+    scroll: &mut isize,
+    sender: mpsc::Sender<Event>,
+) -> AppResult<bool> {
+    match event {
+        // Only the log pane changes on its own
+        Event::Tick => {
+            app.tick();
+            return Ok(app.config.debug);
+        }
+        Event::Key(key_event) => handle_key_events(key_event, app, sender).await?,
+        Event::Mouse(mouse_event) => {
+            let lines = mouse_scroll(&mouse_event);
+            *scroll += lines;
+            return Ok(lines != 0);
+        }
+        Event::Paste(text) => handle_paste(&text, app),
+        Event::Message => handle_new_message(app, sender).await?,
+        Event::StartGeneration => handle_start_generation(app, sender).await?,
+        Event::Token(token, count, first) => handle_token(app, &token, count, first)?,
+        Event::EndGeneration => handle_end(app)?,
+        Event::ToolCalls(calls) => handle_tool_calls(app, calls, sender).await?,
+        Event::ToolOutput(id, output) => handle_tool_output(app, id, output, sender).await?,
+        Event::CommandOutputs(text, outputs) => {
+            handle_command_outputs(app, text, outputs, sender).await?;
+        }
+        Event::RecallResults(query, hits) => handle_recall_results(app, query, hits)?,
+        Event::Resize(_, _) => {}
+        Event::ErrorPopup(severity, message) => handle_error_popup(app, severity, message)?,
+        Event::ClearErrorPopup => app.error = None,
+    }
+    Ok(true)
+}
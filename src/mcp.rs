@@ -0,0 +1,298 @@
+use crate::app::AppResult;
+use crate::tool::{Runner, Tool};
+use log::{debug, warn};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use std::collections::{BTreeMap, HashMap};
+use std::process::Stdio;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader, Lines};
+use tokio::process::{Child, ChildStdin, ChildStdout, Command};
+use tokio::sync::Mutex;
+use tokio::time::timeout;
+
+/// The protocol version we ask servers for.
+const PROTOCOL_VERSION: &str = "2024-11-05";
+/// How long a server gets to start and list its tools.
+const STARTUP_TIMEOUT: Duration = Duration::from_secs(10);
+/// How long a server gets to answer a tool call.
+const CALL_TIMEOUT: Duration = Duration::from_secs(30);
+/// Separates the server name from the tool name in the names the model sees.
+pub const SEPARATOR: &str = "__";
+
+/// How to launch a Model Context Protocol server that talks over stdio.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct Server {
+    pub command: String,
+    #[serde(default)]
+    pub args: Vec<String>,
+    #[serde(default)]
+    pub env: HashMap<String, String>,
+}
+
+/// A running server and the JSON-RPC connection to it.
+#[derive(Debug)]
+pub struct Client {
+    name: String,
+    // Kept so the server is killed when the client is dropped
+    _child: Child,
+    stdin: ChildStdin,
+    stdout: Lines<BufReader<ChildStdout>>,
+    next_id: u64,
+}
+
+impl Client {
+    /// Launch the server and do the initialize handshake.
+    pub async fn start(name: &str, server: &Server) -> AppResult<Self> {
+        let mut child = Command::new(&server.command)
+            .args(&server.args)
+            .envs(&server.env)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::null())
+            .kill_on_drop(true)
+            .spawn()
+            .map_err(|err| format!("Couldn't run {}: {err}", server.command))?;
+        let stdin = child.stdin.take().ok_or("The server has no stdin")?;
+        let stdout = child.stdout.take().ok_or("The server has no stdout")?;
+
+        let mut client = Self {
+            name: name.to_string(),
+            _child: child,
+            stdin,
+            stdout: BufReader::new(stdout).lines(),
+            next_id: 0,
+        };
+        client
+            .request(
+                "initialize",
+                json!({
+                    "protocolVersion": PROTOCOL_VERSION,
+                    "capabilities": {},
+                    "clientInfo": {
+                        "name": env!("CARGO_PKG_NAME"),
+                        "version": env!("CARGO_PKG_VERSION"),
+                    },
+                }),
+            )
+            .await?;
+        client.notify("notifications/initialized").await?;
+        Ok(client)
+    }
+
+    /// The tools the server offers as they're declared to the model.
+    pub async fn list_tools(&mut self) -> AppResult<Vec<ServerTool>> {
+        let mut tools = vec![];
+        let mut cursor: Option<String> = None;
+        loop {
+            let params = cursor.map_or_else(|| json!({}), |cursor| json!({ "cursor": cursor }));
+            let result = self.request("tools/list", params).await?;
+            let page: ToolList = serde_json::from_value(result)?;
+            tools.extend(page.tools);
+            match page.next_cursor {
+                Some(next) => cursor = Some(next),
+                None => return Ok(tools),
+            }
+        }
+    }
+
+    /// Call a tool and return its output as text.
+    pub async fn call_tool(&mut self, name: &str, arguments: Value) -> AppResult<String> {
+        let result = self
+            .request(
+                "tools/call",
+                json!({ "name": name, "arguments": arguments }),
+            )
+            .await?;
+        let result: CallResult = serde_json::from_value(result)?;
+
+        let mut text = if result.is_error {
+            "Error: ".to_string()
+        } else {
+            String::new()
+        };
+        let parts: Vec<String> = result
+            .content
+            .into_iter()
+            .map(|part| match part {
+                Content::Text { text } => text,
+                Content::Image { mime_type } => format!("[image: {mime_type}]"),
+                Content::Resource { resource } => resource
+                    .get("text")
+                    .and_then(Value::as_str)
+                    .map_or_else(|| format!("[resource: {resource}]"), ToString::to_string),
+                Content::Other => "[unsupported content]".to_string(),
+            })
+            .collect();
+        text.push_str(&parts.join("\n"));
+        Ok(text)
+    }
+
+    async fn send(&mut self, message: &Value) -> AppResult<()> {
+        let mut line = serde_json::to_string(message)?;
+        line.push('\n');
+        self.stdin.write_all(line.as_bytes()).await?;
+        self.stdin.flush().await?;
+        Ok(())
+    }
+
+    async fn notify(&mut self, method: &str) -> AppResult<()> {
+        self.send(&json!({ "jsonrpc": "2.0", "method": method }))
+            .await
+    }
+
+    /// Send a request and wait for the response to it.
+    async fn request(&mut self, method: &str, params: Value) -> AppResult<Value> {
+        self.next_id += 1;
+        let id = self.next_id;
+        debug!("Sending {method} to the MCP server {}", self.name);
+        self.send(&json!({ "jsonrpc": "2.0", "id": id, "method": method, "params": params }))
+            .await?;
+
+        loop {
+            let line = self
+                .stdout
+                .next_line()
+                .await?
+                .ok_or_else(|| format!("The MCP server {} exited", self.name))?;
+            let Ok(message) = serde_json::from_str::<Value>(&line) else {
+                warn!("Ignoring a line from the MCP server {}: {line}", self.name);
+                continue;
+            };
+
+            if message.get("method").is_some() {
+                // We don't offer the server anything so turn down its requests and ignore its
+                // notifications
+                if let Some(request) = message.get("id") {
+                    self.send(&json!({
+                        "jsonrpc": "2.0",
+                        "id": request,
+                        "error": { "code": -32601, "message": "Method not found" },
+                    }))
+                    .await?;
+                }
+            } else if message.get("id").and_then(Value::as_u64) == Some(id) {
+                if let Some(error) = message.get("error") {
+                    let reason = error
+                        .get("message")
+                        .and_then(Value::as_str)
+                        .unwrap_or("unknown error");
+                    return Err(format!("The MCP server {} returned: {reason}", self.name).into());
+                }
+                return Ok(message.get("result").cloned().unwrap_or(Value::Null));
+            }
+        }
+    }
+}
+
+/// A tool as a server lists it.
+#[derive(Clone, Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ServerTool {
+    pub name: String,
+    #[serde(default)]
+    pub description: String,
+    pub input_schema: Value,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct ToolList {
+    tools: Vec<ServerTool>,
+    next_cursor: Option<String>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct CallResult {
+    #[serde(default)]
+    content: Vec<Content>,
+    #[serde(default)]
+    is_error: bool,
+}
+
+#[derive(Deserialize)]
+#[serde(tag = "type", rename_all = "lowercase")]
+enum Content {
+    Text {
+        text: String,
+    },
+    Image {
+        #[serde(rename = "mimeType")]
+        mime_type: String,
+    },
+    Resource {
+        resource: Value,
+    },
+    #[serde(other)]
+    Other,
+}
+
+/// Where to send calls to a tool from an MCP server.
+#[derive(Clone, Debug)]
+pub struct Remote {
+    pub server: String,
+    pub tool: String,
+    client: Arc<Mutex<Client>>,
+}
+
+impl Remote {
+    pub async fn call(&self, arguments: &str) -> AppResult<String> {
+        let arguments: Value = serde_json::from_str(arguments)?;
+        let mut client = self.client.lock().await;
+        // A response that comes in after this is skipped by the next request as it has another id
+        timeout(CALL_TIMEOUT, client.call_tool(&self.tool, arguments))
+            .await
+            .map_err(|_| {
+                format!(
+                    "The MCP server {} took longer than {} seconds to answer",
+                    self.server,
+                    CALL_TIMEOUT.as_secs()
+                )
+            })?
+    }
+}
+
+/// Start every server and collect their tools. Servers that fail to start are left out and
+/// their errors returned alongside the tools.
+pub async fn connect(servers: &BTreeMap<String, Server>) -> (Vec<Tool>, Vec<String>) {
+    let mut tools = vec![];
+    let mut errors = vec![];
+
+    for (name, server) in servers {
+        let started = timeout(STARTUP_TIMEOUT, async {
+            let mut client = Client::start(name, server).await?;
+            let listed = client.list_tools().await?;
+            AppResult::Ok((client, listed))
+        })
+        .await;
+
+        let (client, listed) = match started {
+            Ok(Ok(started)) => started,
+            Ok(Err(err)) => {
+                errors.push(format!("MCP server {name}: {err}"));
+                continue;
+            }
+            Err(_) => {
+                errors.push(format!("MCP server {name} took too long to start"));
+                continue;
+            }
+        };
+
+        let client = Arc::new(Mutex::new(client));
+        tools.extend(listed.into_iter().map(|tool| Tool {
+            name: format!("{name}{SEPARATOR}{}", tool.name),
+            description: tool.description,
+            command: vec![],
+            parameters: tool.input_schema,
+            runner: Runner::Remote(Remote {
+                server: name.clone(),
+                tool: tool.name,
+                client: client.clone(),
+            }),
+        }));
+    }
+
+    (tools, errors)
+}
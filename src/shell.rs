@@ -0,0 +1,131 @@
+use crate::app::AppResult;
+use crate::tool::{self, Runner, Tool};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use std::process::Stdio;
+use std::time::Duration;
+use tokio::process::Command;
+use tokio::time::timeout;
+
+/// The name the model calls the shell tool by.
+pub const NAME: &str = "shell";
+
+/// Characters that chain or redirect commands. Commands using them always need approval.
+const OPERATORS: &[char] = &[';', '&', '|', '`', '$', '>', '<', '(', ')', '\n'];
+
+/// Settings for the built-in shell tool. The tool is only offered to the model when the config
+/// has a `shell` section.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct Shell {
+    /// Commands starting with one of these run without asking
+    #[serde(default)]
+    pub allow: Vec<String>,
+    /// Seconds a command can run before it's killed
+    #[serde(default = "default_timeout")]
+    pub timeout: u64,
+    /// The most output of a command that's sent back to the model
+    #[serde(default = "default_output_limit")]
+    pub output_limit: usize,
+}
+
+impl Default for Shell {
+    fn default() -> Self {
+        Self {
+            allow: vec![],
+            timeout: default_timeout(),
+            output_limit: default_output_limit(),
+        }
+    }
+}
+
+const fn default_timeout() -> u64 {
+    30
+}
+
+const fn default_output_limit() -> usize {
+    20_000
+}
+
+impl Shell {
+    /// The shell as a tool the model can call.
+    #[must_use]
+    pub fn tool(&self) -> Tool {
+        Tool {
+            name: NAME.to_string(),
+            description: "Run a shell command in the current directory and get its output"
+                .to_string(),
+            command: vec![],
+            parameters: json!({
+                "type": "object",
+                "properties": {
+                    "command": { "type": "string", "description": "The command to run" }
+                },
+                "required": ["command"]
+            }),
+            runner: Runner::Shell(self.clone()),
+        }
+    }
+
+    /// Whether `command` can run without asking first.
+    #[must_use]
+    pub fn is_allowed(&self, command: &str) -> bool {
+        let command = command.trim();
+        !command.contains(OPERATORS)
+            && self.allow.iter().any(|prefix| {
+                command
+                    .strip_prefix(prefix.as_str())
+                    .is_some_and(|rest| rest.is_empty() || rest.starts_with(' '))
+            })
+    }
+
+    /// Run `command` in the working directory and return what it printed.
+    pub async fn run(&self, command: &str) -> AppResult<String> {
+        let (shell, flag) = if cfg!(windows) {
+            ("cmd", "/C")
+        } else {
+            ("sh", "-c")
+        };
+        let child = Command::new(shell)
+            .arg(flag)
+            .arg(command)
+            .stdin(Stdio::null())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .kill_on_drop(true)
+            .spawn()?;
+
+        let Ok(output) = timeout(Duration::from_secs(self.timeout), child.wait_with_output()).await
+        else {
+            return Ok(format!(
+                "The command was killed after running for {} seconds.",
+                self.timeout
+            ));
+        };
+        let output = output?;
+
+        let mut text = String::from_utf8_lossy(&output.stdout).to_string();
+        text.push_str(&String::from_utf8_lossy(&output.stderr));
+        if !output.status.success() {
+            text = format!("{text}\n({})", output.status);
+        }
+        Ok(tool::truncate(text, self.output_limit))
+    }
+}
+
+/// The command the model asked to run.
+pub fn command(arguments: &str) -> AppResult<String> {
+    let arguments: Value = serde_json::from_str(arguments)?;
+    arguments
+        .get("command")
+        .and_then(Value::as_str)
+        .map(ToString::to_string)
+        .ok_or_else(|| "The shell tool was called without a command".into())
+}
+
+/// What "always allow" adds to the allowlist for `command`: the whole command, so approving
+/// `git status` doesn't also approve `git push`. Commands that chain or redirect can't be allowed.
+#[must_use]
+pub fn allow_entry(command: &str) -> Option<&str> {
+    let command = command.trim();
+    (!command.is_empty() && !command.contains(OPERATORS)).then_some(command)
+}
@@ -0,0 +1,137 @@
+use crate::app::AppResult;
+use crate::config::Prompt;
+use log::warn;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fs;
+use std::hash::BuildHasher;
+use std::path::{Path, PathBuf};
+
+/// A reusable prompt with `{{variable}}` placeholders.
+///
+/// Templates are read from the `templates` directory next to the config file. JSON files hold a
+/// whole template and any other file is used as the body of one.
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+pub struct Template {
+    #[serde(skip)]
+    pub name: String,
+    #[serde(default)]
+    pub description: String,
+    pub body: String,
+    /// Messages that replace the system prompt when the template is used
+    #[serde(default)]
+    pub prompt: Vec<Prompt>,
+}
+
+impl Template {
+    /// The names of the variables in the body in the order they first appear.
+    #[must_use]
+    pub fn variables(&self) -> Vec<String> {
+        let mut variables: Vec<String> = vec![];
+        for (start, end) in placeholders(&self.body) {
+            let name = self.body[start + 2..end - 2].trim().to_string();
+            if !variables.contains(&name) {
+                variables.push(name);
+            }
+        }
+        variables
+    }
+
+    /// The body with every variable replaced by its value.
+    #[must_use]
+    pub fn fill(&self, values: &HashMap<String, String>) -> String {
+        fill(&self.body, values)
+    }
+}
+
+/// Replace every `{{variable}}` in `text` with its value. Missing variables are left empty.
+#[must_use]
+pub fn fill<S: BuildHasher>(text: &str, values: &HashMap<String, String, S>) -> String {
+    let mut filled = String::with_capacity(text.len());
+    let mut last = 0;
+    for (start, end) in placeholders(text) {
+        let name = text[start + 2..end - 2].trim();
+        filled.push_str(&text[last..start]);
+        filled.push_str(values.get(name).map_or("", String::as_str));
+        last = end;
+    }
+    filled.push_str(&text[last..]);
+    filled
+}
+
+/// The directory templates are kept in.
+#[must_use]
+pub fn dir(config_path: &Path) -> PathBuf {
+    config_path
+        .parent()
+        .map_or_else(|| PathBuf::from("templates"), |dir| dir.join("templates"))
+}
+
+/// Every template in `dir`, sorted by name. Files that can't be read are skipped.
+pub fn load_all(dir: &Path) -> AppResult<Vec<Template>> {
+    if !dir.exists() {
+        return Ok(vec![]);
+    }
+
+    let mut templates = vec![];
+    for entry in fs::read_dir(dir)? {
+        let path = entry?.path();
+        if path.is_file() {
+            match read(&path) {
+                Ok(template) => templates.push(template),
+                Err(err) => warn!("Skipping the template {}: {err}", path.display()),
+            }
+        }
+    }
+    templates.sort_by(|a, b| a.name.cmp(&b.name));
+    Ok(templates)
+}
+
+/// The template called `name` in `dir`.
+pub fn load(dir: &Path, name: &str) -> AppResult<Template> {
+    load_all(dir)?
+        .into_iter()
+        .find(|template| template.name == name)
+        .ok_or_else(|| {
+            format!(
+                "Couldn't find a template called {name} in {}",
+                dir.display()
+            )
+            .into()
+        })
+}
+
+fn read(path: &Path) -> AppResult<Template> {
+    let text = fs::read_to_string(path)?;
+    let mut template = if path.extension().is_some_and(|ext| ext == "json") {
+        serde_json::from_str(&text)
+            .map_err(|err| format!("Couldn't parse template {}: {err}", path.display()))?
+    } else {
+        Template {
+            body: text,
+            ..Template::default()
+        }
+    };
+    template.name = path
+        .file_stem()
+        .map(|stem| stem.to_string_lossy().to_string())
+        .unwrap_or_default();
+    Ok(template)
+}
+
+/// The byte ranges of every `{{variable}}` in `body`, braces included.
+fn placeholders(body: &str) -> Vec<(usize, usize)> {
+    let mut found = vec![];
+    let mut rest = 0;
+    while let Some(start) = body[rest..].find("{{").map(|i| i + rest) {
+        let Some(end) = body[start + 2..].find("}}").map(|i| i + start + 4) else {
+            break;
+        };
+        let name = body[start + 2..end - 2].trim();
+        if !name.is_empty() && !name.contains(char::is_whitespace) {
+            found.push((start, end));
+        }
+        rest = end;
+    }
+    found
+}
@@ -0,0 +1,189 @@
+use crate::app::AppResult;
+use crate::mcp::Remote;
+use crate::shell::{self, Shell};
+use crate::template;
+use async_openai::types::{
+    ChatCompletionFunctionsArgs, ChatCompletionMessageToolCall, ChatCompletionMessageToolCallChunk,
+    ChatCompletionTool, ChatCompletionToolArgs, ChatCompletionToolType, FunctionCall,
+};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use std::collections::HashMap;
+use std::process::Stdio;
+use tokio::io::AsyncWriteExt;
+use tokio::process::Command;
+
+/// The most output of a tool that's sent back to the model.
+const OUTPUT_LIMIT: usize = 20_000;
+
+/// A local command the model can call.
+///
+/// `{{argument}}` placeholders in the command are replaced with the arguments the model calls it
+/// with, and all of the arguments are passed to it on stdin as JSON.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct Tool {
+    pub name: String,
+    #[serde(default)]
+    pub description: String,
+    /// The program to run followed by its arguments
+    #[serde(default)]
+    pub command: Vec<String>,
+    /// A JSON schema for the arguments
+    #[serde(default = "no_parameters")]
+    pub parameters: Value,
+    /// What runs the tool
+    #[serde(skip)]
+    pub runner: Runner,
+}
+
+/// How a tool is run.
+#[derive(Clone, Debug, Default)]
+pub enum Runner {
+    /// The local command of the tool
+    #[default]
+    Command,
+    /// A tool of an MCP server
+    Remote(Remote),
+    /// The built-in shell tool
+    Shell(Shell),
+}
+
+fn no_parameters() -> Value {
+    json!({ "type": "object", "properties": {} })
+}
+
+impl Tool {
+    /// The tool as it's declared in a request.
+    ///
+    /// # Panics
+    ///
+    /// Will panic if the tool can't be built
+    #[must_use]
+    pub fn to_openai(&self) -> ChatCompletionTool {
+        let mut function = ChatCompletionFunctionsArgs::default();
+        function
+            .name(self.name.clone())
+            .parameters(self.parameters.clone());
+        if !self.description.is_empty() {
+            function.description(self.description.clone());
+        }
+        ChatCompletionToolArgs::default()
+            .function(function.build().unwrap())
+            .build()
+            .unwrap()
+    }
+
+    /// The command line that will be run for `arguments`.
+    pub fn command_line(&self, arguments: &str) -> AppResult<Vec<String>> {
+        let values: HashMap<String, String> = match serde_json::from_str(arguments)? {
+            Value::Object(map) => map
+                .into_iter()
+                .map(|(name, value)| {
+                    let value = match value {
+                        Value::String(value) => value,
+                        value => value.to_string(),
+                    };
+                    (name, value)
+                })
+                .collect(),
+            _ => return Err("The arguments aren't a JSON object".into()),
+        };
+        Ok(self
+            .command
+            .iter()
+            .map(|arg| template::fill(arg, &values))
+            .collect())
+    }
+
+    /// Run the tool and return what it printed.
+    pub async fn run(&self, arguments: &str) -> AppResult<String> {
+        match &self.runner {
+            Runner::Command => {}
+            Runner::Remote(remote) => return remote.call(arguments).await,
+            Runner::Shell(shell) => {
+                let command = shell::command(arguments)?;
+                return shell.run(&command).await;
+            }
+        }
+
+        let command_line = self.command_line(arguments)?;
+        let (program, args) = command_line
+            .split_first()
+            .ok_or_else(|| format!("The tool {} doesn't have a command", self.name))?;
+
+        let mut child = Command::new(program)
+            .args(args)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .spawn()?;
+        if let Some(mut stdin) = child.stdin.take() {
+            stdin.write_all(arguments.as_bytes()).await?;
+        }
+        let output = child.wait_with_output().await?;
+
+        let mut text = String::from_utf8_lossy(&output.stdout).to_string();
+        if !output.status.success() {
+            text.push_str(&String::from_utf8_lossy(&output.stderr));
+            text = format!("{text}\n({})", output.status);
+        }
+        Ok(truncate(text, OUTPUT_LIMIT))
+    }
+}
+
+/// Cut `text` down to at most `limit` bytes, noting that it was cut.
+#[must_use]
+pub fn truncate(mut text: String, limit: usize) -> String {
+    if text.len() > limit {
+        let mut end = limit;
+        while !text.is_char_boundary(end) {
+            end -= 1;
+        }
+        text.truncate(end);
+        text.push_str("\n(output truncated)");
+    }
+    text
+}
+
+/// A call the model made to a tool.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct ToolCall {
+    pub id: String,
+    pub name: String,
+    /// The arguments as JSON
+    pub arguments: String,
+}
+
+impl ToolCall {
+    /// Add a streamed piece of a call to the calls collected so far.
+    pub fn accumulate(calls: &mut Vec<Self>, chunk: &ChatCompletionMessageToolCallChunk) {
+        let index = usize::try_from(chunk.index).unwrap_or_default();
+        if calls.len() <= index {
+            calls.resize(index + 1, Self::default());
+        }
+        let call = &mut calls[index];
+        if let Some(id) = &chunk.id {
+            call.id.push_str(id);
+        }
+        if let Some(function) = &chunk.function {
+            if let Some(name) = &function.name {
+                call.name.push_str(name);
+            }
+            if let Some(arguments) = &function.arguments {
+                call.arguments.push_str(arguments);
+            }
+        }
+    }
+
+    #[must_use]
+    pub fn to_openai(&self) -> ChatCompletionMessageToolCall {
+        ChatCompletionMessageToolCall {
+            id: self.id.clone(),
+            r#type: ChatCompletionToolType::Function,
+            function: FunctionCall {
+                name: self.name.clone(),
+                arguments: self.arguments.clone(),
+            },
+        }
+    }
+}
@@ -1,5 +1,5 @@
 use ratatui::{
-    layout::{Alignment, Layout},
+    layout::{Alignment, Layout, Rect},
     prelude::{Constraint, Direction},
     style::{Color, Style},
     widgets::{Block, BorderType, Borders, Paragraph},
@@ -7,7 +7,11 @@ use ratatui::{
 };
 use tui_logger::{TuiLoggerLevelOutput, TuiLoggerWidget};
 
-use crate::app::App;
+use crate::app::{App, Focus};
+use crate::shell;
+use crate::tool::{Runner, ToolCall};
+use crate::widgets::chat::ChatView;
+use crate::widgets::confirm::Confirm;
 
 /// Renders the user interface widgets.
 pub fn render(app: &mut App, frame: &mut Frame) {
@@ -18,7 +22,7 @@ pub fn render(app: &mut App, frame: &mut Frame) {
         .direction(Direction::Vertical)
         .margin(1)
         .constraints([
-            Constraint::Max(6),
+            Constraint::Max(7),
             Constraint::Percentage(70),
             Constraint::Max(6),
         ])
@@ -39,10 +43,12 @@ pub fn render(app: &mut App, frame: &mut Frame) {
     // Title widget
     let title = Paragraph::new(format!(
         "Model: {}\n\
+        Persona: {}\n\
         API key: {}\n\
         API Base URL: {}\n\
         Tokens: {}",
         app.config.model,
+        app.config.persona.as_deref().unwrap_or("default"),
         mask_api_key(&app.config.api_key, 5),
         app.config.api_base,
         app.chat_text.tokens
@@ -59,12 +65,27 @@ pub fn render(app: &mut App, frame: &mut Frame) {
 
     // Chat list widget
     app.chat_text.text_width = chat_area.width - 2;
-    let chat_list = Paragraph::new(app.chat_text.render_history())
-        .scroll(app.chat_scroll)
-        .block(Block::default().borders(Borders::LEFT | Borders::RIGHT));
+    app.chat_height = chat_area.height;
+    let border_style = if app.focus == Focus::Chat {
+        Style::default().fg(Color::Cyan)
+    } else {
+        Style::default()
+    };
+    layout_chat(app);
+    let selected = app.selected().filter(|_| app.focus == Focus::Chat);
+    let chat_list = ChatView::new(&app.chat_text, app.chat_scroll)
+        .search(app.search.as_ref())
+        .selected(selected)
+        .block(
+            Block::default()
+                .borders(Borders::LEFT | Borders::RIGHT)
+                .border_style(border_style),
+        );
 
     frame.render_widget(chat_list, chat_area);
 
+    render_chat_footer(app, frame, chat_area);
+
     if app.config.debug {
         let tui_sm = TuiLoggerWidget::default()
             .style_error(Style::default().fg(Color::Red))
@@ -88,11 +109,176 @@ pub fn render(app: &mut App, frame: &mut Frame) {
 
     frame.render_widget(chat_input, main_layout[2]);
 
+    let input_area = main_layout[2];
+    let hint_area = Rect::new(
+        input_area.x + 2,
+        input_area.bottom().saturating_sub(1),
+        input_area.width.saturating_sub(4),
+        1,
+    );
+    if let Some(search) = &app.reverse_search {
+        frame.render_widget(search.clone(), hint_area);
+    } else if app.running_commands {
+        frame.render_widget(
+            Paragraph::new("Running commands…").style(Style::default().fg(Color::DarkGray)),
+            hint_area,
+        );
+    } else if let Some(hint) = &app.input_hint {
+        frame.render_widget(
+            Paragraph::new(hint.as_str()).style(Style::default().fg(Color::DarkGray)),
+            hint_area,
+        );
+    }
+
+    render_overlays(app, frame);
+}
+
+/// Renders the search bar and the new text indicator over the bottom of the chat.
+fn render_chat_footer(app: &App, frame: &mut Frame, chat_area: Rect) {
+    if let Some(search) = &app.search {
+        let search_area = Rect::new(
+            chat_area.x + 1,
+            chat_area.bottom().saturating_sub(1),
+            chat_area.width.saturating_sub(2),
+            1,
+        );
+        frame.render_widget(search, search_area);
+    }
+
+    if app.unseen {
+        let text = " ↓ New text below (End) ";
+        let width = u16::try_from(text.chars().count()).unwrap_or(u16::MAX);
+        let offset = if app.search.is_some() { 2 } else { 1 };
+        let unseen_area = Rect::new(
+            chat_area.right().saturating_sub(width + 1),
+            chat_area.bottom().saturating_sub(offset),
+            width.min(chat_area.width),
+            1,
+        );
+        frame.render_widget(
+            Paragraph::new(text).style(Style::default().fg(Color::Black).bg(Color::Yellow)),
+            unseen_area,
+        );
+    }
+}
+
+/// Renders the popups and overlays that are open on top of everything else.
+fn render_overlays(app: &App, frame: &mut Frame) {
+    if let Some(system_editor) = &app.system_editor {
+        frame.render_widget(system_editor.clone(), frame.size());
+    }
+
+    if let Some(template_picker) = &app.template_picker {
+        frame.render_widget(template_picker.clone(), frame.size());
+    }
+
+    if let Some(recall) = &app.recall {
+        frame.render_widget(recall.clone(), frame.size());
+    }
+
+    if let Some(preview) = &app.preview {
+        frame.render_widget(preview.clone(), frame.size());
+    }
+
+    if let Some(path) = &app.overwrite {
+        let confirm = Confirm::new(
+            "Overwrite?".to_string(),
+            format!("{} already exists.", path.display()),
+            vec![('y', "overwrite".to_string()), ('n', "cancel".to_string())],
+        );
+        frame.render_widget(confirm, frame.size());
+    }
+
+    if let Some(call) = app.pending_tools.first() {
+        frame.render_widget(tool_confirmation(app, call), frame.size());
+    }
+
+    if let Some(palette) = &app.palette {
+        frame.render_widget(palette.clone(), frame.size());
+    }
+
     if app.error.is_some() {
         frame.render_widget(app.error.clone().unwrap(), frame.size());
     }
 }
 
+/// Lay out the chat and pick the line to show at the top. Keeps the newest text in view when
+/// following it, otherwise scrolls to the message to jump to or the current search match if there
+/// is one.
+fn layout_chat(app: &mut App) {
+    let before = app.chat_text.text_lines;
+    app.chat_text.layout();
+    if app.follow {
+        app.chat_scroll = app.max_scroll();
+    } else if app.chat_text.text_lines > before && app.chat_scroll < app.max_scroll() {
+        app.unseen = true;
+    }
+
+    if let Some(message) = app.jump_to.take() {
+        let line = app.chat_text.message_lines.get(message).copied();
+        app.scroll_to(line.unwrap_or(0));
+    }
+
+    let Some(search) = app.search.as_mut() else {
+        return;
+    };
+    let scroll = app.chat_scroll;
+    if let Some(line) = search.update(&app.chat_text, scroll) {
+        // Only scroll if the match is out of view, and then leave some context above it
+        let height = usize::from(app.chat_height.saturating_sub(1));
+        if line < scroll || line >= scroll + height {
+            app.scroll_to(line.saturating_sub(height / 3));
+        }
+    }
+}
+
+/// The popup asking whether to run a tool call.
+fn tool_confirmation(app: &App, call: &ToolCall) -> Confirm {
+    let arguments = serde_json::from_str::<serde_json::Value>(&call.arguments)
+        .and_then(|arguments| serde_json::to_string_pretty(&arguments))
+        .unwrap_or_else(|_| call.arguments.clone());
+    let tool = app.tool(&call.name);
+    match tool.map(|tool| &tool.runner) {
+        Some(Runner::Remote(remote)) => {
+            let body = format!(
+                "The model wants to call {} on the MCP server {} with:\n{arguments}",
+                remote.tool, remote.server
+            );
+            Confirm::new(
+                format!("Call {}?", remote.tool),
+                body,
+                vec![('y', "call".to_string()), ('n', "skip".to_string())],
+            )
+        }
+        Some(Runner::Shell(_)) => {
+            let command = shell::command(&call.arguments).unwrap_or(arguments);
+            let directory = std::env::current_dir().unwrap_or_default();
+            let body = format!(
+                "The model wants to run this command in {}:\n\n$ {command}",
+                directory.display()
+            );
+            let mut choices = vec![('y', "run once".to_string())];
+            if let Some(entry) = shell::allow_entry(&command) {
+                choices.push(('a', format!("always allow `{entry}`")));
+            }
+            choices.push(('n', "deny".to_string()));
+            Confirm::new("Run shell command?".to_string(), body, choices)
+        }
+        _ => {
+            let mut body = format!("The model wants to run {} with:\n{arguments}", call.name);
+            if let Some(Ok(command_line)) = tool.map(|tool| tool.command_line(&call.arguments)) {
+                body.push_str("\n\n$ ");
+                body.push_str(&command_line.join(" "));
+            }
+            Confirm::new(
+                format!("Run {}?", call.name),
+                body,
+                vec![('y', "run".to_string()), ('n', "skip".to_string())],
+            )
+        }
+    }
+}
+
 /// Mask a displayed API key from shoulder snoopers
 ///
 /// * `api_key` - The API key to mask
@@ -1,5 +1,8 @@
+use chrono::Local;
 use log::LevelFilter;
-use std::fs::create_dir;
+use std::fs::{create_dir, create_dir_all};
+use std::io;
+use std::path::PathBuf;
 
 pub fn initialize_file_logger() {
     // Set up our logging
@@ -21,3 +24,55 @@ pub fn initialize_logger() {
     });
     tui_logger::set_default_level(log::LevelFilter::Trace);
 }
+
+/// A directory under the data directory, created if it doesn't exist yet.
+pub fn data_dir(name: &str) -> io::Result<PathBuf> {
+    let dir = dirs::data_dir()
+        .ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, "No data directory"))?
+        .join("gptrs")
+        .join(name);
+    create_dir_all(&dir)?;
+    Ok(dir)
+}
+
+/// The current local time in a form that can be used in a file name.
+#[must_use]
+pub fn timestamp() -> String {
+    Local::now().format("%Y-%m-%d-%H%M%S").to_string()
+}
+
+/// Score how well `query` matches `candidate` as a case insensitive subsequence. Matches at the
+/// start of words and runs of consecutive characters score higher. Returns `None` if there's no
+/// match at all.
+#[must_use]
+pub fn fuzzy_score(query: &str, candidate: &str) -> Option<i64> {
+    let mut score = 0;
+    let mut previous = None;
+    let mut last_match = None;
+    let mut candidate = candidate
+        .chars()
+        .map(|c| c.to_ascii_lowercase())
+        .enumerate();
+
+    for wanted in query.chars().map(|c| c.to_ascii_lowercase()) {
+        loop {
+            let (i, c) = candidate.next()?;
+            let word_start = previous.is_none_or(|p: char| !p.is_alphanumeric());
+            previous = Some(c);
+            if c != wanted {
+                score -= 1;
+                continue;
+            }
+            if word_start {
+                score += 10;
+            }
+            if last_match.is_some_and(|last| last + 1 == i) {
+                score += 5;
+            }
+            last_match = Some(i);
+            break;
+        }
+    }
+
+    Some(score)
+}
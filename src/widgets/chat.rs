@@ -0,0 +1,90 @@
+use crate::chat::History;
+use crate::widgets::search::Search;
+use ratatui::prelude::*;
+use ratatui::widgets::{Block, Widget};
+
+/// The scrollable chat. Only the lines in view are drawn, so it costs the same however long the
+/// conversation is. The history has to be laid out first.
+///
+/// With a block that has a left border, the border marks the selected message and the pinned
+/// ones.
+#[derive(Clone, Debug)]
+pub struct ChatView<'a> {
+    history: &'a History,
+    /// The first line in view
+    scroll: usize,
+    search: Option<&'a Search>,
+    /// The index of the selected message
+    selected: Option<usize>,
+    block: Option<Block<'a>>,
+}
+
+impl<'a> ChatView<'a> {
+    #[must_use]
+    pub const fn new(history: &'a History, scroll: usize) -> Self {
+        Self {
+            history,
+            scroll,
+            search: None,
+            selected: None,
+            block: None,
+        }
+    }
+
+    /// Highlight the matches of a search.
+    #[must_use]
+    pub const fn search(mut self, search: Option<&'a Search>) -> Self {
+        self.search = search;
+        self
+    }
+
+    /// Mark the message at `index` as selected.
+    #[must_use]
+    pub const fn selected(mut self, index: Option<usize>) -> Self {
+        self.selected = index;
+        self
+    }
+
+    #[must_use]
+    pub fn block(mut self, block: Block<'a>) -> Self {
+        self.block = Some(block);
+        self
+    }
+}
+
+impl Widget for ChatView<'_> {
+    fn render(self, outer: Rect, buf: &mut Buffer) {
+        let area = self.block.map_or(outer, |block| {
+            let inner = block.inner(outer);
+            block.render(outer, buf);
+            inner
+        });
+        let gutter = (area.x > outer.x).then(|| area.x - 1);
+
+        let lines = self.history.lines(self.scroll, usize::from(area.height));
+        for ((y, index), line) in (area.top()..).zip(self.scroll..).zip(lines) {
+            match self.search {
+                Some(search) => {
+                    let line = search.highlight(index, line.clone());
+                    buf.set_line(area.x, y, &line, area.width);
+                }
+                None => {
+                    buf.set_line(area.x, y, line, area.width);
+                }
+            }
+
+            let Some((x, message)) = gutter.zip(self.history.message_at(index)) else {
+                continue;
+            };
+            if self.selected == Some(message) {
+                buf.get_mut(x, y)
+                    .set_symbol("▌")
+                    .set_style(Style::new().fg(Color::Cyan));
+            } else if self.history.pinned.contains(&message) {
+                buf.get_mut(x, y)
+                    .set_symbol("┃")
+                    .set_style(Style::new().fg(Color::Yellow));
+            }
+        }
+    }
+}
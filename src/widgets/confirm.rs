@@ -0,0 +1,56 @@
+use crate::widgets::centered;
+use ratatui::prelude::*;
+use ratatui::widgets::{Block, BorderType, Borders, Clear, Paragraph, Widget, Wrap};
+
+/// A popup asking whether to go ahead with something.
+#[derive(Clone, Debug)]
+pub struct Confirm {
+    pub title: String,
+    pub body: String,
+    /// The keys that can be pressed and what they do
+    pub options: Vec<(char, String)>,
+}
+
+impl Confirm {
+    #[must_use]
+    pub const fn new(title: String, body: String, options: Vec<(char, String)>) -> Self {
+        Self {
+            title,
+            body,
+            options,
+        }
+    }
+}
+
+impl Widget for Confirm {
+    fn render(self, area: Rect, buf: &mut Buffer) {
+        let height = u16::try_from(self.body.lines().count()).unwrap_or(u16::MAX);
+        let area = centered(area, 80, height.saturating_add(5));
+        Clear.render(area, buf);
+
+        let block = Block::default()
+            .title(self.title)
+            .borders(Borders::ALL)
+            .border_type(BorderType::Rounded)
+            .border_style(Style::default().fg(Color::Yellow));
+        let inner = block.inner(area);
+        block.render(area, buf);
+
+        let layout = Layout::default()
+            .direction(Direction::Vertical)
+            .constraints([Constraint::Min(1), Constraint::Length(1)])
+            .split(inner);
+
+        Paragraph::new(self.body)
+            .wrap(Wrap { trim: false })
+            .render(layout[0], buf);
+        let help: Vec<String> = self
+            .options
+            .iter()
+            .map(|(key, action)| format!("{key} {action}"))
+            .collect();
+        Paragraph::new(help.join("  "))
+            .style(Style::default().fg(Color::DarkGray))
+            .render(layout[1], buf);
+    }
+}
@@ -1 +1,25 @@
+use ratatui::layout::Rect;
+
+pub mod chat;
+pub mod confirm;
 pub mod error;
+pub mod palette;
+pub mod preview;
+pub mod recall;
+pub mod reverse_search;
+pub mod search;
+pub mod system;
+pub mod template;
+
+/// A `width` by `height` rectangle in the middle of `area`, shrunk to fit if it's too big.
+#[must_use]
+pub fn centered(area: Rect, width: u16, height: u16) -> Rect {
+    let width = width.min(area.width);
+    let height = height.min(area.height);
+    Rect::new(
+        area.x + (area.width - width) / 2,
+        area.y + (area.height - height) / 2,
+        width,
+        height,
+    )
+}
@@ -0,0 +1,112 @@
+use crate::action::{Action, KeyBinding};
+use crate::utils::fuzzy_score;
+use crate::widgets::centered;
+use ratatui::prelude::*;
+use ratatui::widgets::{Block, BorderType, Borders, Clear, Paragraph, Widget};
+
+/// A searchable list of every action.
+#[derive(Clone, Debug, Default)]
+pub struct Palette {
+    pub query: String,
+    pub selected: usize,
+    /// The key the shortcuts follow, if they have one
+    pub leader: Option<KeyBinding>,
+}
+
+impl Palette {
+    #[must_use]
+    pub fn new(leader: Option<KeyBinding>) -> Self {
+        Self {
+            leader,
+            ..Self::default()
+        }
+    }
+
+    /// The actions matching the query, best match first.
+    #[must_use]
+    pub fn matches(&self) -> Vec<Action> {
+        let mut scored: Vec<(i64, Action)> = Action::ALL
+            .into_iter()
+            .filter_map(|action| Some((fuzzy_score(&self.query, action.name())?, action)))
+            .collect();
+        scored.sort_by_key(|(score, _)| std::cmp::Reverse(*score));
+        scored.into_iter().map(|(_, action)| action).collect()
+    }
+
+    #[must_use]
+    pub fn selected_action(&self) -> Option<Action> {
+        self.matches().get(self.selected).copied()
+    }
+
+    pub fn push(&mut self, c: char) {
+        self.query.push(c);
+        self.selected = 0;
+    }
+
+    pub fn pop(&mut self) {
+        self.query.pop();
+        self.selected = 0;
+    }
+
+    pub fn next(&mut self) {
+        let count = self.matches().len();
+        if count > 0 {
+            self.selected = (self.selected + 1) % count;
+        }
+    }
+
+    pub fn previous(&mut self) {
+        let count = self.matches().len();
+        if count > 0 {
+            self.selected = (self.selected + count - 1) % count;
+        }
+    }
+}
+
+impl Widget for Palette {
+    fn render(self, area: Rect, buf: &mut Buffer) {
+        let matches = self.matches();
+        let height = u16::try_from(matches.len()).unwrap_or(u16::MAX).max(1) + 3;
+        let area = centered(area, 60, height);
+        Clear.render(area, buf);
+
+        let width = usize::from(area.width.saturating_sub(2));
+        let mut lines = vec![Line::from(vec![
+            Span::styled("> ", Style::default().fg(Color::Cyan)),
+            Span::raw(self.query.clone()),
+        ])];
+        if matches.is_empty() {
+            lines.push(Line::styled(
+                "No matching actions",
+                Style::default().fg(Color::DarkGray),
+            ));
+        }
+        for (i, action) in matches.into_iter().enumerate() {
+            let binding = match (self.leader, action.binding()) {
+                (Some(leader), Some(binding)) => format!("{leader} {binding}"),
+                (None, Some(binding)) => binding.to_string(),
+                (_, None) => String::new(),
+            };
+            let padding = width.saturating_sub(action.name().len() + binding.len());
+            let style = if i == self.selected {
+                Style::default().add_modifier(Modifier::REVERSED)
+            } else {
+                Style::default()
+            };
+            lines.push(Line::from(vec![
+                Span::styled(format!("{}{}", action.name(), " ".repeat(padding)), style),
+                Span::styled(binding, style.fg(Color::DarkGray)),
+            ]));
+        }
+
+        Paragraph::new(lines)
+            .block(
+                Block::default()
+                    .title("Command Palette")
+                    .borders(Borders::ALL)
+                    .border_type(BorderType::Rounded)
+                    .border_style(Style::default().fg(Color::Cyan)),
+            )
+            .render(area, buf);
+    }
+}
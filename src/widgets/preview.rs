@@ -0,0 +1,72 @@
+use crate::attach::{Expanded, Image};
+use crate::widgets::centered;
+use ratatui::prelude::*;
+use ratatui::widgets::{Block, BorderType, Borders, Clear, Paragraph, Widget, Wrap};
+
+/// An overlay showing an expanded message before it's sent.
+#[derive(Clone, Debug)]
+pub struct Preview {
+    pub text: String,
+    pub images: Vec<Image>,
+    pub tokens: usize,
+    pub scroll: u16,
+}
+
+impl Preview {
+    #[must_use]
+    pub fn new(message: Expanded, tokens: usize) -> Self {
+        Self {
+            text: message.text,
+            images: message.images,
+            tokens,
+            scroll: 0,
+        }
+    }
+
+    pub const fn scroll_down(&mut self) {
+        self.scroll = self.scroll.saturating_add(1);
+    }
+
+    pub const fn scroll_up(&mut self) {
+        self.scroll = self.scroll.saturating_sub(1);
+    }
+}
+
+impl Widget for Preview {
+    fn render(self, area: Rect, buf: &mut Buffer) {
+        let area = centered(
+            area,
+            area.width.saturating_sub(8),
+            area.height.saturating_sub(4),
+        );
+        Clear.render(area, buf);
+
+        let block = Block::default()
+            .title(format!("Preview · {} tokens", self.tokens))
+            .borders(Borders::ALL)
+            .border_type(BorderType::Rounded)
+            .border_style(Style::default().fg(Color::Cyan));
+        let inner = block.inner(area);
+        block.render(area, buf);
+
+        let layout = Layout::default()
+            .direction(Direction::Vertical)
+            .constraints([Constraint::Min(1), Constraint::Length(1)])
+            .split(inner);
+
+        let mut lines: Vec<Line> = self.text.lines().map(Line::raw).collect();
+        for image in &self.images {
+            lines.push(Line::styled(
+                format!("[image: {}]", image.name),
+                Style::new().fg(Color::Black).bg(Color::Magenta),
+            ));
+        }
+        Paragraph::new(lines)
+            .wrap(Wrap { trim: false })
+            .scroll((self.scroll, 0))
+            .render(layout[0], buf);
+        Paragraph::new("Enter send  Esc keep editing  Up/Down scroll")
+            .style(Style::default().fg(Color::DarkGray))
+            .render(layout[1], buf);
+    }
+}
@@ -0,0 +1,141 @@
+use crate::archive::Hit;
+use crate::widgets::centered;
+use ratatui::prelude::*;
+use ratatui::widgets::{Block, BorderType, Borders, Clear, Paragraph, Widget};
+
+/// An overlay for searching saved conversations by meaning.
+#[derive(Clone, Debug, Default)]
+pub struct Recall {
+    pub query: String,
+    /// The query the hits are for
+    pub searched: Option<String>,
+    pub hits: Vec<Hit>,
+    pub selected: usize,
+    /// Whether a search is running
+    pub searching: bool,
+    pub error: Option<String>,
+}
+
+impl Recall {
+    #[must_use]
+    pub fn new(query: String) -> Self {
+        Self {
+            query,
+            ..Self::default()
+        }
+    }
+
+    pub fn push(&mut self, c: char) {
+        self.query.push(c);
+    }
+
+    pub fn pop(&mut self) {
+        self.query.pop();
+    }
+
+    pub const fn next(&mut self) {
+        if !self.hits.is_empty() {
+            self.selected = (self.selected + 1) % self.hits.len();
+        }
+    }
+
+    pub const fn previous(&mut self) {
+        if !self.hits.is_empty() {
+            self.selected = (self.selected + self.hits.len() - 1) % self.hits.len();
+        }
+    }
+
+    /// Whether the query changed since the last search.
+    #[must_use]
+    pub fn is_stale(&self) -> bool {
+        !self.query.trim().is_empty() && self.searched.as_deref() != Some(self.query.as_str())
+    }
+
+    #[must_use]
+    pub fn selected_hit(&self) -> Option<&Hit> {
+        self.hits.get(self.selected)
+    }
+
+    /// Show the outcome of a search, unless the query has changed since it started.
+    pub fn finish(&mut self, query: String, hits: Result<Vec<Hit>, String>) {
+        if query != self.query {
+            return;
+        }
+        self.searching = false;
+        self.selected = 0;
+        self.searched = Some(query);
+        match hits {
+            Ok(hits) => {
+                self.hits = hits;
+                self.error = None;
+            }
+            Err(error) => {
+                self.hits.clear();
+                self.error = Some(error);
+            }
+        }
+    }
+}
+
+impl Widget for Recall {
+    fn render(self, area: Rect, buf: &mut Buffer) {
+        let area = centered(area, 90, 24);
+        Clear.render(area, buf);
+
+        let block = Block::default()
+            .title("Search conversations")
+            .borders(Borders::ALL)
+            .border_type(BorderType::Rounded)
+            .border_style(Style::default().fg(Color::Cyan));
+        let inner = block.inner(area);
+        block.render(area, buf);
+
+        let layout = Layout::default()
+            .direction(Direction::Vertical)
+            .constraints([Constraint::Min(1), Constraint::Length(1)])
+            .split(inner);
+
+        let width = usize::from(layout[0].width);
+        let mut lines = vec![Line::from(vec![
+            Span::styled("> ", Style::default().fg(Color::Cyan)),
+            Span::raw(self.query.clone()),
+        ])];
+        let status = if self.searching {
+            Some("Searching…".to_string())
+        } else if let Some(error) = &self.error {
+            Some(error.clone())
+        } else if self.searched.is_some() && self.hits.is_empty() {
+            Some("Nothing found.".to_string())
+        } else {
+            None
+        };
+        if let Some(status) = status {
+            lines.push(Line::styled(status, Style::default().fg(Color::DarkGray)));
+        }
+        for (i, hit) in self.hits.iter().enumerate() {
+            let style = if i == self.selected {
+                Style::default().add_modifier(Modifier::REVERSED)
+            } else {
+                Style::default()
+            };
+            let label = format!("{} · {}  ", hit.conversation, hit.role);
+            let text: String = hit
+                .text
+                .split_whitespace()
+                .collect::<Vec<_>>()
+                .join(" ")
+                .chars()
+                .take(width.saturating_sub(label.chars().count()))
+                .collect();
+            lines.push(Line::from(vec![
+                Span::styled(label, style.fg(Color::DarkGray)),
+                Span::styled(text, style),
+            ]));
+        }
+        Paragraph::new(lines).render(layout[0], buf);
+
+        Paragraph::new("Enter search or open  Up/Down choose  Esc close")
+            .style(Style::default().fg(Color::DarkGray))
+            .render(layout[1], buf);
+    }
+}
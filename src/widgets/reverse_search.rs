@@ -0,0 +1,93 @@
+use crate::input_history::InputHistory;
+use ratatui::prelude::*;
+use ratatui::widgets::{Paragraph, Widget};
+
+/// A search back through the prompt history as you type, like `C-r` in a shell.
+#[derive(Clone, Debug, Default)]
+pub struct ReverseSearch {
+    pub query: String,
+    /// The index of the prompt found
+    pub found: Option<usize>,
+    /// Whether nothing matches the query. The last prompt found stays in the editor.
+    pub failing: bool,
+    /// What was in the editor before the search
+    pub draft: String,
+}
+
+impl ReverseSearch {
+    #[must_use]
+    pub fn new(draft: String) -> Self {
+        Self {
+            draft,
+            ..Self::default()
+        }
+    }
+
+    /// Add to the query. The prompt found stays if it still matches.
+    pub fn push(&mut self, c: char, history: &InputHistory) {
+        self.push_str(c.encode_utf8(&mut [0; 4]), history);
+    }
+
+    /// Add `text` to the query, like a paste.
+    pub fn push_str(&mut self, text: &str, history: &InputHistory) {
+        self.query.push_str(text);
+        let before = self.found.map_or(history.len(), |found| found + 1);
+        self.find(history, before);
+    }
+
+    /// Take the last character off the query and search again from the newest prompt.
+    pub fn pop(&mut self, history: &InputHistory) {
+        self.query.pop();
+        if self.query.is_empty() {
+            self.found = None;
+            self.failing = false;
+        } else {
+            self.find(history, history.len());
+        }
+    }
+
+    /// Go to the next older prompt that matches.
+    pub fn older(&mut self, history: &InputHistory) {
+        if !self.query.is_empty() {
+            self.find(history, self.found.unwrap_or(history.len()));
+        }
+    }
+
+    fn find(&mut self, history: &InputHistory, before: usize) {
+        match history.find(&self.query, before) {
+            Some(found) => {
+                self.found = Some(found);
+                self.failing = false;
+            }
+            None => self.failing = true,
+        }
+    }
+
+    /// The text to show in the editor.
+    #[must_use]
+    pub fn text<'a>(&'a self, history: &'a InputHistory) -> &'a str {
+        self.found
+            .and_then(|found| history.get(found))
+            .unwrap_or(&self.draft)
+    }
+}
+
+impl Widget for ReverseSearch {
+    fn render(self, area: Rect, buf: &mut Buffer) {
+        let label = if self.failing {
+            "failing reverse search: "
+        } else {
+            "reverse search: "
+        };
+        Paragraph::new(Line::from(vec![
+            Span::styled(label, Style::default().fg(Color::Cyan)),
+            Span::raw(self.query),
+            Span::styled(
+                "  C-r older  Enter accept  Esc cancel",
+                Style::default().fg(Color::DarkGray),
+            ),
+        ]))
+        .style(Style::default().bg(Color::Black))
+        .render(area, buf);
+    }
+}
@@ -0,0 +1,278 @@
+use crate::chat::History;
+use ratatui::prelude::*;
+use ratatui::widgets::{Paragraph, Widget};
+use regex::{Regex, RegexBuilder};
+use std::collections::HashMap;
+
+/// Incremental regex search over the rendered chat.
+#[derive(Clone, Debug, Default)]
+pub struct Search {
+    pub query: String,
+    regex: Option<Regex>,
+    /// Why the query isn't a valid regex
+    pub error: Option<String>,
+    pub matches: Vec<Match>,
+    pub current: usize,
+    /// Whether the query changed since the matches were found
+    changed: bool,
+    /// Whether to scroll to the current match the next time the chat is drawn
+    jump: bool,
+    /// The revision of the chat the matches were found in
+    revision: Option<u64>,
+    /// The matches in each message by the version of its lines, with lines counted from the
+    /// start of the message. Only messages that changed are searched again.
+    found: HashMap<u64, Vec<Match>>,
+}
+
+/// A match, which can go over several lines when a message is wrapped.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Match {
+    /// The line and the byte range in it of each part of the match
+    pub parts: Vec<(usize, usize, usize)>,
+}
+
+impl Search {
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn push(&mut self, c: char) {
+        self.query.push(c);
+        self.compile();
+    }
+
+    pub fn push_str(&mut self, text: &str) {
+        self.query.push_str(text);
+        self.compile();
+    }
+
+    pub fn pop(&mut self) {
+        self.query.pop();
+        self.compile();
+    }
+
+    /// Build the regex for the query. It ignores case unless the query has a capital letter.
+    fn compile(&mut self) {
+        self.changed = true;
+        self.regex = None;
+        self.error = None;
+        if self.query.is_empty() {
+            return;
+        }
+        match RegexBuilder::new(&self.query)
+            .case_insensitive(!self.query.chars().any(char::is_uppercase))
+            .build()
+        {
+            Ok(regex) => self.regex = Some(regex),
+            Err(err) => {
+                self.error = Some(
+                    err.to_string()
+                        .lines()
+                        .last()
+                        .unwrap_or_default()
+                        .to_string(),
+                );
+            }
+        }
+    }
+
+    pub const fn next(&mut self) {
+        if !self.matches.is_empty() {
+            self.current = (self.current + 1) % self.matches.len();
+            self.jump = true;
+        }
+    }
+
+    pub const fn previous(&mut self) {
+        if !self.matches.is_empty() {
+            self.current = (self.current + self.matches.len() - 1) % self.matches.len();
+            self.jump = true;
+        }
+    }
+
+    /// Find the matches in the laid out chat, if it or the query changed since the last time.
+    /// `scroll` is the first line in view.
+    ///
+    /// Returns the line to scroll to if the view should move to the current match.
+    pub fn update(&mut self, history: &History, scroll: usize) -> Option<usize> {
+        if self.changed || self.revision != Some(history.revision()) {
+            self.revision = Some(history.revision());
+            self.find(history);
+        }
+
+        if self.changed {
+            self.changed = false;
+            self.current = self
+                .matches
+                .iter()
+                .position(|found| found.line() >= scroll)
+                .unwrap_or(0);
+            self.jump = true;
+        }
+        self.current = self.current.min(self.matches.len().saturating_sub(1));
+
+        let jump = std::mem::take(&mut self.jump);
+        self.matches
+            .get(self.current)
+            .filter(|_| jump)
+            .map(Match::line)
+    }
+
+    fn find(&mut self, history: &History) {
+        self.matches.clear();
+        if self.changed {
+            self.found.clear();
+        }
+        let Some(regex) = &self.regex else {
+            return;
+        };
+
+        let mut found = HashMap::new();
+        for ((version, lines), &start) in history.rendered_messages().zip(&history.message_lines) {
+            let matches = self
+                .found
+                .remove(&version)
+                .unwrap_or_else(|| find_in(regex, lines));
+            self.matches.extend(matches.iter().map(|found| {
+                Match {
+                    parts: found
+                        .parts
+                        .iter()
+                        .map(|&(line, from, to)| (start + line, from, to))
+                        .collect(),
+                }
+            }));
+            found.insert(version, matches);
+        }
+        self.found = found;
+    }
+
+    /// Highlight the matches on line `index` of the chat.
+    #[must_use]
+    pub fn highlight(&self, index: usize, mut line: Line<'static>) -> Line<'static> {
+        // Matches don't overlap, so they're in order of their last line too
+        let first = self
+            .matches
+            .partition_point(|found| found.last_line() < index);
+        for (i, found) in self.matches.iter().enumerate().skip(first) {
+            if found.line() > index {
+                break;
+            }
+            let style = if i == self.current {
+                Style::new().fg(Color::Black).bg(Color::Yellow)
+            } else {
+                Style::new().add_modifier(Modifier::REVERSED)
+            };
+            for &(_, start, end) in found.parts.iter().filter(|part| part.0 == index) {
+                line = restyle(line, start, end, style);
+            }
+        }
+        line
+    }
+}
+
+impl Match {
+    /// The line the match starts on.
+    #[must_use]
+    pub fn line(&self) -> usize {
+        self.parts.first().map_or(0, |(line, _, _)| *line)
+    }
+
+    /// The line the match ends on.
+    #[must_use]
+    pub fn last_line(&self) -> usize {
+        self.parts.last().map_or(0, |(line, _, _)| *line)
+    }
+}
+
+/// The matches of `regex` in the lines of one message.
+fn find_in(regex: &Regex, lines: &[Line]) -> Vec<Match> {
+    // Wrapped lines are joined back together so matches can span them
+    let mut text = String::new();
+    let mut starts = Vec::with_capacity(lines.len());
+    for (i, line) in lines.iter().enumerate() {
+        if i > 0 {
+            text.push(' ');
+        }
+        starts.push(text.len());
+        for span in &line.spans {
+            text.push_str(&span.content);
+        }
+    }
+
+    regex
+        .find_iter(&text)
+        .filter(|found| !found.is_empty())
+        .map(|found| {
+            let first = starts.partition_point(|&start| start <= found.start()) - 1;
+            let parts = (first..starts.len())
+                .take_while(|&i| starts[i] < found.end())
+                .filter_map(|i| {
+                    let end = starts.get(i + 1).map_or(text.len(), |next| next - 1);
+                    let from = found.start().max(starts[i]);
+                    let to = found.end().min(end);
+                    (from < to).then(|| (i, from - starts[i], to - starts[i]))
+                })
+                .collect();
+            Match { parts }
+        })
+        .collect()
+}
+
+/// Patch `style` onto the bytes from `start` to `end` of `line`, splitting spans as needed.
+fn restyle(line: Line<'static>, start: usize, end: usize, style: Style) -> Line<'static> {
+    let mut spans = vec![];
+    let mut offset = 0;
+    for span in line.spans {
+        let content = span.content.to_string();
+        let (from, to) = (
+            start.clamp(offset, offset + content.len()) - offset,
+            end.clamp(offset, offset + content.len()) - offset,
+        );
+        offset += content.len();
+        if from == to {
+            spans.push(Span::styled(content, span.style));
+            continue;
+        }
+        for (text, patched) in [
+            (&content[..from], false),
+            (&content[from..to], true),
+            (&content[to..], false),
+        ] {
+            if !text.is_empty() {
+                let style = if patched {
+                    span.style.patch(style)
+                } else {
+                    span.style
+                };
+                spans.push(Span::styled(text.to_string(), style));
+            }
+        }
+    }
+    Line {
+        spans,
+        alignment: line.alignment,
+    }
+}
+
+impl Widget for &Search {
+    fn render(self, area: Rect, buf: &mut Buffer) {
+        let status = match &self.error {
+            Some(error) => error.clone(),
+            None if self.query.is_empty() => "Type a regex".to_string(),
+            None if self.matches.is_empty() => "No matches".to_string(),
+            None => format!("{}/{}", self.current + 1, self.matches.len()),
+        };
+        Paragraph::new(Line::from(vec![
+            Span::styled("/", Style::default().fg(Color::Cyan)),
+            Span::raw(self.query.as_str()),
+            Span::styled(
+                format!("  {status} · Enter next  Up previous  Esc close"),
+                Style::default().fg(Color::DarkGray),
+            ),
+        ]))
+        .style(Style::default().bg(Color::Black))
+        .render(area, buf);
+    }
+}
@@ -0,0 +1,139 @@
+use crate::input::StyledTextArea;
+use crate::widgets::centered;
+use ratatui::prelude::*;
+use ratatui::widgets::{Block, BorderType, Borders, Clear, Paragraph, Widget, Wrap};
+use tui_textarea::TextArea;
+
+/// An overlay for viewing and changing the system prompts of the conversation.
+#[derive(Clone, Debug)]
+pub struct SystemEditor<'a> {
+    pub prompts: Vec<String>,
+    pub selected: usize,
+    /// The prompt being edited, if any
+    pub editor: Option<TextArea<'a>>,
+}
+
+impl SystemEditor<'_> {
+    #[must_use]
+    pub const fn new(prompts: Vec<String>) -> Self {
+        Self {
+            prompts,
+            selected: 0,
+            editor: None,
+        }
+    }
+
+    pub const fn next(&mut self) {
+        if self.selected + 1 < self.prompts.len() {
+            self.selected += 1;
+        }
+    }
+
+    pub const fn previous(&mut self) {
+        self.selected = self.selected.saturating_sub(1);
+    }
+
+    /// Add an empty prompt after the others and start editing it.
+    pub fn add(&mut self) {
+        self.prompts.push(String::new());
+        self.selected = self.prompts.len() - 1;
+        self.edit();
+    }
+
+    /// Start editing the selected prompt.
+    pub fn edit(&mut self) {
+        if let Some(prompt) = self.prompts.get(self.selected) {
+            let mut editor = TextArea::new(prompt.lines().map(ToString::to_string).collect());
+            editor.set_block(
+                StyledTextArea::styled_default()
+                    .block()
+                    .unwrap()
+                    .clone()
+                    .title("Editing (Esc to finish)"),
+            );
+            editor.move_cursor(tui_textarea::CursorMove::Bottom);
+            editor.move_cursor(tui_textarea::CursorMove::End);
+            self.editor = Some(editor);
+        }
+    }
+
+    /// Stop editing and keep the changes.
+    pub fn finish_edit(&mut self) {
+        if let Some(mut editor) = self.editor.take() {
+            let text = StyledTextArea::text(&mut editor);
+            if text.trim().is_empty() {
+                self.remove();
+            } else if let Some(prompt) = self.prompts.get_mut(self.selected) {
+                *prompt = text;
+            }
+        }
+    }
+
+    /// Remove the selected prompt.
+    pub fn remove(&mut self) {
+        if self.selected < self.prompts.len() {
+            self.prompts.remove(self.selected);
+            self.selected = self.selected.min(self.prompts.len().saturating_sub(1));
+        }
+    }
+}
+
+impl Widget for SystemEditor<'_> {
+    fn render(self, area: Rect, buf: &mut Buffer) {
+        let area = centered(area, 80, 24);
+        Clear.render(area, buf);
+
+        let block = Block::default()
+            .title("System Prompt")
+            .borders(Borders::ALL)
+            .border_type(BorderType::Rounded)
+            .border_style(Style::default().fg(Color::Green));
+        let inner = block.inner(area);
+        block.render(area, buf);
+
+        let layout = Layout::default()
+            .direction(Direction::Vertical)
+            .constraints([Constraint::Min(3), Constraint::Length(1)])
+            .split(inner);
+
+        if let Some(editor) = &self.editor {
+            editor.widget().render(layout[0], buf);
+        } else {
+            let mut lines = vec![];
+            if self.prompts.is_empty() {
+                lines.push(Line::styled(
+                    "There's no system prompt. Press a to add one.",
+                    Style::default().fg(Color::DarkGray),
+                ));
+            }
+            for (i, prompt) in self.prompts.iter().enumerate() {
+                let style = if i == self.selected {
+                    Style::default().add_modifier(Modifier::REVERSED)
+                } else {
+                    Style::default()
+                };
+                for (n, line) in prompt.lines().enumerate() {
+                    let prefix = if n == 0 {
+                        format!("{}. ", i + 1)
+                    } else {
+                        "   ".to_string()
+                    };
+                    lines.push(Line::styled(prefix + line, style));
+                }
+                lines.push(Line::default());
+            }
+            Paragraph::new(lines)
+                .wrap(Wrap { trim: false })
+                .render(layout[0], buf);
+        }
+
+        let help = if self.editor.is_some() {
+            "Esc finish editing"
+        } else {
+            "a add  e edit  d delete  s save as default  Esc close"
+        };
+        Paragraph::new(help)
+            .style(Style::default().fg(Color::DarkGray))
+            .render(layout[1], buf);
+    }
+}
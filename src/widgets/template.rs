@@ -0,0 +1,232 @@
+use crate::input::StyledTextArea;
+use crate::template::Template;
+use crate::utils::fuzzy_score;
+use crate::widgets::centered;
+use ratatui::prelude::*;
+use ratatui::widgets::{Block, BorderType, Borders, Clear, Paragraph, Widget};
+use std::cmp::Reverse;
+use std::collections::HashMap;
+use tui_textarea::{Input, Key, TextArea};
+
+/// An overlay for picking a template and filling in its variables.
+#[derive(Clone, Debug)]
+pub struct TemplatePicker<'a> {
+    pub templates: Vec<Template>,
+    pub query: String,
+    pub selected: usize,
+    /// The template being filled in
+    pub form: Option<Form<'a>>,
+}
+
+/// An input field for each variable of a template.
+#[derive(Clone, Debug)]
+pub struct Form<'a> {
+    pub template: Template,
+    pub fields: Vec<(String, TextArea<'a>)>,
+    pub focused: usize,
+}
+
+impl TemplatePicker<'_> {
+    #[must_use]
+    pub const fn new(templates: Vec<Template>) -> Self {
+        Self {
+            templates,
+            query: String::new(),
+            selected: 0,
+            form: None,
+        }
+    }
+
+    /// The templates matching the query, best match first.
+    #[must_use]
+    pub fn matches(&self) -> Vec<&Template> {
+        let mut scored: Vec<(i64, &Template)> = self
+            .templates
+            .iter()
+            .filter_map(|template| {
+                let name = fuzzy_score(&self.query, &template.name);
+                let description = fuzzy_score(&self.query, &template.description);
+                Some((name.max(description)?, template))
+            })
+            .collect();
+        scored.sort_by_key(|(score, _)| Reverse(*score));
+        scored.into_iter().map(|(_, template)| template).collect()
+    }
+
+    pub fn push(&mut self, c: char) {
+        self.query.push(c);
+        self.selected = 0;
+    }
+
+    pub fn pop(&mut self) {
+        self.query.pop();
+        self.selected = 0;
+    }
+
+    pub fn next(&mut self) {
+        let count = self.matches().len();
+        if count > 0 {
+            self.selected = (self.selected + 1) % count;
+        }
+    }
+
+    pub fn previous(&mut self) {
+        let count = self.matches().len();
+        if count > 0 {
+            self.selected = (self.selected + count - 1) % count;
+        }
+    }
+
+    /// Start filling in the selected template.
+    pub fn choose(&mut self) {
+        if let Some(template) = self.matches().get(self.selected) {
+            self.form = Some(Form::new((*template).clone()));
+        }
+    }
+}
+
+impl Form<'_> {
+    #[must_use]
+    pub fn new(template: Template) -> Self {
+        let fields = template
+            .variables()
+            .into_iter()
+            .map(|name| {
+                let mut field = StyledTextArea::styled_default();
+                field.set_block(field.block().unwrap().clone().title(name.clone()));
+                field.set_cursor_line_style(Style::default());
+                (name, field)
+            })
+            .collect();
+        Self {
+            template,
+            fields,
+            focused: 0,
+        }
+    }
+
+    pub const fn next(&mut self) {
+        if !self.fields.is_empty() {
+            self.focused = (self.focused + 1) % self.fields.len();
+        }
+    }
+
+    pub const fn previous(&mut self) {
+        if !self.fields.is_empty() {
+            self.focused = (self.focused + self.fields.len() - 1) % self.fields.len();
+        }
+    }
+
+    /// Type into the focused field. Fields only hold a single line.
+    pub fn input(&mut self, input: Input) {
+        if input.key == Key::Enter {
+            return;
+        }
+        if let Some((_, field)) = self.fields.get_mut(self.focused) {
+            field.input(input);
+        }
+    }
+
+    #[must_use]
+    pub const fn is_last(&self) -> bool {
+        self.fields.is_empty() || self.focused + 1 == self.fields.len()
+    }
+
+    /// The body of the template with the variables filled in.
+    #[must_use]
+    pub fn fill(&self) -> String {
+        let values: HashMap<String, String> = self
+            .fields
+            .iter()
+            .map(|(name, field)| (name.clone(), field.lines().join("\n")))
+            .collect();
+        self.template.fill(&values)
+    }
+}
+
+impl Widget for TemplatePicker<'_> {
+    fn render(self, area: Rect, buf: &mut Buffer) {
+        let area = centered(area, 70, 20);
+        Clear.render(area, buf);
+
+        let title = self.form.as_ref().map_or_else(
+            || "Templates".to_string(),
+            |form| format!("Template: {}", form.template.name),
+        );
+        let block = Block::default()
+            .title(title)
+            .borders(Borders::ALL)
+            .border_type(BorderType::Rounded)
+            .border_style(Style::default().fg(Color::Cyan));
+        let inner = block.inner(area);
+        block.render(area, buf);
+
+        let layout = Layout::default()
+            .direction(Direction::Vertical)
+            .constraints([Constraint::Min(1), Constraint::Length(1)])
+            .split(inner);
+
+        let help = if let Some(form) = &self.form {
+            let mut constraints = vec![Constraint::Length(3); form.fields.len()];
+            constraints.push(Constraint::Min(0));
+            let fields = Layout::default()
+                .direction(Direction::Vertical)
+                .constraints(constraints)
+                .split(layout[0]);
+            if form.fields.is_empty() {
+                Paragraph::new("This template has no variables.").render(fields[0], buf);
+            }
+            for (i, (_, field)) in form.fields.iter().enumerate() {
+                let mut field = field.clone();
+                if i == form.focused {
+                    field.set_block(
+                        field
+                            .block()
+                            .unwrap()
+                            .clone()
+                            .border_style(Style::default().fg(Color::Cyan)),
+                    );
+                } else {
+                    field.set_cursor_style(Style::default());
+                }
+                field.widget().render(fields[i], buf);
+            }
+            "Tab next field  Enter insert  Esc back"
+        } else {
+            let width = usize::from(layout[0].width);
+            let mut lines = vec![Line::from(vec![
+                Span::styled("> ", Style::default().fg(Color::Cyan)),
+                Span::raw(self.query.clone()),
+            ])];
+            let matches = self.matches();
+            if matches.is_empty() {
+                lines.push(Line::styled(
+                    "No templates. Add some to the templates directory next to your config.",
+                    Style::default().fg(Color::DarkGray),
+                ));
+            }
+            for (i, template) in matches.into_iter().enumerate() {
+                let style = if i == self.selected {
+                    Style::default().add_modifier(Modifier::REVERSED)
+                } else {
+                    Style::default()
+                };
+                let description: String = template
+                    .description
+                    .chars()
+                    .take(width.saturating_sub(template.name.len() + 2))
+                    .collect();
+                lines.push(Line::from(vec![
+                    Span::styled(template.name.clone(), style),
+                    Span::styled(format!("  {description}"), style.fg(Color::DarkGray)),
+                ]));
+            }
+            Paragraph::new(lines).render(layout[0], buf);
+            "Enter choose  Esc close"
+        };
+
+        Paragraph::new(help)
+            .style(Style::default().fg(Color::DarkGray))
+            .render(layout[1], buf);
+    }
+}
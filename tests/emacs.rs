@@ -0,0 +1,132 @@
+use gptrs::input::Emacs;
+use tui_textarea::{CursorMove, Input, Key, TextArea};
+
+/// The inputs for keys written like in Emacs, such as `C-a C-k x C-y`.
+fn inputs(keys: &str) -> Vec<Input> {
+    keys.split(' ')
+        .map(|key| {
+            let (ctrl, key) = key
+                .strip_prefix("C-")
+                .map_or((false, key), |key| (true, key));
+            let (alt, key) = key
+                .strip_prefix("M-")
+                .map_or((false, key), |key| (true, key));
+            let key = match key {
+                "RET" => Key::Enter,
+                "DEL" => Key::Backspace,
+                "SPC" => Key::Char(' '),
+                _ => Key::Char(key.chars().next().unwrap()),
+            };
+            Input {
+                key,
+                ctrl,
+                alt,
+                shift: false,
+            }
+        })
+        .collect()
+}
+
+/// The text and the cursor after pressing `keys`.
+fn run(text: &str, (row, col): (u16, u16), keys: &str) -> (String, (usize, usize)) {
+    let mut emacs = Emacs::new();
+    let mut textarea = TextArea::from(text.split('\n'));
+    textarea.move_cursor(CursorMove::Jump(row, col));
+    for input in inputs(keys) {
+        emacs.input(&input, &mut textarea);
+    }
+    (textarea.lines().join("\n"), textarea.cursor())
+}
+
+#[test]
+fn kill_and_yank() {
+    assert_eq!(
+        run("hello world", (0, 5), "C-k C-a C-y"),
+        (" worldhello".to_string(), (0, 6))
+    );
+}
+
+#[test]
+fn kill_line_takes_newline_at_end() {
+    assert_eq!(run("one\ntwo", (0, 0), "C-k C-k").0, "two");
+    assert_eq!(run("one\ntwo", (0, 0), "C-k C-k C-y").0, "one\ntwo");
+}
+
+#[test]
+fn consecutive_kills_add_up() {
+    assert_eq!(
+        run("one two three", (0, 13), "M-DEL M-DEL C-y").0,
+        "one two three"
+    );
+    assert_eq!(
+        run("one two three", (0, 0), "M-d M-d C-e C-y").0,
+        " threeone two"
+    );
+}
+
+#[test]
+fn yank_pop_goes_back_through_kills() {
+    assert_eq!(run("a b", (0, 0), "M-d C-e M-DEL C-y M-y").0, " a");
+    assert_eq!(
+        run("a b c", (0, 0), "M-d M-f C-f M-d C-y M-y M-y").0,
+        " b c"
+    );
+}
+
+#[test]
+fn yank_pop_only_after_yank() {
+    assert_eq!(run("a b", (0, 0), "M-d M-y").0, " b");
+}
+
+#[test]
+fn region_kill_and_copy() {
+    assert_eq!(run("hello world", (0, 0), "C-SPC M-f C-w").0, " world");
+    assert_eq!(
+        run("hello world", (0, 0), "C-SPC M-f M-w C-e C-y").0,
+        "hello worldhello"
+    );
+    assert_eq!(run("one\ntwo", (0, 0), "C-x h C-w").0, "");
+}
+
+#[test]
+fn exchange_point_and_mark() {
+    assert_eq!(run("hello world", (0, 0), "C-SPC C-e C-x C-x").1, (0, 0));
+}
+
+#[test]
+fn typing_drops_the_region() {
+    assert_eq!(run("abc", (0, 0), "C-SPC C-e x").0, "abcx");
+}
+
+#[test]
+fn transpose() {
+    assert_eq!(run("abc", (0, 1), "C-t"), ("bac".to_string(), (0, 2)));
+    assert_eq!(run("abc", (0, 3), "C-t"), ("acb".to_string(), (0, 3)));
+    assert_eq!(
+        run("one two", (0, 1), "M-t"),
+        ("two one".to_string(), (0, 7))
+    );
+    assert_eq!(run("one two", (0, 7), "M-t").0, "two one");
+}
+
+#[test]
+fn word_case() {
+    assert_eq!(run("hello world", (0, 0), "M-u").0, "HELLO world");
+    assert_eq!(run("HELLO world", (0, 0), "M-l M-c").0, "hello World");
+}
+
+#[test]
+fn motions_and_deletes() {
+    assert_eq!(run("one two", (0, 0), "M-f").1, (0, 3));
+    assert_eq!(run("one two", (0, 7), "M-b").1, (0, 4));
+    assert_eq!(run("abc", (0, 1), "C-d").0, "ac");
+    assert_eq!(run("abc", (0, 1), "C-h").0, "bc");
+    assert_eq!(run("one two", (0, 7), "C-u").0, "");
+    assert_eq!(run("ab", (0, 1), "C-j").0, "a\nb");
+}
+
+#[test]
+fn undo() {
+    assert_eq!(run("abc", (0, 0), "C-k C-_").0, "abc");
+    assert_eq!(run("abc", (0, 0), "C-k C-x u").0, "abc");
+}
@@ -0,0 +1,326 @@
+use gptrs::input::{Mode, Transition, Vim};
+use tui_textarea::{CursorMove, Input, Key, TextArea};
+
+/// The inputs for keys written like in a Vim mapping, such as `ciwfoo<Esc>`.
+fn inputs(keys: &str) -> Vec<Input> {
+    let mut inputs = vec![];
+    let mut rest = keys;
+    while let Some(c) = rest.chars().next() {
+        let special = rest
+            .strip_prefix('<')
+            .and_then(|after| after.split_once('>'))
+            .filter(|(name, _)| name.len() > 1);
+        if let Some((name, after)) = special {
+            let input = match name {
+                "Esc" => Input {
+                    key: Key::Esc,
+                    ..Input::default()
+                },
+                "CR" => Input {
+                    key: Key::Enter,
+                    ..Input::default()
+                },
+                "BS" => Input {
+                    key: Key::Backspace,
+                    ..Input::default()
+                },
+                _ => match name.strip_prefix("C-") {
+                    Some(ctrl) => Input {
+                        key: Key::Char(ctrl.chars().next().unwrap()),
+                        ctrl: true,
+                        ..Input::default()
+                    },
+                    None => panic!("unknown key <{name}>"),
+                },
+            };
+            inputs.push(input);
+            rest = after;
+        } else {
+            inputs.push(Input {
+                key: Key::Char(c),
+                ..Input::default()
+            });
+            rest = &rest[c.len_utf8()..];
+        }
+    }
+    inputs
+}
+
+fn editor(text: &str, (row, col): (u16, u16)) -> TextArea<'static> {
+    let mut textarea = TextArea::from(text.split('\n'));
+    textarea.move_cursor(CursorMove::Jump(row, col));
+    textarea
+}
+
+fn press(vim: &mut Vim, textarea: &mut TextArea<'_>, keys: &str) {
+    for input in inputs(keys) {
+        vim.transition(input, textarea);
+    }
+}
+
+/// The text and the cursor after pressing `keys` in Normal mode.
+fn run(text: &str, cursor: (u16, u16), keys: &str) -> (String, (usize, usize)) {
+    let mut vim = Vim::new(Mode::Normal);
+    let mut textarea = editor(text, cursor);
+    press(&mut vim, &mut textarea, keys);
+    (textarea.lines().join("\n"), textarea.cursor())
+}
+
+#[test]
+fn count_repeats_motion() {
+    assert_eq!(run("one two three four", (0, 0), "3w").1, (0, 14));
+}
+
+#[test]
+fn count_deletes_lines() {
+    assert_eq!(
+        run("1\n2\n3\n4\n5\n6\n7", (1, 0), "5dd"),
+        ("1\n7".to_string(), (1, 0))
+    );
+}
+
+#[test]
+fn counts_multiply() {
+    assert_eq!(
+        run("a b c d e f g h", (0, 0), "2d3w"),
+        ("g h".to_string(), (0, 0))
+    );
+}
+
+#[test]
+fn dw_stops_at_end_of_line() {
+    assert_eq!(
+        run("foo bar\nbaz", (0, 4), "dw"),
+        ("foo \nbaz".to_string(), (0, 3))
+    );
+}
+
+#[test]
+fn cw_changes_to_end_of_word() {
+    assert_eq!(
+        run("foo bar baz", (0, 4), "cwqux<Esc>"),
+        ("foo qux baz".to_string(), (0, 6))
+    );
+}
+
+#[test]
+fn word_motions() {
+    assert_eq!(run("foo bar", (0, 0), "e").1, (0, 2));
+    assert_eq!(run("foo bar", (0, 6), "b").1, (0, 4));
+    assert_eq!(run("foo bar", (0, 6), "bb").1, (0, 0));
+    assert_eq!(run("a.b c", (0, 0), "W").1, (0, 4));
+    assert_eq!(run("a.b c", (0, 0), "w").1, (0, 1));
+    assert_eq!(run("foo bar", (0, 0), "de").0, " bar");
+}
+
+#[test]
+fn change_inner_word() {
+    assert_eq!(
+        run("let value = 1;", (0, 6), "ciwcount<Esc>"),
+        ("let count = 1;".to_string(), (0, 8))
+    );
+}
+
+#[test]
+fn delete_a_word() {
+    assert_eq!(
+        run("one two three", (0, 5), "daw"),
+        ("one three".to_string(), (0, 4))
+    );
+}
+
+#[test]
+fn quote_objects() {
+    assert_eq!(
+        run("say \"hello world\" now", (0, 0), "di\""),
+        ("say \"\" now".to_string(), (0, 5))
+    );
+    assert_eq!(
+        run("x = \"a\" + 1", (0, 5), "da\""),
+        ("x = + 1".to_string(), (0, 4))
+    );
+}
+
+#[test]
+fn bracket_objects() {
+    assert_eq!(
+        run("call(a, b)", (0, 6), "ci(x<Esc>"),
+        ("call(x)".to_string(), (0, 5))
+    );
+    assert_eq!(run("v[1][2]", (0, 2), "da[").0, "v[2]");
+}
+
+#[test]
+fn block_object_takes_whole_lines() {
+    let text = "fn main() {\n    one\n    two\n}";
+    assert_eq!(
+        run(text, (1, 4), "di{"),
+        ("fn main() {\n}".to_string(), (1, 0))
+    );
+    assert_eq!(run(text, (2, 0), "ci{x<Esc>").0, "fn main() {\nx\n}");
+}
+
+#[test]
+fn find_and_repeat() {
+    assert_eq!(run("a,b,c,d", (0, 0), "f,").1, (0, 1));
+    assert_eq!(run("a,b,c,d", (0, 0), "f,;").1, (0, 3));
+    assert_eq!(run("a,b,c,d", (0, 0), "f,;,").1, (0, 1));
+    assert_eq!(run("a,b,c,d", (0, 6), "F,").1, (0, 5));
+    assert_eq!(run("a,b,c", (0, 0), "t,;").1, (0, 2));
+}
+
+#[test]
+fn operators_with_find() {
+    assert_eq!(
+        run("foo(bar)", (0, 4), "dt)"),
+        ("foo()".to_string(), (0, 4))
+    );
+    assert_eq!(run("a, b, c", (0, 0), "2df,").0, " c");
+    assert_eq!(run("foo(bar)", (0, 0), "ct(baz<Esc>").0, "baz(bar)");
+}
+
+#[test]
+fn matching_bracket() {
+    assert_eq!(run("if (a[0]) {", (0, 0), "%").1, (0, 8));
+    assert_eq!(run("if (a[0]) {", (0, 8), "%").1, (0, 3));
+    assert_eq!(run("if (a[0]) {", (0, 3), "d%").0, "if  {");
+}
+
+#[test]
+fn search_forward_and_back() {
+    let text = "alpha\nbeta\nalpha beta";
+    assert_eq!(run(text, (0, 0), "/beta<CR>").1, (1, 0));
+    assert_eq!(run(text, (0, 0), "/beta<CR>n").1, (2, 6));
+    assert_eq!(run(text, (0, 0), "/beta<CR>nn").1, (1, 0));
+    assert_eq!(run(text, (0, 0), "/beta<CR>nnN").1, (2, 6));
+    assert_eq!(run("one two one", (0, 8), "?one<CR>").1, (0, 0));
+    assert_eq!(run(text, (0, 0), "/zzz<CR>").1, (0, 0));
+}
+
+#[test]
+fn search_as_motion() {
+    assert_eq!(
+        run("keep this and drop", (0, 5), "d/drop<CR>"),
+        ("keep drop".to_string(), (0, 5))
+    );
+}
+
+#[test]
+fn search_shows_pattern_and_cancels() {
+    let mut vim = Vim::new(Mode::Normal);
+    let mut textarea = editor("foo", (0, 0));
+    press(&mut vim, &mut textarea, "/fo");
+    assert_eq!(vim.mode, Mode::Search);
+    assert_eq!(vim.to_string(), "/fo");
+    press(&mut vim, &mut textarea, "<Esc>");
+    assert_eq!(vim.mode, Mode::Normal);
+    assert_eq!(textarea.cursor(), (0, 0));
+}
+
+#[test]
+fn named_registers() {
+    assert_eq!(
+        run("one\ntwo", (0, 0), "\"ayyj\"ap"),
+        ("one\ntwo\none".to_string(), (2, 0))
+    );
+    assert_eq!(
+        run("one\ntwo\nx", (0, 0), "\"ayyj\"Ayyj\"ap").0,
+        "one\ntwo\nx\none\ntwo"
+    );
+}
+
+#[test]
+fn black_hole_register_keeps_unnamed() {
+    assert_eq!(
+        run("one\ntwo\nthree", (0, 0), "yyj\"_ddp").0,
+        "one\nthree\none"
+    );
+}
+
+#[test]
+fn put_lines_and_characters() {
+    assert_eq!(
+        run("a\nb\nc", (0, 0), "ddp"),
+        ("b\na\nc".to_string(), (1, 0))
+    );
+    assert_eq!(run("a\nb", (1, 0), "yyP"), ("a\nb\nb".to_string(), (1, 0)));
+    assert_eq!(run("abc", (0, 0), "xp"), ("bac".to_string(), (0, 1)));
+    assert_eq!(run("ab", (0, 0), "x3p").0, "baaa");
+}
+
+#[test]
+fn dot_repeats_last_change() {
+    assert_eq!(run("a b c d", (0, 0), "dw.."), ("d".to_string(), (0, 0)));
+    assert_eq!(run("a b c d e f", (0, 0), "dw3.").0, "e f");
+    assert_eq!(run("a b c d e f g h", (0, 0), "d2w3.").0, "f g h");
+    assert_eq!(run("a b c d e f g h", (0, 0), "2d2w3.").0, "h");
+    assert_eq!(
+        run("foo bar foo", (0, 0), "cwbaz<Esc>ww."),
+        ("baz bar baz".to_string(), (0, 10))
+    );
+    assert_eq!(run("abcd", (0, 0), "x.").0, "cd");
+}
+
+#[test]
+fn dot_skips_motions_and_yanks() {
+    assert_eq!(run("abcdef", (0, 0), "xlyl.").0, "bdef");
+}
+
+#[test]
+fn undo_and_redo() {
+    assert_eq!(run("one\ntwo", (0, 0), "ddu").0, "one\ntwo");
+    assert_eq!(run("one\ntwo", (0, 0), "ddu<C-r>").0, "two");
+}
+
+#[test]
+fn visual_operators() {
+    assert_eq!(run("hello world", (0, 0), "vey$p").0, "hello worldhello");
+    assert_eq!(run("a\nb\nc", (0, 0), "Vjd").0, "c");
+    assert_eq!(run("hello world", (0, 6), "vld").0, "hello rld");
+}
+
+#[test]
+fn small_edits() {
+    assert_eq!(run("abc", (0, 0), "2rx"), ("xxc".to_string(), (0, 1)));
+    assert_eq!(run("abc", (0, 0), "~~"), ("ABc".to_string(), (0, 2)));
+    assert_eq!(
+        run("one\n  two", (0, 0), "J"),
+        ("one two".to_string(), (0, 3))
+    );
+    assert_eq!(run("foo bar", (0, 4), "D"), ("foo ".to_string(), (0, 3)));
+    assert_eq!(run("foo bar", (0, 0), "A!<Esc>").0, "foo bar!");
+    assert_eq!(run("  old\nx", (0, 2), "ccnew<Esc>").0, "new\nx");
+    assert_eq!(run("abc", (0, 2), "X").0, "ac");
+}
+
+#[test]
+fn goto_line() {
+    let text = "a\nb\nc\nd";
+    assert_eq!(run(text, (0, 0), "3G").1, (2, 0));
+    assert_eq!(run(text, (0, 0), "G").1, (3, 0));
+    assert_eq!(run(text, (3, 0), "gg").1, (0, 0));
+    assert_eq!(run(text, (0, 0), "dG").0, "");
+}
+
+#[test]
+fn transitions_report_mode_changes() {
+    let mut vim = Vim::new(Mode::Normal);
+    let mut textarea = editor("", (0, 0));
+    let mut transition = |vim: &mut Vim, keys: &str| {
+        let input = inputs(keys).remove(0);
+        match vim.transition(input, &mut textarea) {
+            Transition::Mode(mode) => Some(mode),
+            Transition::Nop => None,
+        }
+    };
+    assert_eq!(transition(&mut vim, "i"), Some(Mode::Insert));
+    assert_eq!(transition(&mut vim, "x"), None);
+    assert_eq!(transition(&mut vim, "<Esc>"), Some(Mode::Normal));
+    assert_eq!(transition(&mut vim, "\""), None);
+    assert_eq!(transition(&mut vim, "a"), None);
+    assert_eq!(transition(&mut vim, "2"), None);
+    assert_eq!(transition(&mut vim, "d"), Some(Mode::Operator('d')));
+    assert_eq!(vim.to_string(), "Operator (d) \"a2d");
+    assert_eq!(transition(&mut vim, "<Esc>"), Some(Mode::Normal));
+    assert_eq!(vim.to_string(), "Normal");
+}